@@ -1,3 +1,5 @@
 pub mod servers;
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
 
 pub use servers::*;
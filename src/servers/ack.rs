@@ -0,0 +1,217 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::ops::Range;
+use std::time::{Duration, Instant};
+use wg_2024::network::{NodeId, SourceRoutingHeader};
+use wg_2024::packet::{Ack, Packet, PacketType};
+
+/// Set in the fragment index of a cumulative ack, which acknowledges every fragment of
+/// the session below the index without the flag.
+///
+/// No message has that many fragments, so a standard node never sends it.
+pub const CUMULATIVE_ACK_FLAG: u64 = 1 << 63;
+
+/// In-order fragments acknowledged together by a cumulative ack.
+pub const ACK_BATCH: u64 = 8;
+
+/// Longest a fragment of an incoming message waits to be acknowledged.
+pub const ACK_DELAY: Duration = Duration::from_millis(10);
+
+/// Sessions remembered on each side before the least recently active is forgotten.
+pub const MAX_SESSIONS: usize = 1024;
+
+/// What an ack acknowledges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AckKind {
+    /// One fragment, as in standard wg_2024.
+    Single(u64),
+    /// Every fragment below the given index.
+    Cumulative(u64),
+}
+
+impl AckKind {
+    #[must_use]
+    pub fn decode(fragment_index: u64) -> Self {
+        if fragment_index & CUMULATIVE_ACK_FLAG == 0 {
+            Self::Single(fragment_index)
+        } else {
+            Self::Cumulative(fragment_index & !CUMULATIVE_ACK_FLAG)
+        }
+    }
+
+    #[must_use]
+    pub fn encode(self) -> u64 {
+        match self {
+            Self::Single(fragment_index) => fragment_index,
+            Self::Cumulative(next) => next | CUMULATIVE_ACK_FLAG,
+        }
+    }
+}
+
+/// Fragments received of an incoming message from a peer using cumulative acks.
+#[derive(Debug)]
+struct Incoming {
+    /// Every fragment below this one was received.
+    next: u64,
+    /// Fragments received past a gap.
+    beyond: BTreeSet<u64>,
+    /// Fragments below this one were acknowledged.
+    acked: u64,
+    /// Cumulative ack waiting to be sent, with the time its oldest fragment arrived.
+    deferred: Option<(Packet, Instant)>,
+    last_active: u64,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    peers: HashSet<NodeId>,
+    incoming: BTreeMap<(u64, NodeId), Incoming>,
+    /// Fragments of each outgoing session already acknowledged, and its last activity.
+    acked_through: BTreeMap<u64, (u64, u64)>,
+    /// Incremented on every fragment or ack recorded, to find the least recently
+    /// active sessions.
+    activity: u64,
+}
+
+/// Aggregated acknowledgements, used only with peers that support them.
+///
+/// A peer shows it supports them by sending a cumulative ack itself, so standard
+/// wg_2024 nodes keep getting one ack per fragment. For those peers in-order fragments
+/// are acknowledged [`ACK_BATCH`] at a time, or after [`ACK_DELAY`], by a single
+/// cumulative ack; out-of-order fragments still get a selective ack of their own.
+/// Recording only needs `&self`.
+#[derive(Debug, Default)]
+pub struct AckAggregator {
+    inner: RefCell<Inner>,
+}
+
+impl AckAggregator {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn supports(&self, peer_id: NodeId) -> bool {
+        self.inner.borrow().peers.contains(&peer_id)
+    }
+
+    /// Records that `peer_id` understands cumulative acks.
+    pub fn enable(&self, peer_id: NodeId) {
+        self.inner.borrow_mut().peers.insert(peer_id);
+    }
+
+    /// Fragments of an outgoing session newly acknowledged by a cumulative ack up to
+    /// `next`, so each one is processed once.
+    pub fn newly_acked(&self, session_id: u64, next: u64) -> Range<u64> {
+        let inner = &mut *self.inner.borrow_mut();
+        inner.activity += 1;
+        let (acked, last_active) = inner.acked_through.entry(session_id).or_default();
+        let range = *acked..next.max(*acked);
+        *acked = range.end;
+        *last_active = inner.activity;
+        if inner.acked_through.len() > MAX_SESSIONS {
+            let idle = inner
+                .acked_through
+                .iter()
+                .min_by_key(|(_, (_, last_active))| *last_active)
+                .map(|(&session_id, _)| session_id);
+            if let Some(idle) = idle {
+                inner.acked_through.remove(&idle);
+            }
+        }
+        range
+    }
+
+    /// Records a fragment received from a peer using cumulative acks, returning the
+    /// ack to send right away, if any.
+    ///
+    /// `ack_route` is the route back to the peer.
+    pub fn received(
+        &self,
+        peer_id: NodeId,
+        session_id: u64,
+        fragment_index: u64,
+        total_n_fragments: u64,
+        ack_route: SourceRoutingHeader,
+    ) -> Option<Packet> {
+        let now = Instant::now();
+        let inner = &mut *self.inner.borrow_mut();
+        inner.activity += 1;
+        if inner.incoming.len() >= MAX_SESSIONS
+            && !inner.incoming.contains_key(&(session_id, peer_id))
+        {
+            let idle = inner
+                .incoming
+                .iter()
+                .min_by_key(|(_, session)| session.last_active)
+                .map(|(&key, _)| key);
+            if let Some(idle) = idle {
+                inner.incoming.remove(&idle);
+            }
+        }
+        let session = inner
+            .incoming
+            .entry((session_id, peer_id))
+            .or_insert_with(|| Incoming {
+                next: 0,
+                beyond: BTreeSet::new(),
+                acked: 0,
+                deferred: None,
+                last_active: 0,
+            });
+        session.last_active = inner.activity;
+        let ack = |kind: AckKind| Packet {
+            routing_header: ack_route.clone(),
+            session_id,
+            pack_type: PacketType::Ack(Ack {
+                fragment_index: kind.encode(),
+            }),
+        };
+
+        if fragment_index < session.next {
+            // a duplicate, whose ack may have been lost
+            session.deferred = None;
+            session.acked = session.next;
+            return Some(ack(AckKind::Cumulative(session.next)));
+        }
+        if fragment_index > session.next {
+            session.beyond.insert(fragment_index);
+            return Some(ack(AckKind::Single(fragment_index)));
+        }
+        session.next += 1;
+        while session.beyond.remove(&session.next) {
+            session.next += 1;
+        }
+        let ready = session.next >= total_n_fragments || session.next - session.acked >= ACK_BATCH;
+        let result = if ready {
+            session.deferred = None;
+            session.acked = session.next;
+            Some(ack(AckKind::Cumulative(session.next)))
+        } else {
+            let since = session.deferred.as_ref().map_or(now, |(_, since)| *since);
+            session.deferred = Some((ack(AckKind::Cumulative(session.next)), since));
+            None
+        };
+        result
+    }
+
+    /// Takes the deferred acks that waited for [`ACK_DELAY`].
+    pub fn due(&self, now: Instant) -> Vec<Packet> {
+        let mut inner = self.inner.borrow_mut();
+        let mut due = Vec::new();
+        for session in inner.incoming.values_mut() {
+            if session
+                .deferred
+                .as_ref()
+                .is_some_and(|(_, since)| now.saturating_duration_since(*since) >= ACK_DELAY)
+            {
+                if let Some((ack, _)) = session.deferred.take() {
+                    session.acked = session.next;
+                    due.push(ack);
+                }
+            }
+        }
+        due
+    }
+}
@@ -0,0 +1,107 @@
+use crate::servers::control_message::Feature;
+use messages::high_level_messages::ServerType;
+use wg_2024::network::NodeId;
+
+/// Version of the control protocol, bumped when a control message changes meaning.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// What a server supports, sent in reply to `ControlMessage::GetCapabilities`.
+///
+/// Lets a client adapt to the server instead of assuming from its `ServerType` alone.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    pub node_id: NodeId,
+    /// `text`, `media` or `chat`, as answered to `GetServerType`.
+    pub server_type: String,
+    /// Every role the server takes on, several for a hybrid server.
+    pub roles: Vec<String>,
+    pub protocol_version: u32,
+    /// `ClientMessage` variants the server answers, by their metrics name.
+    pub requests: Vec<String>,
+    /// Features a client can enable.
+    pub features: Vec<Feature>,
+    /// Whether clients can exchange public keys for end-to-end encryption.
+    pub encryption: bool,
+    /// Largest message the server reassembles, in bytes.
+    pub max_upload_size: u64,
+    /// Files or media served, zero for a chat server.
+    pub catalog_size: usize,
+}
+
+impl Capabilities {
+    #[must_use]
+    pub fn encode(&self) -> String {
+        let features = self
+            .features
+            .iter()
+            .map(|feature| feature.as_str())
+            .collect::<Vec<_>>()
+            .join(",");
+        format!(
+            "node={};type={};roles={};version={};requests={};features={};encryption={};max_upload={};catalog={}",
+            self.node_id,
+            self.server_type,
+            self.roles.join(","),
+            self.protocol_version,
+            self.requests.join(","),
+            features,
+            self.encryption,
+            self.max_upload_size,
+            self.catalog_size,
+        )
+    }
+
+    /// Parses [`Capabilities::encode`], ignoring fields it does not know so newer
+    /// servers can add some.
+    #[must_use]
+    pub fn parse(encoded: &str) -> Option<Self> {
+        let (mut node_id, mut server_type, mut protocol_version) = (None, None, None);
+        let mut capabilities = Self {
+            node_id: 0,
+            server_type: String::new(),
+            roles: Vec::new(),
+            protocol_version: 0,
+            requests: Vec::new(),
+            features: Vec::new(),
+            encryption: false,
+            max_upload_size: 0,
+            catalog_size: 0,
+        };
+        for field in encoded.split(';') {
+            let (name, value) = field.split_once('=')?;
+            match name {
+                "node" => node_id = value.parse().ok(),
+                "type" => server_type = Some(value.to_string()),
+                "roles" => capabilities.roles = list(value).map(str::to_string).collect(),
+                "version" => protocol_version = value.parse().ok(),
+                "requests" => {
+                    capabilities.requests = list(value).map(str::to_string).collect();
+                }
+                "features" => {
+                    capabilities.features = list(value).filter_map(Feature::parse).collect()
+                }
+                "encryption" => capabilities.encryption = value.parse().ok()?,
+                "max_upload" => capabilities.max_upload_size = value.parse().ok()?,
+                "catalog" => capabilities.catalog_size = value.parse().ok()?,
+                _ => {}
+            }
+        }
+        capabilities.node_id = node_id?;
+        capabilities.server_type = server_type?;
+        capabilities.protocol_version = protocol_version?;
+        Some(capabilities)
+    }
+}
+
+fn list(value: &str) -> impl Iterator<Item = &str> {
+    value.split(',').filter(|item| !item.is_empty())
+}
+
+#[must_use]
+pub fn server_type_name(server_type: &ServerType) -> &'static str {
+    match server_type {
+        ServerType::Text => "text",
+        ServerType::Media => "media",
+        ServerType::Chat => "chat",
+    }
+}
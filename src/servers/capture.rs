@@ -0,0 +1,148 @@
+use crate::servers::server::Server;
+use crossbeam_channel::Sender;
+use serde::{Deserialize, Serialize};
+use std::cell::{Cell, RefCell};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::time::Instant;
+use wg_2024::packet::Packet;
+
+/// Whether a captured packet entered or left the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Direction {
+    /// Received in `handle_packet`.
+    Incoming,
+    /// Emitted through `send_packet`.
+    Outgoing,
+}
+
+/// One captured packet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaptureRecord {
+    pub direction: Direction,
+    /// Microseconds since the capture started.
+    pub elapsed_us: u64,
+    pub packet: Packet,
+}
+
+/// Records the packets going through a server to a capture file.
+///
+/// Each record is written as a little-endian `u32` length followed by the bincode
+/// encoding of a [`CaptureRecord`]. Recording only needs `&self`.
+#[derive(Debug, Default)]
+pub struct PacketCapture {
+    writer: RefCell<Option<(Instant, BufWriter<File>)>>,
+    records: Cell<u64>,
+}
+
+impl PacketCapture {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts writing to `path`, replacing any capture already in progress.
+    ///
+    /// # Errors
+    /// Returns the I/O error if the file cannot be created.
+    pub fn start(&self, path: &Path) -> io::Result<()> {
+        let file = File::create(path)?;
+        self.stop()?;
+        *self.writer.borrow_mut() = Some((Instant::now(), BufWriter::new(file)));
+        Ok(())
+    }
+
+    /// Stops the capture in progress, returning the number of records written.
+    ///
+    /// # Errors
+    /// Returns the I/O error if the file cannot be flushed.
+    pub fn stop(&self) -> io::Result<u64> {
+        let records = self.records.replace(0);
+        match self.writer.borrow_mut().take() {
+            Some((_, mut writer)) => writer.flush().map(|()| records),
+            None => Ok(0),
+        }
+    }
+
+    #[must_use]
+    pub fn is_active(&self) -> bool {
+        self.writer.borrow().is_some()
+    }
+
+    /// Appends `packet` to the capture in progress, if any.
+    ///
+    /// A packet that cannot be written stops the capture.
+    pub fn record(&self, direction: Direction, packet: &Packet) {
+        let mut writer = self.writer.borrow_mut();
+        let Some((start, file)) = writer.as_mut() else {
+            return;
+        };
+        let record = CaptureRecord {
+            direction,
+            elapsed_us: u64::try_from(start.elapsed().as_micros()).unwrap_or(u64::MAX),
+            packet: packet.clone(),
+        };
+        if write_record(file, &record).is_err() {
+            *writer = None;
+            return;
+        }
+        self.records.set(self.records.get() + 1);
+    }
+}
+
+fn write_record(writer: &mut impl Write, record: &CaptureRecord) -> io::Result<()> {
+    let bytes = bincode::serialize(record).map_err(io::Error::other)?;
+    let len = u32::try_from(bytes.len()).map_err(io::Error::other)?;
+    writer.write_all(&len.to_le_bytes())?;
+    writer.write_all(&bytes)
+}
+
+/// Reads every record of a capture file.
+///
+/// # Errors
+/// Returns an error if the file cannot be read or contains a malformed record.
+pub fn read_capture(path: &Path) -> io::Result<Vec<CaptureRecord>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut records = Vec::new();
+    loop {
+        let mut len = [0; 4];
+        match reader.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(records),
+            Err(e) => return Err(e),
+        }
+        let mut bytes = vec![0; u32::from_le_bytes(len) as usize];
+        reader.read_exact(&mut bytes)?;
+        records.push(bincode::deserialize(&bytes).map_err(io::Error::other)?);
+    }
+}
+
+/// Feeds the incoming packets of a capture straight into `server`, in order.
+///
+/// The server is driven on the calling thread, so the replay is deterministic. Returns
+/// the number of packets replayed.
+pub fn replay(records: &[CaptureRecord], server: &mut impl Server) -> usize {
+    let mut replayed = 0;
+    for record in incoming(records) {
+        server.handle_packet(record.packet.clone());
+        replayed += 1;
+    }
+    replayed
+}
+
+/// Sends the incoming packets of a capture on `packet_send`, which should be the
+/// sending half of the channel a running server receives from.
+///
+/// Returns the number of packets sent before the channel was closed, if it was.
+pub fn replay_over_channel(records: &[CaptureRecord], packet_send: &Sender<Packet>) -> usize {
+    incoming(records)
+        .take_while(|record| packet_send.send(record.packet.clone()).is_ok())
+        .count()
+}
+
+fn incoming(records: &[CaptureRecord]) -> impl Iterator<Item = &CaptureRecord> {
+    records
+        .iter()
+        .filter(|record| record.direction == Direction::Incoming)
+}
@@ -0,0 +1,88 @@
+use crate::servers::config::ServerConfig;
+use base64::{engine::general_purpose, Engine as _};
+use image::ImageReader;
+use log::info;
+use messages::high_level_messages::{ServerMessage, ServerType};
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::path::Path;
+
+/// The hardcoded catalog of a server of the given type: file name to file path.
+#[must_use]
+pub fn default_catalog(server_type: &ServerType) -> HashMap<String, String> {
+    let mut hm = HashMap::new();
+    match server_type {
+        //inizialize the hashmap
+        ServerType::Text => {
+            hm.insert("file1".to_string(), "file1.html".to_string());
+            hm.insert("file2".to_string(), "file2.html".to_string());
+            hm.insert("file3".to_string(), "file3.html".to_string());
+            hm.insert("file4".to_string(), "file4.html".to_string());
+            hm.insert("file5".to_string(), "file5.html".to_string());
+        }
+        ServerType::Media => {
+            hm.insert("media1".to_string(), "media1.jpg".to_string());
+            hm.insert("media2".to_string(), "media2.jpg".to_string());
+            hm.insert("media3".to_string(), "media3.jpg".to_string());
+            hm.insert("media4".to_string(), "media4.jpg".to_string());
+            hm.insert("media5".to_string(), "media5.jpg".to_string());
+        }
+        ServerType::Chat => {}
+    }
+    hm
+}
+
+/// Reads the file (or, if `media`, the image) a client asked for by name.
+///
+/// The name comes from the client, so only names in `catalog` are read: anything else
+/// could pick any path on the server.
+///
+/// # Errors
+/// Returns the reason if the name is not in the catalog or the file cannot be read.
+pub fn read_from_catalog(
+    catalog: &HashMap<String, String>,
+    file_name: &str,
+    media: bool,
+    config: &ServerConfig,
+) -> Result<ServerMessage, String> {
+    let file_path_t = catalog
+        .get(file_name)
+        .ok_or_else(|| "not in the catalog".to_string())?;
+    if media {
+        read_media(file_name, file_path_t, &config.media_dir)
+    } else {
+        read_file(file_name, file_path_t, &config.text_dir)
+    }
+}
+
+/// Reads a text file from `dir`, as the `File` sent for `GetFile`.
+///
+/// # Errors
+/// Returns the reason if the file cannot be read.
+pub fn read_file(file_name: &str, file_path_t: &str, dir: &Path) -> Result<ServerMessage, String> {
+    let file_path = dir.join(file_path_t);
+    info!("reading file: {:?}", file_path.display());
+    let file_content = std::fs::read_to_string(file_path).map_err(|e| e.to_string())?;
+    Ok(ServerMessage::File {
+        file_id: file_name.to_string(),
+        size: file_content.len(),
+        content: file_content,
+    })
+}
+
+/// Reads an image from `dir` and re-encodes it as a base64 JPEG, as the `Media` sent
+/// for `GetMedia`.
+///
+/// # Errors
+/// Returns the reason if the image cannot be read or encoded.
+pub fn read_media(file_name: &str, file_path_t: &str, dir: &Path) -> Result<ServerMessage, String> {
+    let file_path = dir.join(file_path_t);
+    let file_content = ImageReader::open(file_path).map_err(|e| e.to_string())?;
+    let file_media_content = file_content.decode().map_err(|e| e.to_string())?;
+    let mut buf = Vec::new();
+    file_media_content
+        .write_to(&mut Cursor::new(&mut buf), image::ImageFormat::Jpeg)
+        .map_err(|e| e.to_string())?;
+    let base_64 = general_purpose::STANDARD.encode(&buf);
+    Ok(ServerMessage::Media(file_name.to_string(), base_64))
+}
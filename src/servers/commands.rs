@@ -0,0 +1,82 @@
+use crate::servers::config::ServerConfig;
+use crate::servers::metrics::MetricsSnapshot;
+use crate::servers::reassembly::{Eviction, Progress};
+use crate::servers::topology::Topology;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::Duration;
+use wg_2024::network::NodeId;
+use wg_2024::packet::Packet;
+
+/// Commands understood by both servers on top of the ones defined in `messages`.
+///
+/// They travel on a separate channel pair attached with `attach_server_channels`, so
+/// controllers that only speak the `messages` commands keep working unchanged.
+#[derive(Debug, Clone)]
+pub enum ServerCommand {
+    /// Replies with [`ServerEvent::Metrics`].
+    GetMetrics,
+    /// Writes the message trace as JSON lines to the given file and replies with
+    /// [`ServerEvent::TraceExported`].
+    ExportTrace(PathBuf),
+    /// Starts recording every packet received and sent to the given capture file.
+    StartCapture(PathBuf),
+    /// Stops the capture in progress and replies with [`ServerEvent::CaptureStopped`].
+    StopCapture,
+    /// Saves a snapshot of the server state to the given file and replies with
+    /// [`ServerEvent::SnapshotSaved`].
+    SaveSnapshot(PathBuf),
+    /// Saves a snapshot to the given file at the given interval from now on, or stops
+    /// doing so if `None`.
+    SetAutosnapshot(Option<(PathBuf, Duration)>),
+    /// Changes the keys of the server configuration given as TOML, such as
+    /// `max_retransmissions = 5` or `unset = ["rate_limit"]`, and replies with
+    /// [`ServerEvent::Reconfigured`]. See [`ServerConfig::updated`].
+    Reconfigure(String),
+    /// Replies with [`ServerEvent::Neighbours`].
+    GetNeighbours,
+    /// Replies with [`ServerEvent::Topology`].
+    GetTopology,
+    /// Replies with [`ServerEvent::Clients`].
+    GetClients,
+    /// Replies with [`ServerEvent::Catalog`].
+    GetCatalog,
+    /// Replies with [`ServerEvent::PendingCache`].
+    GetPendingCache,
+    /// Replies with [`ServerEvent::Reassemblies`].
+    GetReassemblies,
+}
+
+/// Events sent in response to a [`ServerCommand`].
+#[derive(Debug, Clone)]
+pub enum ServerEvent {
+    Metrics(MetricsSnapshot),
+    /// Path of the exported trace, or the reason the export failed.
+    TraceExported(Result<PathBuf, String>),
+    /// Path of the capture file, or the reason it could not be created.
+    CaptureStarted(Result<PathBuf, String>),
+    /// Number of packets captured, or the reason the file could not be flushed.
+    CaptureStopped(Result<u64, String>),
+    /// Path of the snapshot, or the reason it could not be written.
+    SnapshotSaved(Result<PathBuf, String>),
+    /// The configuration in effect, or the reason the change was refused and nothing
+    /// changed.
+    Reconfigured(Result<ServerConfig, String>),
+    /// Nodes the server has a channel to, in ascending order.
+    Neighbours(Vec<NodeId>),
+    /// The network as learned from the last flood.
+    Topology(Topology),
+    /// Clients registered to chat, none for a content server.
+    Clients(Vec<NodeId>),
+    /// File name to file path, for every file the server serves, `replica` for the
+    /// replicas a content server keeps for its peers.
+    Catalog(BTreeMap<String, String>),
+    /// Fragments sent and not acknowledged yet, by session and fragment index.
+    PendingCache(Vec<Packet>),
+    /// Incoming messages still missing fragments.
+    Reassemblies(Vec<Progress>),
+    /// A packet was dropped because it could not be processed safely, with the reason.
+    MalformedPacket(Packet, String),
+    /// An incomplete incoming message was discarded.
+    SessionEvicted(Eviction),
+}
@@ -1,28 +1,29 @@
-use assembler::HighLevelMessageFactory;
-use crossbeam_channel::{select_biased, Receiver, Sender};
+use crate::servers::config::ServerConfig;
+use crate::servers::federation::Federation;
+use crate::servers::hybrid_server::Roles;
+use crate::servers::server::Server;
+use crate::servers::snapshot::ServerSnapshot;
+use crate::servers::transport::Transport;
+use colored::Colorize;
+use crossbeam_channel::{Receiver, Sender};
+use log::info;
 use messages;
-use messages::high_level_messages::ServerType;
 use messages::high_level_messages::ServerType::Chat;
+use messages::high_level_messages::{ClientMessage, Message, ServerType};
 use messages::server_commands::{CommunicationServerCommand, CommunicationServerEvent};
-use packet_cache::PacketCache;
-use source_routing::Router;
-use std::collections::HashMap;
-use std::thread;
-use std::time::Duration;
+use std::collections::{BTreeMap, HashMap};
 use wg_2024::network::NodeId;
 use wg_2024::packet::{NodeType, Packet};
 
 pub struct CommunicationServer {
-    pub id: NodeId,
-    pub router: Router,
-    pub message_factory: HighLevelMessageFactory,
-    pub packet_cache: PacketCache, //cache for the packets
-    pub packet_recv: Receiver<Packet>,
-    pub packet_send: HashMap<NodeId, Sender<Packet>>,
-    pub controller_send: Sender<CommunicationServerEvent>,
+    pub transport: Transport<CommunicationServerEvent>,
     pub controller_recv: Receiver<CommunicationServerCommand>,
     pub server_type: ServerType,
-    pub registered_clients: Vec<NodeId>, //note id of the sender and the path to the receiver
+    pub roles: Roles,
+    pub file_list: HashMap<String, String>, //file name and file path, for the content roles
+    pub registered_clients: Vec<NodeId>,    //note id of the sender and the path to the receiver
+    pub public_keys: HashMap<NodeId, String>, //base64 public keys published by registered clients
+    pub federation: Federation,
 }
 
 impl CommunicationServer {
@@ -33,43 +34,93 @@ impl CommunicationServer {
         packet_send: HashMap<NodeId, Sender<Packet>>,
         controller_send: Sender<CommunicationServerEvent>,
         controller_recv: Receiver<CommunicationServerCommand>,
+        config: ServerConfig,
     ) -> Self {
         Self {
-            id,
-            router: Router::new(id, NodeType::Server),
-            packet_cache: PacketCache::new(),
-            message_factory: HighLevelMessageFactory::new(id, NodeType::Server),
-            packet_recv,
-            packet_send,
-            controller_send,
+            transport: Transport::new(id, packet_recv, packet_send, controller_send, config),
             controller_recv,
             server_type: Chat,
+            roles: Roles::CHAT,
+            file_list: HashMap::new(),
             registered_clients: vec![],
+            public_keys: HashMap::new(),
+            federation: Federation::new(),
         }
     }
-    pub fn run(&mut self) {
-        self.flood_network();
-        loop {
-            select_biased! {
-                recv(self.packet_recv) -> packet => {
-                    if let Ok(packet) = packet {
-                        self.handle_packet(packet);
-                    }
-                },
-                recv(self.controller_recv) -> command => {
-                    if let Ok(command) = command {
-                        self.handle_command(command);
-                    }
-                }
+}
+
+impl Server for CommunicationServer {
+    type Event = CommunicationServerEvent;
+    type Command = CommunicationServerCommand;
+
+    fn transport(&self) -> &Transport<CommunicationServerEvent> {
+        &self.transport
+    }
+
+    fn transport_mut(&mut self) -> &mut Transport<CommunicationServerEvent> {
+        &mut self.transport
+    }
+
+    fn controller_recv(&self) -> &Receiver<CommunicationServerCommand> {
+        &self.controller_recv
+    }
+
+    fn handle_command(&mut self, command: CommunicationServerCommand) {
+        CommunicationServer::handle_command(self, command);
+    }
+
+    fn handle_message(&mut self, message: Message) {
+        CommunicationServer::handle_message(self, message);
+    }
+
+    /// Asks the servers first seen in a path trace whether they are chat servers.
+    fn discover_servers(&mut self, path_trace: &[(NodeId, NodeType)]) {
+        if !(self.roles.chat && self.transport.config.federation) {
+            return;
+        }
+        for &(id, node_type) in path_trace {
+            if id != self.transport.id
+                && matches!(node_type, NodeType::Server)
+                && self.federation.discovered(id)
+            {
+                self.transport
+                    .send_request_to_server(ClientMessage::GetServerType, id);
             }
         }
     }
 
-    pub fn flood_network(&mut self) {
-        let requests = self.router.get_flood_requests(self.packet_send.len());
-        for (sender, request) in self.packet_send.values().zip(requests) {
-            self.send_packet(request, Some(sender));
+    fn forget_present_servers(&mut self) {
+        self.federation.clear_present();
+    }
+
+    fn clients(&self) -> Vec<NodeId> {
+        self.registered_clients.clone()
+    }
+
+    fn catalog(&self) -> BTreeMap<String, String> {
+        self.file_list.clone().into_iter().collect()
+    }
+
+    fn snapshot(&self) -> ServerSnapshot {
+        ServerSnapshot {
+            registered_clients: self.registered_clients.clone(),
+            public_keys: self.public_keys.clone().into_iter().collect(),
+            file_list: self.catalog(),
+            ..self.transport.snapshot()
         }
-        thread::sleep(Duration::from_secs(2));
+    }
+
+    /// Registered clients stay registered; federation peers are found again by `run`.
+    fn restore_state(&mut self, snapshot: ServerSnapshot) {
+        self.registered_clients = snapshot.registered_clients;
+        self.public_keys = snapshot.public_keys.into_iter().collect();
+        self.file_list = snapshot.file_list.into_iter().collect();
+        info!(
+            "{} [ CommunicationServer {} ]: Restored {} registered clients and {} routes.",
+            "✔".green(),
+            self.transport.id,
+            self.registered_clients.len(),
+            self.transport.topology.path_traces().len()
+        );
     }
 }
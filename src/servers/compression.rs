@@ -0,0 +1,125 @@
+use base64::{engine::general_purpose, Engine as _};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use messages::high_level_messages::ServerMessage;
+use std::io::{Read, Write};
+
+/// Prefix of a compressed content string, followed by the base64 of the DEFLATE stream.
+///
+/// Compressed contents are only sent to clients that enabled `Feature::Deflate`, so a
+/// standard client never sees one. Those clients read every content through
+/// [`decode`]: an uncompressed content starting with a prefix is escaped with
+/// [`RAW_PREFIX`], so it is never taken for a compressed one.
+pub const DEFLATE_PREFIX: &str = "DEFLATE:";
+
+/// Prefix of an uncompressed content that would otherwise start with a prefix.
+pub const RAW_PREFIX: &str = "RAW:";
+
+/// Contents shorter than this fit in a couple of fragments and are never compressed.
+pub const MIN_COMPRESSED_LEN: usize = 256;
+
+/// Largest content a compressed string may expand to.
+const MAX_DECOMPRESSED_LEN: u64 = 16 << 20;
+
+/// DEFLATE-compresses `content`, returning `None` unless that makes it shorter.
+#[must_use]
+pub fn compress(content: &str) -> Option<String> {
+    if content.len() < MIN_COMPRESSED_LEN {
+        return None;
+    }
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(content.as_bytes()).ok()?;
+    let compressed = format!(
+        "{DEFLATE_PREFIX}{}",
+        general_purpose::STANDARD.encode(encoder.finish().ok()?)
+    );
+    (compressed.len() < content.len()).then_some(compressed)
+}
+
+/// Reverses [`compress`], returning `None` for a content that is not compressed or is
+/// not valid.
+#[must_use]
+pub fn decompress(content: &str) -> Option<String> {
+    let encoded = content.strip_prefix(DEFLATE_PREFIX)?;
+    let compressed = general_purpose::STANDARD.decode(encoded).ok()?;
+    let mut decompressed = String::new();
+    DeflateDecoder::new(compressed.as_slice())
+        .take(MAX_DECOMPRESSED_LEN)
+        .read_to_string(&mut decompressed)
+        .ok()?;
+    Some(decompressed)
+}
+
+/// `content` escaped with [`RAW_PREFIX`], or `None` if it starts with no prefix.
+#[must_use]
+pub fn escape(content: &str) -> Option<String> {
+    (content.starts_with(DEFLATE_PREFIX) || content.starts_with(RAW_PREFIX))
+        .then(|| format!("{RAW_PREFIX}{content}"))
+}
+
+/// A content as sent to a client that enabled compression, read back as the client
+/// does: `None` if it is compressed but not valid.
+#[must_use]
+pub fn decode(content: &str) -> Option<String> {
+    if let Some(raw) = content.strip_prefix(RAW_PREFIX) {
+        return Some(raw.to_string());
+    }
+    if content.starts_with(DEFLATE_PREFIX) {
+        return decompress(content);
+    }
+    Some(content.to_string())
+}
+
+/// Whether a chat content looks end-to-end encrypted: ciphertexts travel as base64,
+/// which does not compress, so trying would only cost time.
+fn is_opaque(content: &str) -> bool {
+    content
+        .bytes()
+        .all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'+' | b'/' | b'='))
+}
+
+/// `message` with its text content compressed, or `None` if it has none worth it.
+///
+/// Only files and chat contents are compressed; media are already compressed images,
+/// chat contents that look encrypted are left alone, and `size` of a file stays the
+/// size of the uncompressed file.
+#[must_use]
+pub fn compress_message(message: &ServerMessage) -> Option<ServerMessage> {
+    match message {
+        ServerMessage::MessageReceived { content, .. } if is_opaque(content) => None,
+        _ => map_content(message, compress),
+    }
+}
+
+/// `message` with its text content escaped, or `None` if it needs no escaping.
+#[must_use]
+pub fn escape_message(message: &ServerMessage) -> Option<ServerMessage> {
+    map_content(message, escape)
+}
+
+/// `message` with the content of a file or chat message replaced by `f`, or `None`
+/// if `f` leaves it as is.
+fn map_content(
+    message: &ServerMessage,
+    f: impl Fn(&str) -> Option<String>,
+) -> Option<ServerMessage> {
+    match message {
+        ServerMessage::File {
+            file_id,
+            size,
+            content,
+        } => Some(ServerMessage::File {
+            file_id: file_id.clone(),
+            size: *size,
+            content: f(content)?,
+        }),
+        ServerMessage::MessageReceived { sender_id, content } => {
+            Some(ServerMessage::MessageReceived {
+                sender_id: *sender_id,
+                content: f(content)?,
+            })
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,308 @@
+use crate::servers::control_message::Feature;
+use crate::servers::dedup::DEFAULT_DEDUP_WINDOW;
+use crate::servers::directory::Directory;
+use crate::servers::fec::FecPolicy;
+use crate::servers::reassembly::ReassemblyLimits;
+use crate::servers::replication::{
+    Replication, DEFAULT_MAX_REPLICAS, DEFAULT_REPLICATION_FACTOR, REPLICATION_INTERVAL,
+};
+use crate::servers::scheduler::DEFAULT_QUEUE_CAPACITY;
+use crate::servers::trace::DEFAULT_TRACE_CAPACITY;
+use crate::servers::transport::{ControllerEvent, Transport};
+use log::LevelFilter;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use wg_2024::network::NodeId;
+
+/// Tunables shared by both servers, usually loaded from a TOML file.
+///
+/// Every key is optional and defaults to the value the servers used before they were
+/// configurable; unknown keys are refused so that typos do not go unnoticed.
+///
+/// ```toml
+/// flood_wait_ms = 500
+/// reflood_threshold = 20
+/// text_dir = "/srv/text"
+/// features = ["deflate"]
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// Time `flood_network` waits for the flood responses, in milliseconds.
+    pub flood_wait_ms: u64,
+    /// Nacks for one fragment after which the network is flooded again.
+    pub reflood_threshold: u16,
+    /// Time an incoming message has to be completed, in milliseconds.
+    pub reassembly_timeout_ms: u64,
+    /// Incomplete incoming messages a single source may have at once.
+    pub max_sessions_per_source: usize,
+    /// Largest incoming message, in bytes.
+    pub max_message_size: u64,
+    /// Packets of each traffic class queued for each neighbour; further packets of a
+    /// full class are dropped.
+    pub queue_capacity: usize,
+    /// Sessions remembered per source to drop duplicate fragments.
+    pub dedup_window: usize,
+    /// Events kept by the message tracer.
+    pub trace_capacity: usize,
+    /// Content servers holding each file.
+    pub replication_factor: usize,
+    /// Time between two replication rounds, in milliseconds.
+    pub replication_interval_ms: u64,
+    /// Replicas a content server keeps for its peers; the oldest are dropped over it.
+    pub max_replicas: usize,
+    /// Content servers hosting the files this one does not hold, by file name, for the
+    /// clients that enabled redirects; they take precedence over the peer digests.
+    pub redirects: BTreeMap<String, NodeId>,
+    /// Directory the text files are read from, relative to the working directory.
+    pub text_dir: PathBuf,
+    /// Directory the media files are read from, relative to the working directory.
+    pub media_dir: PathBuf,
+    /// Whether log lines are colored. Process-wide, see [`ServerConfig::apply_logging`].
+    pub colored_logs: bool,
+    /// Features clients may enable.
+    pub features: Vec<Feature>,
+    /// Parity added to large responses for the clients that enabled forward error
+    /// correction.
+    pub fec_policy: FecPolicy,
+    /// Whether chat servers federate with the chat servers they find.
+    pub federation: bool,
+    /// Nacks a fragment is resent for before it is given up, unlimited if unset.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_retransmissions: Option<u16>,
+    /// Requests a client may send per second, unlimited if unset. Peer servers are
+    /// never limited.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<u32>,
+    /// Fragments kept for retransmission, unlimited if unset; the oldest are dropped
+    /// over it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_budget: Option<usize>,
+    /// Most verbose level logged (`off`, `error`, ... `trace`), left to the logger if
+    /// unset. Process-wide, see [`ServerConfig::apply_logging`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_level: Option<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        let reassembly = ReassemblyLimits::default();
+        Self {
+            flood_wait_ms: 2000,
+            reflood_threshold: 100,
+            reassembly_timeout_ms: duration_ms(reassembly.deadline),
+            max_sessions_per_source: reassembly.max_sessions_per_source,
+            max_message_size: reassembly.max_message_size,
+            queue_capacity: DEFAULT_QUEUE_CAPACITY,
+            dedup_window: DEFAULT_DEDUP_WINDOW,
+            trace_capacity: DEFAULT_TRACE_CAPACITY,
+            replication_factor: DEFAULT_REPLICATION_FACTOR,
+            replication_interval_ms: duration_ms(REPLICATION_INTERVAL),
+            max_replicas: DEFAULT_MAX_REPLICAS,
+            redirects: BTreeMap::new(),
+            text_dir: PathBuf::from("src/text_files"),
+            media_dir: PathBuf::from("src/data_files"),
+            colored_logs: true,
+            features: Feature::ALL.to_vec(),
+            fec_policy: FecPolicy::default(),
+            federation: true,
+            max_retransmissions: None,
+            rate_limit: None,
+            cache_budget: None,
+            log_level: None,
+        }
+    }
+}
+
+/// Keys that may be unset by [`ServerConfig::updated`].
+const OPTIONAL_KEYS: [&str; 4] = [
+    "max_retransmissions",
+    "rate_limit",
+    "cache_budget",
+    "log_level",
+];
+
+fn duration_ms(duration: Duration) -> u64 {
+    u64::try_from(duration.as_millis()).unwrap_or(u64::MAX)
+}
+
+impl ServerConfig {
+    /// Parses and validates a configuration written in TOML.
+    ///
+    /// # Errors
+    /// Returns the reason if the TOML is malformed or a value is invalid.
+    pub fn from_toml(toml: &str) -> Result<Self, String> {
+        let config: Self = toml::from_str(toml).map_err(|e| e.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Reads and validates the configuration file at `path`.
+    ///
+    /// # Errors
+    /// Returns the reason if the file cannot be read or is not a valid configuration.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let toml = std::fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {e}", path.display()))?;
+        Self::from_toml(&toml)
+    }
+
+    /// This configuration with the keys of `toml` changed, validated.
+    ///
+    /// TOML has no null, so optional keys are unset by listing them under `unset`, as in
+    /// `unset = ["rate_limit"]`.
+    ///
+    /// # Errors
+    /// Returns the reason if the TOML is malformed, a key cannot be unset or a resulting
+    /// value is invalid.
+    pub fn updated(&self, toml: &str) -> Result<Self, String> {
+        let mut table = toml::Table::try_from(self).map_err(|e| e.to_string())?;
+        let mut changes: toml::Table = toml.parse().map_err(|e: toml::de::Error| e.to_string())?;
+        if let Some(unset) = changes.remove("unset") {
+            let keys = unset.as_array().ok_or("unset must be an array of keys")?;
+            for key in keys {
+                let key = key
+                    .as_str()
+                    .filter(|key| OPTIONAL_KEYS.contains(key))
+                    .ok_or_else(|| format!("{key} cannot be unset"))?;
+                if changes.contains_key(key) {
+                    return Err(format!("{key} is both set and unset"));
+                }
+                table.remove(key);
+            }
+        }
+        table.extend(changes);
+        let config: Self = table
+            .try_into()
+            .map_err(|e: toml::de::Error| e.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks that every value is usable.
+    ///
+    /// # Errors
+    /// Returns the reason for the first invalid value found.
+    pub fn validate(&self) -> Result<(), String> {
+        let positive = [
+            ("reflood_threshold", u64::from(self.reflood_threshold)),
+            ("reassembly_timeout_ms", self.reassembly_timeout_ms),
+            (
+                "max_sessions_per_source",
+                self.max_sessions_per_source as u64,
+            ),
+            ("max_message_size", self.max_message_size),
+            ("queue_capacity", self.queue_capacity as u64),
+            ("dedup_window", self.dedup_window as u64),
+            ("trace_capacity", self.trace_capacity as u64),
+            ("replication_factor", self.replication_factor as u64),
+            ("replication_interval_ms", self.replication_interval_ms),
+            ("max_replicas", self.max_replicas as u64),
+            (
+                "fec_policy",
+                match self.fec_policy {
+                    FecPolicy::Fixed(block) => block,
+                    FecPolicy::Off | FecPolicy::Adaptive => 1,
+                },
+            ),
+            // unset limits are valid
+            ("rate_limit", self.rate_limit.map_or(1, u64::from)),
+            (
+                "cache_budget",
+                self.cache_budget.map_or(1, |budget| budget as u64),
+            ),
+        ];
+        if let Some((key, _)) = positive.iter().find(|(_, value)| *value == 0) {
+            return Err(format!("{key} must be greater than zero"));
+        }
+        for (key, dir) in [("text_dir", &self.text_dir), ("media_dir", &self.media_dir)] {
+            if dir.as_os_str().is_empty() {
+                return Err(format!("{key} must not be empty"));
+            }
+        }
+        if let Some(level) = &self.log_level {
+            LevelFilter::from_str(level).map_err(|_| format!("unknown log_level {level}"))?;
+        }
+        Ok(())
+    }
+
+    #[must_use]
+    pub fn flood_wait(&self) -> Duration {
+        Duration::from_millis(self.flood_wait_ms)
+    }
+
+    #[must_use]
+    pub fn reassembly_limits(&self) -> ReassemblyLimits {
+        ReassemblyLimits {
+            deadline: Duration::from_millis(self.reassembly_timeout_ms),
+            max_sessions_per_source: self.max_sessions_per_source,
+            max_message_size: self.max_message_size,
+        }
+    }
+
+    #[must_use]
+    pub fn replication(&self) -> Replication {
+        let mut replication = Replication::new(self.replication_factor);
+        replication.interval = Duration::from_millis(self.replication_interval_ms);
+        replication.max_replicas = self.max_replicas;
+        replication
+    }
+
+    /// The directory holding the configured redirects.
+    #[must_use]
+    pub fn directory(&self) -> Directory {
+        let mut directory = Directory::new();
+        directory.set_configured(self.redirects.clone());
+        directory
+    }
+
+    #[must_use]
+    pub fn allows(&self, feature: Feature) -> bool {
+        self.features.contains(&feature)
+    }
+
+    /// Applies the logging settings.
+    ///
+    /// Colors and the log level are shared by every server of the process, so the
+    /// servers never apply them when built: the program starting them calls this once,
+    /// usually with the configuration it gives them.
+    pub fn apply_logging(&self) {
+        if self.colored_logs {
+            colored::control::unset_override();
+        } else {
+            colored::control::set_override(false);
+        }
+        if let Some(level) = self
+            .log_level
+            .as_deref()
+            .and_then(|level| LevelFilter::from_str(level).ok())
+        {
+            log::set_max_level(level);
+        }
+    }
+
+    pub(crate) fn logging_changed(&self, previous: &Self) -> bool {
+        self.colored_logs != previous.colored_logs || self.log_level != previous.log_level
+    }
+}
+
+impl<E: ControllerEvent> Transport<E> {
+    /// Replaces the configuration, applying what the transport uses of it.
+    ///
+    /// Logging settings are applied to the whole process, and only if they changed.
+    pub(crate) fn apply_config(&mut self, config: ServerConfig) {
+        if config.logging_changed(&self.config) {
+            config.apply_logging();
+        }
+        self.reassembly.set_limits(config.reassembly_limits());
+        self.scheduler.set_capacity(config.queue_capacity);
+        self.duplicate_filter.set_window(config.dedup_window);
+        self.tracer.set_capacity(config.trace_capacity);
+        self.fec_policy = config.fec_policy;
+        self.config = config;
+        self.enforce_cache_budget();
+    }
+}
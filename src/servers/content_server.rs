@@ -1,29 +1,29 @@
-use assembler::HighLevelMessageFactory;
-use crossbeam_channel::{select_biased, Receiver, Sender};
-use std::collections::HashMap;
-use std::thread;
+use crate::servers::catalog::default_catalog;
+use crate::servers::config::ServerConfig;
+use crate::servers::directory::Directory;
+use crate::servers::replication::Replication;
+use crate::servers::server::Server;
+use crate::servers::snapshot::ServerSnapshot;
+use crate::servers::transport::Transport;
+use colored::Colorize;
+use crossbeam_channel::{Receiver, Sender};
+use log::info;
+use std::collections::{BTreeMap, HashMap};
 use std::time::Duration;
 use wg_2024::network::NodeId;
 use wg_2024::packet::{NodeType, Packet};
 
 use messages;
-use messages::high_level_messages::ServerType;
-use messages::high_level_messages::ServerType::Text;
+use messages::high_level_messages::{ClientMessage, Message, ServerType};
 use messages::server_commands::{ContentServerCommand, ContentServerEvent};
-use packet_cache::PacketCache;
-use source_routing::Router;
 
 pub struct ContentServer {
-    pub id: NodeId,
-    pub router: Router,
-    pub message_factory: HighLevelMessageFactory,
-    pub packet_cache: PacketCache,
-    pub packet_recv: Receiver<Packet>,
-    pub packet_send: HashMap<NodeId, Sender<Packet>>,
-    pub controller_send: Sender<ContentServerEvent>,
+    pub transport: Transport<ContentServerEvent>,
     pub controller_recv: Receiver<ContentServerCommand>,
     pub server_type: ServerType,            //text or media
     pub file_list: HashMap<String, String>, //file name and file path
+    pub replication: Replication,
+    pub directory: Directory,
 }
 
 impl ContentServer {
@@ -35,62 +35,103 @@ impl ContentServer {
         controller_send: Sender<ContentServerEvent>,
         controller_recv: Receiver<ContentServerCommand>,
         server_type: ServerType,
+        config: ServerConfig,
     ) -> Self {
-        let mut hm = HashMap::new();
-        match server_type {
-            //inizialize the hashmap
-            Text => {
-                hm.insert("file1".to_string(), "file1.html".to_string());
-                hm.insert("file2".to_string(), "file2.html".to_string());
-                hm.insert("file3".to_string(), "file3.html".to_string());
-                hm.insert("file4".to_string(), "file4.html".to_string());
-                hm.insert("file5".to_string(), "file5.html".to_string());
-            }
-            ServerType::Media => {
-                hm.insert("media1".to_string(), "media1.jpg".to_string());
-                hm.insert("media2".to_string(), "media2.jpg".to_string());
-                hm.insert("media3".to_string(), "media3.jpg".to_string());
-                hm.insert("media4".to_string(), "media4.jpg".to_string());
-                hm.insert("media5".to_string(), "media5.jpg".to_string());
-            }
-            ServerType::Chat => {}
-        }
         Self {
-            id,
-            router: Router::new(id, NodeType::Server),
-            packet_cache: PacketCache::new(),
-            message_factory: HighLevelMessageFactory::new(id, NodeType::Server),
-            packet_recv,
-            packet_send,
-            controller_send,
+            replication: config.replication(),
+            directory: config.directory(),
+            transport: Transport::new(id, packet_recv, packet_send, controller_send, config),
             controller_recv,
             server_type,
-            file_list: hm,
+            file_list: default_catalog(&server_type),
         }
     }
-    pub fn run(&mut self) {
-        self.flood_network();
-        loop {
-            select_biased! {
-                recv(self.packet_recv) -> packet => {
-                    if let Ok(packet) = packet {
-                        self.handle_packet(packet);
-                    }
-                },
-                recv(self.controller_recv) -> command => {
-                    if let Ok(command) = command {
-                        self.handle_command(command);
-                    }
-                }
+}
+
+impl Server for ContentServer {
+    type Event = ContentServerEvent;
+    type Command = ContentServerCommand;
+
+    fn transport(&self) -> &Transport<ContentServerEvent> {
+        &self.transport
+    }
+
+    fn transport_mut(&mut self) -> &mut Transport<ContentServerEvent> {
+        &mut self.transport
+    }
+
+    fn controller_recv(&self) -> &Receiver<ContentServerCommand> {
+        &self.controller_recv
+    }
+
+    fn handle_command(&mut self, command: ContentServerCommand) {
+        ContentServer::handle_command(self, command);
+    }
+
+    fn handle_message(&mut self, message: Message) {
+        ContentServer::handle_message(self, message);
+    }
+
+    /// Asks the servers first seen in a path trace whether they serve content.
+    fn discover_servers(&mut self, path_trace: &[(NodeId, NodeType)]) {
+        for &(id, node_type) in path_trace {
+            if id != self.transport.id
+                && matches!(node_type, NodeType::Server)
+                && self.replication.discovered(id)
+            {
+                self.transport
+                    .send_request_to_server(ClientMessage::GetServerType, id);
             }
         }
     }
 
-    pub fn flood_network(&mut self) {
-        let requests = self.router.get_flood_requests(self.packet_send.len());
-        for (sender, request) in self.packet_send.values().zip(requests) {
-            self.send_packet(request, Some(sender));
+    fn forget_present_servers(&mut self) {
+        self.replication.clear_present();
+    }
+
+    fn clients(&self) -> Vec<NodeId> {
+        Vec::new()
+    }
+
+    /// Replicas kept for the peers are listed too, with `replica` as their path.
+    fn catalog(&self) -> BTreeMap<String, String> {
+        let replicas = self
+            .replication
+            .names()
+            .map(|name| (name.clone(), "replica".to_string()));
+        replicas.chain(self.file_list.clone()).collect()
+    }
+
+    /// Replicas are left out, peers offer them again.
+    fn snapshot(&self) -> ServerSnapshot {
+        ServerSnapshot {
+            file_list: self.file_list.clone().into_iter().collect(),
+            ..self.transport.snapshot()
         }
-        thread::sleep(Duration::from_secs(2));
+    }
+
+    /// Replication peers are found again by `run`.
+    fn restore_state(&mut self, snapshot: ServerSnapshot) {
+        self.file_list = snapshot.file_list.into_iter().collect();
+        info!(
+            "{} [ ContentServer {} ]: Restored {} files and {} routes.",
+            "✔".green(),
+            self.transport.id,
+            self.file_list.len(),
+            self.transport.topology.path_traces().len()
+        );
+    }
+
+    fn config_applied(&mut self) {
+        let config = &self.transport.config;
+        self.replication.factor = config.replication_factor;
+        self.replication.interval = Duration::from_millis(config.replication_interval_ms);
+        self.replication.max_replicas = config.max_replicas;
+        self.directory.set_configured(config.redirects.clone());
+        self.evict_replicas();
+    }
+
+    fn on_interval(&mut self) {
+        self.replicate_if_due();
     }
 }
@@ -0,0 +1,227 @@
+use crate::servers::capabilities::Capabilities;
+use crate::servers::replication::CatalogEntry;
+use serde::{Deserialize, Serialize};
+use wg_2024::network::NodeId;
+
+/// Prefix marking a chat content string as a control message for the server.
+///
+/// `ClientMessage` has no variants for server-side features, so a client talks to the
+/// server itself by sending a `SendMessage` whose `recipient_id` is the server id and
+/// whose content starts with this prefix. Replies travel back as `MessageReceived`
+/// with the server as `sender_id`.
+pub const CONTROL_PREFIX: &str = "#ctl:";
+
+/// Requests a client, or a peer server, can address to the server through a control
+/// message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlMessage {
+    /// Publishes the sender's public key (base64) so other clients can fetch it.
+    PublishKey(String),
+    /// Asks for the public keys of every registered client.
+    GetKeys,
+    /// Turns on an optional feature for the messages the server sends to the client.
+    Enable(Feature),
+    /// Asks what the server supports.
+    GetCapabilities,
+    /// Files a peer content server holds and expects this server to replicate.
+    Digest(Vec<CatalogEntry>),
+    /// Clients registered on a peer chat server, replacing the ones announced before.
+    Clients(Vec<NodeId>),
+    /// A chat message for a client registered on the receiving peer chat server.
+    Relay {
+        sender_id: NodeId,
+        recipient_id: NodeId,
+        content: String,
+    },
+    /// A relayed message whose recipient is not registered on the peer chat server.
+    RelayFailed {
+        sender_id: NodeId,
+        recipient_id: NodeId,
+    },
+}
+
+/// Optional features a client can enable with [`ControlMessage::Enable`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Feature {
+    /// Parity fragments in large responses, see `fec`.
+    Fec,
+    /// DEFLATE-compressed text contents, see `compression`.
+    Deflate,
+    /// Redirects for files a content server does not hold, see `directory`.
+    Redirect,
+}
+
+impl Feature {
+    pub const ALL: [Self; 3] = [Self::Fec, Self::Deflate, Self::Redirect];
+
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Fec => "fec",
+            Self::Deflate => "deflate",
+            Self::Redirect => "redirect",
+        }
+    }
+
+    #[must_use]
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "fec" => Some(Self::Fec),
+            "deflate" => Some(Self::Deflate),
+            "redirect" => Some(Self::Redirect),
+            _ => None,
+        }
+    }
+}
+
+/// Replies the server sends back inside a `MessageReceived`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlReply {
+    /// Public keys of the registered clients that published one.
+    Keys(Vec<(NodeId, String)>),
+    /// The feature is now enabled.
+    Enabled(Feature),
+    /// What the server supports.
+    Capabilities(Capabilities),
+    /// The requested file is not here but on another server.
+    Redirect { file_name: String, node_id: NodeId },
+    /// The requested file is in no catalog of the server, or cannot be read.
+    ///
+    /// Only sent to clients that enabled a feature.
+    NotFound(String),
+    /// The control message was understood but rejected.
+    Rejected(String),
+}
+
+impl ControlMessage {
+    /// Parses a chat content string, returning `None` if it is not a control message.
+    #[must_use]
+    pub fn parse(content: &str) -> Option<Self> {
+        let body = content.strip_prefix(CONTROL_PREFIX)?;
+        let (name, args) = body.split_once(':').unwrap_or((body, ""));
+        match name {
+            "publish_key" if !args.is_empty() => Some(Self::PublishKey(args.to_string())),
+            "get_keys" => Some(Self::GetKeys),
+            "enable" => Feature::parse(args).map(Self::Enable),
+            "capabilities" => Some(Self::GetCapabilities),
+            "digest" => args
+                .split(';')
+                .filter(|entry| !entry.is_empty())
+                .map(CatalogEntry::parse)
+                .collect::<Option<_>>()
+                .map(Self::Digest),
+            "clients" => args
+                .split(',')
+                .filter(|id| !id.is_empty())
+                .map(|id| id.parse().ok())
+                .collect::<Option<_>>()
+                .map(Self::Clients),
+            "relay" => {
+                let mut fields = args.splitn(3, ':');
+                Some(Self::Relay {
+                    sender_id: fields.next()?.parse().ok()?,
+                    recipient_id: fields.next()?.parse().ok()?,
+                    content: fields.next()?.to_string(),
+                })
+            }
+            "relay_failed" => {
+                let (sender_id, recipient_id) = args.split_once(':')?;
+                Some(Self::RelayFailed {
+                    sender_id: sender_id.parse().ok()?,
+                    recipient_id: recipient_id.parse().ok()?,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    #[must_use]
+    pub fn encode(&self) -> String {
+        match self {
+            Self::PublishKey(key) => format!("{CONTROL_PREFIX}publish_key:{key}"),
+            Self::GetKeys => format!("{CONTROL_PREFIX}get_keys"),
+            Self::Enable(feature) => format!("{CONTROL_PREFIX}enable:{}", feature.as_str()),
+            Self::GetCapabilities => format!("{CONTROL_PREFIX}capabilities"),
+            Self::Digest(entries) => {
+                let entries = entries
+                    .iter()
+                    .map(CatalogEntry::encode)
+                    .collect::<Vec<_>>()
+                    .join(";");
+                format!("{CONTROL_PREFIX}digest:{entries}")
+            }
+            Self::Clients(clients) => {
+                let clients = clients
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(",");
+                format!("{CONTROL_PREFIX}clients:{clients}")
+            }
+            Self::Relay {
+                sender_id,
+                recipient_id,
+                content,
+            } => format!("{CONTROL_PREFIX}relay:{sender_id}:{recipient_id}:{content}"),
+            Self::RelayFailed {
+                sender_id,
+                recipient_id,
+            } => format!("{CONTROL_PREFIX}relay_failed:{sender_id}:{recipient_id}"),
+        }
+    }
+}
+
+impl ControlReply {
+    #[must_use]
+    pub fn encode(&self) -> String {
+        match self {
+            Self::Keys(keys) => {
+                let keys = keys
+                    .iter()
+                    .map(|(id, key)| format!("{id}:{key}"))
+                    .collect::<Vec<_>>()
+                    .join(";");
+                format!("{CONTROL_PREFIX}keys:{keys}")
+            }
+            Self::Enabled(feature) => format!("{CONTROL_PREFIX}enabled:{}", feature.as_str()),
+            Self::Capabilities(capabilities) => {
+                format!("{CONTROL_PREFIX}capabilities:{}", capabilities.encode())
+            }
+            Self::Redirect { file_name, node_id } => {
+                format!("{CONTROL_PREFIX}redirect:{node_id}:{file_name}")
+            }
+            Self::NotFound(file_name) => format!("{CONTROL_PREFIX}not_found:{file_name}"),
+            Self::Rejected(reason) => format!("{CONTROL_PREFIX}rejected:{reason}"),
+        }
+    }
+
+    /// Parses a reply, as a client receiving it would.
+    #[must_use]
+    pub fn parse(content: &str) -> Option<Self> {
+        let body = content.strip_prefix(CONTROL_PREFIX)?;
+        let (name, args) = body.split_once(':').unwrap_or((body, ""));
+        match name {
+            "keys" => Some(Self::Keys(
+                args.split(';')
+                    .filter_map(|entry| {
+                        let (id, key) = entry.split_once(':')?;
+                        Some((id.parse().ok()?, key.to_string()))
+                    })
+                    .collect(),
+            )),
+            "enabled" => Feature::parse(args).map(Self::Enabled),
+            "capabilities" => Capabilities::parse(args).map(Self::Capabilities),
+            "redirect" => {
+                let (node_id, file_name) = args.split_once(':')?;
+                Some(Self::Redirect {
+                    file_name: file_name.to_string(),
+                    node_id: node_id.parse().ok()?,
+                })
+            }
+            "not_found" => Some(Self::NotFound(args.to_string())),
+            "rejected" => Some(Self::Rejected(args.to_string())),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use wg_2024::network::NodeId;
+
+/// Sessions remembered per source before the oldest one is forgotten.
+pub const DEFAULT_DEDUP_WINDOW: usize = 64;
+
+/// Detects fragments that were already received.
+///
+/// A drone duplicating a fragment, or a retransmission racing its ack, would otherwise
+/// feed the same fragment to the message factory twice and get the message handled
+/// twice. Sessions stay remembered after their message is complete, so late duplicates
+/// are caught too, up to `window` sessions per source.
+#[derive(Debug)]
+pub struct DuplicateFilter {
+    window: usize,
+    seen: HashMap<(NodeId, u64), HashSet<u64>>,
+    sessions: HashMap<NodeId, VecDeque<u64>>, //sessions of each source, oldest first
+}
+
+impl Default for DuplicateFilter {
+    fn default() -> Self {
+        Self::new(DEFAULT_DEDUP_WINDOW)
+    }
+}
+
+impl DuplicateFilter {
+    #[must_use]
+    pub fn new(window: usize) -> Self {
+        Self {
+            window: window.max(1),
+            seen: HashMap::new(),
+            sessions: HashMap::new(),
+        }
+    }
+
+    /// Changes the number of sessions remembered per source, forgetting the oldest ones
+    /// over it.
+    pub fn set_window(&mut self, window: usize) {
+        self.window = window.max(1);
+        for (&source_id, sessions) in &mut self.sessions {
+            while sessions.len() > self.window {
+                if let Some(oldest) = sessions.pop_front() {
+                    self.seen.remove(&(source_id, oldest));
+                }
+            }
+        }
+    }
+
+    /// Records a fragment, returning `false` if it was already received.
+    pub fn first_seen(&mut self, source_id: NodeId, session_id: u64, fragment_index: u64) -> bool {
+        if let Some(fragments) = self.seen.get_mut(&(source_id, session_id)) {
+            return fragments.insert(fragment_index);
+        }
+
+        let sessions = self.sessions.entry(source_id).or_default();
+        sessions.push_back(session_id);
+        if sessions.len() > self.window {
+            if let Some(oldest) = sessions.pop_front() {
+                self.seen.remove(&(source_id, oldest));
+            }
+        }
+        self.seen
+            .insert((source_id, session_id), HashSet::from([fragment_index]));
+        true
+    }
+
+    /// Forgets the fragments received for a session, so they are accepted again.
+    ///
+    /// Used when the incomplete message of the session is discarded: its
+    /// retransmissions must not be taken for duplicates.
+    pub fn forget(&mut self, source_id: NodeId, session_id: u64) {
+        if self.seen.remove(&(source_id, session_id)).is_some() {
+            if let Some(sessions) = self.sessions.get_mut(&source_id) {
+                sessions.retain(|&session| session != session_id);
+            }
+        }
+    }
+
+    /// Number of sessions currently remembered.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.seen.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.seen.is_empty()
+    }
+}
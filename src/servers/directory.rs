@@ -0,0 +1,46 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use wg_2024::network::NodeId;
+
+/// Where files the server does not hold can be found, to redirect clients there.
+///
+/// Entries come from the catalog digests of peer content servers, or are configured
+/// in `redirects` for servers that do not replicate; configured entries take
+/// precedence.
+#[derive(Debug, Default)]
+pub struct Directory {
+    configured: HashMap<String, NodeId>,
+    learned: BTreeMap<NodeId, BTreeSet<String>>,
+}
+
+impl Directory {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records by hand that `node_id` hosts `name`.
+    pub fn configure(&mut self, name: impl Into<String>, node_id: NodeId) {
+        self.configured.insert(name.into(), node_id);
+    }
+
+    /// Replaces every entry configured by hand with `entries`.
+    pub fn set_configured(&mut self, entries: impl IntoIterator<Item = (String, NodeId)>) {
+        self.configured = entries.into_iter().collect();
+    }
+
+    /// Replaces what `node_id` hosts with the names of its latest digest.
+    pub fn learn(&mut self, node_id: NodeId, names: impl IntoIterator<Item = String>) {
+        self.learned.insert(node_id, names.into_iter().collect());
+    }
+
+    /// A server known to host `name`, among the `present` ones if learned from a digest.
+    #[must_use]
+    pub fn locate(&self, name: &str, present: &BTreeSet<NodeId>) -> Option<NodeId> {
+        self.configured.get(name).copied().or_else(|| {
+            self.learned
+                .iter()
+                .find(|(node_id, names)| present.contains(node_id) && names.contains(name))
+                .map(|(&node_id, _)| node_id)
+        })
+    }
+}
@@ -0,0 +1,158 @@
+use serde::{Deserialize, Serialize};
+use wg_2024::packet::{Fragment, Packet, PacketType};
+
+/// Set in the fragment index of a parity fragment.
+///
+/// Parity fragments are only sent to clients that enabled forward error correction, so
+/// a standard client never sees one.
+pub const PARITY_FLAG: u64 = 1 << 62;
+
+/// Largest number of data fragments protected by one parity fragment.
+pub const MAX_BLOCK: u64 = 16;
+
+/// Fewest data fragments a response needs to get parity fragments; smaller ones are
+/// cheaper to retransmit.
+pub const FEC_MIN_FRAGMENTS: usize = 8;
+
+/// Loss rate below which adaptive correction sends no parity at all.
+const MIN_LOSS: f64 = 1.0 / 32.0;
+
+/// How much redundancy a server adds to large responses.
+///
+/// Written `"off"`, `"adaptive"` or `{ fixed = 4 }` in a configuration.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FecPolicy {
+    /// No parity fragments.
+    Off,
+    /// One parity fragment every given number of data fragments.
+    Fixed(u64),
+    /// Blocks sized from the loss observed on the route, none on a clean route.
+    #[default]
+    Adaptive,
+}
+
+impl FecPolicy {
+    /// Data fragments per parity fragment on a route losing `loss` of its fragments,
+    /// or `None` for no parity.
+    #[must_use]
+    pub fn block_size(self, loss: f64) -> Option<u64> {
+        match self {
+            Self::Off => None,
+            Self::Fixed(block) => Some(block.clamp(1, MAX_BLOCK)),
+            Self::Adaptive if loss < MIN_LOSS => None,
+            // about one loss every two blocks, so a single parity fragment recovers it
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            Self::Adaptive => Some(((0.5 / loss) as u64).clamp(2, MAX_BLOCK)),
+        }
+    }
+}
+
+/// Fragments protected by a parity fragment: `len` data fragments from `start`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParityBlock {
+    pub start: u64,
+    pub len: u64,
+}
+
+impl ParityBlock {
+    #[must_use]
+    pub fn encode(self) -> u64 {
+        PARITY_FLAG | (self.len << 32) | self.start
+    }
+
+    /// The block of a parity fragment, or `None` for a data fragment.
+    #[must_use]
+    pub fn decode(fragment_index: u64) -> Option<Self> {
+        (fragment_index & PARITY_FLAG != 0).then_some(Self {
+            start: fragment_index & 0xFFFF_FFFF,
+            len: (fragment_index & !PARITY_FLAG) >> 32,
+        })
+    }
+
+    #[must_use]
+    pub fn contains(self, fragment_index: u64) -> bool {
+        (self.start..self.start + self.len).contains(&fragment_index)
+    }
+}
+
+#[must_use]
+pub fn is_parity(fragment_index: u64) -> bool {
+    fragment_index & PARITY_FLAG != 0
+}
+
+/// XOR of the data and lengths of `fragments`.
+fn xor<'a>(fragments: impl IntoIterator<Item = &'a Fragment>) -> ([u8; 128], u8) {
+    let mut data = [0; 128];
+    let mut length = 0;
+    for fragment in fragments {
+        for (byte, other) in data.iter_mut().zip(fragment.data.iter()) {
+            *byte ^= other;
+        }
+        length ^= fragment.length;
+    }
+    (data, length)
+}
+
+/// Interleaves a parity fragment after every `block` fragments of a message.
+///
+/// Each parity fragment takes the route of the first fragment of its block.
+#[must_use]
+pub fn with_parity(packets: Vec<Packet>, block: u64) -> Vec<Packet> {
+    let block = usize::try_from(block.clamp(1, MAX_BLOCK)).unwrap_or(1);
+    let mut interleaved = Vec::with_capacity(packets.len() + packets.len() / block + 1);
+    for chunk in packets.chunks(block) {
+        let fragments = chunk
+            .iter()
+            .filter_map(|packet| match &packet.pack_type {
+                PacketType::MsgFragment(fragment) => Some(fragment),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        interleaved.extend(chunk.iter().cloned());
+        let (Some(first), Some(packet)) = (fragments.first(), chunk.first()) else {
+            continue;
+        };
+        let (data, length) = xor(fragments.iter().copied());
+        let parity = ParityBlock {
+            start: first.fragment_index,
+            len: fragments.len() as u64,
+        };
+        interleaved.push(Packet {
+            routing_header: packet.routing_header.clone(),
+            session_id: packet.session_id,
+            pack_type: PacketType::MsgFragment(Fragment {
+                fragment_index: parity.encode(),
+                total_n_fragments: first.total_n_fragments,
+                length,
+                data,
+            }),
+        });
+    }
+    interleaved
+}
+
+/// Rebuilds the one missing fragment of a block from the others and its parity.
+///
+/// `received` are the fragments of the block that arrived; returns `None` unless
+/// exactly one is missing.
+#[must_use]
+pub fn recover(parity: &Fragment, received: &[Fragment]) -> Option<Fragment> {
+    let block = ParityBlock::decode(parity.fragment_index)?;
+    let mut missing = (block.start..block.start + block.len)
+        .filter(|index| !received.iter().any(|f| f.fragment_index == *index));
+    let fragment_index = missing.next()?;
+    if missing.next().is_some() {
+        return None;
+    }
+    let (data, length) = xor(received
+        .iter()
+        .filter(|fragment| block.contains(fragment.fragment_index))
+        .chain([parity]));
+    Some(Fragment {
+        fragment_index,
+        total_n_fragments: parity.total_n_fragments,
+        length,
+        data,
+    })
+}
@@ -0,0 +1,83 @@
+use std::collections::{BTreeMap, BTreeSet};
+use wg_2024::network::NodeId;
+
+/// Chat servers this one federates with, and the clients registered on each.
+///
+/// Peers are the servers seen in flood path traces that answered `GetServerType` as a
+/// chat server. They announce their registered clients to each other, so a client can
+/// message anyone registered on a peer: its server relays the message to the
+/// recipient's server, which delivers it as if it had been sent locally.
+#[derive(Debug, Default)]
+pub struct Federation {
+    /// Servers whose type is known, to whether they are chat servers.
+    known: BTreeMap<NodeId, bool>,
+    /// Servers seen since the last flood.
+    present: BTreeSet<NodeId>,
+    /// Clients registered on each peer, as last announced.
+    remote_clients: BTreeMap<NodeId, Vec<NodeId>>,
+}
+
+impl Federation {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forgets which servers are present, before a new flood.
+    pub fn clear_present(&mut self) {
+        self.present.clear();
+    }
+
+    /// Records a server seen in a path trace, returning whether its type must be asked.
+    pub fn discovered(&mut self, id: NodeId) -> bool {
+        self.present.insert(id);
+        !self.known.contains_key(&id)
+    }
+
+    /// Records the type of a server, returning whether it became a peer.
+    pub fn identified(&mut self, id: NodeId, chat: bool) -> bool {
+        if !chat {
+            self.remote_clients.remove(&id);
+        }
+        self.known.insert(id, chat) != Some(chat) && chat
+    }
+
+    #[must_use]
+    pub fn is_peer(&self, id: NodeId) -> bool {
+        self.present.contains(&id) && self.known.get(&id) == Some(&true)
+    }
+
+    /// Chat servers present since the last flood.
+    #[must_use]
+    pub fn peers(&self) -> BTreeSet<NodeId> {
+        self.present
+            .iter()
+            .copied()
+            .filter(|&id| self.is_peer(id))
+            .collect()
+    }
+
+    /// Replaces the clients registered on a peer.
+    pub fn set_clients(&mut self, peer: NodeId, clients: Vec<NodeId>) {
+        self.remote_clients.insert(peer, clients);
+    }
+
+    /// The peer a remote client is registered on.
+    #[must_use]
+    pub fn home_of(&self, client: NodeId) -> Option<NodeId> {
+        self.remote_clients
+            .iter()
+            .find(|(&peer, clients)| self.is_peer(peer) && clients.contains(&client))
+            .map(|(&peer, _)| peer)
+    }
+
+    /// Clients registered on the peers.
+    #[must_use]
+    pub fn remote_clients(&self) -> Vec<NodeId> {
+        self.remote_clients
+            .iter()
+            .filter(|(&peer, _)| self.is_peer(peer))
+            .flat_map(|(_, clients)| clients.iter().copied())
+            .collect()
+    }
+}
@@ -1,49 +1,74 @@
 use crate::servers::communication_server::CommunicationServer;
 use crate::servers::content_server::ContentServer;
+use crate::servers::server::Server;
+use crate::servers::transport::{ControllerEvent, Transport};
 use colored::Colorize;
+use crossbeam_channel::Sender;
 use log::{info, warn};
 use messages::server_commands::CommunicationServerCommand;
 use messages::server_commands::ContentServerCommand;
+use wg_2024::network::NodeId;
+use wg_2024::packet::Packet;
+
+impl<E: ControllerEvent> Transport<E> {
+    /// Forgets the neighbour `id` and the links to it; the server floods again after.
+    pub(crate) fn remove_sender(&mut self, id: NodeId) {
+        if self.packet_send.remove(&id).is_some() {
+            info!(
+                "{} [ {} {} ]: Sender removed successfully.",
+                "✔".green(),
+                E::SERVER,
+                self.id
+            );
+        } else {
+            warn!(
+                "{} [ {} {} ]: Sender [ Drone {id} ] not found.",
+                "!!!".yellow(),
+                E::SERVER,
+                self.id
+            );
+        }
+        self.router.remove_neighbour(id);
+        self.topology.remove_link(self.id, id);
+    }
+
+    /// Adds the neighbour `id`, returning whether it is new and the server should flood.
+    pub(crate) fn add_sender(&mut self, id: NodeId, sender: Sender<Packet>) -> bool {
+        if let std::collections::hash_map::Entry::Vacant(e) = self.packet_send.entry(id) {
+            e.insert(sender);
+            info!(
+                "{} [ {} {} ]: Sender added successfully.",
+                "✔".green(),
+                E::SERVER,
+                self.id
+            );
+            self.router.add_neighbour(id);
+            true
+        } else {
+            warn!(
+                "{} [ {} {} ] is already connected to [ Drone {id} ]",
+                "!!!".yellow(),
+                E::SERVER,
+                self.id
+            );
+            false
+        }
+    }
+}
 
 impl CommunicationServer {
     /// Handles commands directed at the communication server.
     pub fn handle_command(&mut self, command: CommunicationServerCommand) {
         match command {
             CommunicationServerCommand::InitFlooding => self.flood_network(),
-            CommunicationServerCommand::LogNetwork => self.router.log_network(),
+            CommunicationServerCommand::LogNetwork => self.transport.router.log_network(),
             CommunicationServerCommand::RemoveSender(id) => {
-                if self.packet_send.remove(&id).is_some() {
-                    info!(
-                        "{} [ CommunicationServer {} ]: Sender removed successfully.",
-                        "✔".green(),
-                        self.id
-                    );
-                } else {
-                    warn!(
-                        "{} [ CommunicationServer {} ]: Sender [ Drone {id} ] not found.",
-                        "!!!".yellow(),
-                        self.id
-                    );
-                }
-                self.router.remove_neighbour(id);
+                self.transport.remove_sender(id);
                 self.flood_network();
             }
             CommunicationServerCommand::AddSender(id, sender) => {
-                if let std::collections::hash_map::Entry::Vacant(e) = self.packet_send.entry(id) {
-                    e.insert(sender);
-                    info!(
-                        "{} [ CommunicationServer {} ]: Sender added successfully.",
-                        "✔".green(),
-                        self.id
-                    );
-                    self.router.add_neighbour(id);
+                if self.transport.add_sender(id, sender) {
                     self.flood_network();
-                } else {
-                    warn!(
-                        "{} [ CommunicationServer {} ] is already connected to [ Drone {id} ]",
-                        "!!!".yellow(),
-                        self.id
-                    );
                 }
             }
         }
@@ -55,38 +80,12 @@ impl ContentServer {
         match command {
             ContentServerCommand::InitFlooding => self.flood_network(),
             ContentServerCommand::RemoveSender(id) => {
-                if self.packet_send.remove(&id).is_some() {
-                    info!(
-                        "{} [ CommunicationServer {} ]: Sender removed successfully.",
-                        "✔".green(),
-                        self.id
-                    );
-                } else {
-                    warn!(
-                        "{} [ CommunicationServer {} ]: Sender [ Drone {id} ] not found.",
-                        "!!!".yellow(),
-                        self.id
-                    );
-                }
-                self.router.remove_neighbour(id);
+                self.transport.remove_sender(id);
                 self.flood_network();
             }
             ContentServerCommand::AddSender(id, sender) => {
-                if let std::collections::hash_map::Entry::Vacant(e) = self.packet_send.entry(id) {
-                    e.insert(sender);
-                    info!(
-                        "{} [ ContentServer {} ]: Sender added successfully.",
-                        "✔".green(),
-                        self.id
-                    );
-                    self.router.add_neighbour(id);
+                if self.transport.add_sender(id, sender) {
                     self.flood_network();
-                } else {
-                    warn!(
-                        "{} [ ContentServer {} ] is already connected to [ Drone {id} ]",
-                        "!!!".yellow(),
-                        self.id
-                    );
                 }
             }
         }
@@ -1,38 +1,157 @@
+use crate::servers::capabilities::{server_type_name, Capabilities, PROTOCOL_VERSION};
+use crate::servers::catalog::{read_file, read_from_catalog, read_media};
 use crate::servers::communication_server::CommunicationServer;
 use crate::servers::content_server::ContentServer;
+use crate::servers::control_message::{ControlMessage, ControlReply, Feature};
+use crate::servers::metrics::client_message_kind;
+use crate::servers::replication::{holders, CatalogEntry};
+use crate::servers::transport::{ControllerEvent, Transport};
 use base64::{engine::general_purpose, Engine as _};
 use colored::Colorize;
-use image::ImageReader;
-use log::{error, info};
+use log::{error, info, warn};
 use messages::high_level_messages::MessageContent::{FromClient, FromServer};
 use messages::high_level_messages::ServerMessage::ServerType;
 use messages::high_level_messages::{ClientMessage, Message, ServerMessage};
-use std::io::Cursor;
+use std::collections::{BTreeSet, HashMap};
+use std::time::Instant;
 use wg_2024::network::NodeId;
 
+/// Upper bound on the decoded size of a published public key.
+const MAX_PUBLIC_KEY_LEN: usize = 1024;
+
+impl<E: ControllerEvent> Transport<E> {
+    /// Counts a request of a client, returning whether it is over the rate limit.
+    ///
+    /// Peer servers are `exempt`, their requests keep the network in sync.
+    pub(crate) fn rate_limited(&mut self, source_id: NodeId, exempt: bool) -> bool {
+        if exempt
+            || self
+                .rate_limiter
+                .allow(source_id, self.config.rate_limit, Instant::now())
+        {
+            return false;
+        }
+        warn!(
+            "{} [ {} {} ]: Request of client {source_id} over the rate limit.",
+            "!!!".yellow(),
+            E::SERVER,
+            self.id
+        );
+        self.metrics.increment("requests_rate_limited");
+        true
+    }
+
+    /// Enables `feature` for `client_id` and confirms it.
+    pub(crate) fn enable(&mut self, feature: Feature, client_id: NodeId) {
+        self.client_features
+            .entry(client_id)
+            .or_default()
+            .insert(feature);
+        self.send_control_reply(&ControlReply::Enabled(feature), client_id);
+    }
+
+    /// Whether `client_id` enabled a feature, and so understands control replies it did
+    /// not ask for: the others get no reply, as before the control protocol.
+    fn negotiated(&self, client_id: NodeId) -> bool {
+        self.client_features
+            .get(&client_id)
+            .is_some_and(|features| !features.is_empty())
+    }
+
+    /// The capabilities of a server of type `server_type` answering `requests`, with
+    /// every feature the configuration allows.
+    pub(crate) fn capabilities(
+        &self,
+        server_type: &messages::high_level_messages::ServerType,
+        requests: &[ClientMessage],
+    ) -> Capabilities {
+        Capabilities {
+            node_id: self.id,
+            server_type: server_type_name(server_type).to_string(),
+            roles: vec![server_type_name(server_type).to_string()],
+            protocol_version: PROTOCOL_VERSION,
+            requests: requests
+                .iter()
+                .map(|request| client_message_kind(request).to_string())
+                .collect(),
+            features: Feature::ALL
+                .into_iter()
+                .filter(|&feature| self.config.allows(feature))
+                .collect(),
+            encryption: false,
+            max_upload_size: self.reassembly.limits().max_message_size,
+            catalog_size: 0,
+        }
+    }
+
+    /// Sends a file or media of `file_list`, or tells the client it is not found if it
+    /// speaks the control protocol.
+    pub(crate) fn send_from_catalog(
+        &mut self,
+        file_list: &HashMap<String, String>,
+        file_name: String,
+        media: bool,
+        destination_id: NodeId,
+    ) {
+        match read_from_catalog(file_list, &file_name, media, &self.config) {
+            Ok(server_message) => self.send_message_to_client(&server_message, destination_id),
+            Err(e) => {
+                error!(
+                    "{} [ {} {} ]: Failed to read file {}, error: {e}",
+                    "✗".red(),
+                    E::SERVER,
+                    self.id,
+                    file_name
+                );
+                if self.negotiated(destination_id) {
+                    self.send_control_reply(&ControlReply::NotFound(file_name), destination_id);
+                }
+            }
+        }
+    }
+}
+
 impl CommunicationServer {
     #[allow(clippy::too_many_lines)]
     pub fn handle_message(&mut self, message: Message) {
         info!(
-            "{}, CommunicationServer {}, Recived a packet {:?}",
+            "{}, CommunicationServer {}, Recived a message from {}",
             "✔".green(),
-            self.id,
-            message
+            self.transport.id,
+            message.source_id
         );
-        let FromClient(content) = message.content else {
-            error!(
-                "{} [ CommunicationServer {} ]: Received message is not from a client.",
-                "✗".red(),
-                self.id
-            );
-            return;
+        let content = match message.content {
+            FromClient(content) => content,
+            FromServer(server_message) => {
+                // replies of peer servers to the requests sent for federation
+                self.handle_server_message(&server_message, message.source_id);
+                return;
+            }
         };
+        let peer = self.federation.is_peer(message.source_id);
+        if self.transport.rate_limited(message.source_id, peer) {
+            return;
+        }
+        self.transport.metrics.client_message_handled(&content);
 
         match content {
             ClientMessage::GetServerType => {
                 // Retrieve and send server type to the client
                 let server_message = ServerType(self.server_type);
-                self.send_message_to_client(&server_message, message.source_id);
+                self.transport
+                    .send_message_to_client(&server_message, message.source_id);
+            }
+            ClientMessage::RegisterToChat
+            | ClientMessage::Logout
+            | ClientMessage::GetClientList
+                if !self.roles.chat =>
+            {
+                self.reject_chat_request(message.source_id);
+            }
+            ClientMessage::SendMessage { recipient_id, .. }
+                if !self.roles.chat && recipient_id != self.transport.id =>
+            {
+                self.reject_chat_request(message.source_id);
             }
             ClientMessage::RegisterToChat => {
                 // Handle client registration to chat
@@ -40,19 +159,20 @@ impl CommunicationServer {
                     error!(
                         "{} [ CommunicationServer {} ]: Client {} already registered to chat",
                         "✗".red(),
-                        self.id,
+                        self.transport.id,
                         message.source_id
                     );
                 } else {
                     self.registered_clients.push(message.source_id);
-                    self.send_message_to_client(
+                    self.transport.send_message_to_client(
                         &ServerMessage::SuccessfulRegistration,
                         message.source_id,
                     );
+                    self.announce_clients(&self.federation.peers());
                     info!(
                         "{}, CommunicationServer {}, Client {} registered to chat",
                         "✔".green(),
-                        self.id,
+                        self.transport.id,
                         message.source_id
                     );
                 }
@@ -66,38 +186,51 @@ impl CommunicationServer {
                     .position(|&id| id == message.source_id)
                 {
                     self.registered_clients.remove(index);
-                    self.send_message_to_client(
+                    self.public_keys.remove(&message.source_id);
+                    self.transport.send_message_to_client(
                         &ServerMessage::SuccessfullLogOut,
                         message.source_id,
                     );
+                    self.announce_clients(&self.federation.peers());
                     info!(
                         "{}, CommunicationServer {}, Client {} logged out",
                         "✔".green(),
-                        self.id,
+                        self.transport.id,
                         message.source_id
                     );
                 } else {
                     error!(
                         "{} [ CommunicationServer {} ]: Client {} not registered to chat",
                         "✗".red(),
-                        self.id,
+                        self.transport.id,
                         message.source_id
                     );
                 }
             }
             ClientMessage::GetClientList => {
                 // Retrieve and send the list of clients to the requester
-                let client_list = self.registered_clients.clone();
-                self.send_message_to_client(
+                let mut client_list = self.registered_clients.clone();
+                client_list.extend(self.federation.remote_clients());
+                self.transport.send_message_to_client(
                     &ServerMessage::ClientList(client_list),
                     message.source_id,
                 );
+                if !self.public_keys.is_empty() {
+                    self.send_public_keys(message.source_id);
+                }
+            }
+            ClientMessage::SendMessage {
+                recipient_id,
+                content,
+            } if recipient_id == self.transport.id => {
+                // Messages addressed to the server itself carry control requests
+                self.handle_control_message(&content, message.source_id);
             }
             ClientMessage::SendMessage {
                 recipient_id,
                 content,
             } => {
-                // Send message to the recipient
+                // Relay the content untouched, it may be end-to-end encrypted
                 if self.registered_clients.contains(&recipient_id)
                     && self.registered_clients.contains(&message.source_id)
                 {
@@ -105,192 +238,579 @@ impl CommunicationServer {
                         sender_id: message.source_id,
                         content,
                     };
-                    self.send_message_to_client(&server_message, recipient_id);
-                } else {
-                    self.send_message_to_client(
-                        &ServerMessage::UnreachableClient(message.source_id),
+                    self.transport
+                        .send_message_to_client(&server_message, recipient_id);
+                } else if let Some(peer) = self
+                    .federation
+                    .home_of(recipient_id)
+                    .filter(|_| self.registered_clients.contains(&message.source_id))
+                {
+                    let relay = ControlMessage::Relay {
+                        sender_id: message.source_id,
                         recipient_id,
-                    );
-                    error!(
-                        "{} [ CommunicationServer {} ]: Client {} is not registered to chat",
-                        "✗".red(),
-                        self.id,
-                        recipient_id
-                    );
+                        content,
+                    };
+                    self.send_to_peer(&relay, peer);
+                } else {
+                    self.unreachable_recipient(message.source_id, recipient_id);
                 }
             }
 
+            ClientMessage::GetFilesList if self.roles.text || self.roles.media => {
+                let files_list = self.file_list.keys().cloned().collect();
+                self.transport.send_message_to_client(
+                    &ServerMessage::FilesList(files_list),
+                    message.source_id,
+                );
+            }
+            ClientMessage::GetFile(file_name) if self.roles.text => {
+                self.transport.send_from_catalog(
+                    &self.file_list,
+                    file_name,
+                    false,
+                    message.source_id,
+                );
+            }
+            ClientMessage::GetMedia(file_name) if self.roles.media => {
+                self.transport.send_from_catalog(
+                    &self.file_list,
+                    file_name,
+                    true,
+                    message.source_id,
+                );
+            }
             ClientMessage::GetFilesList
             | ClientMessage::GetFile(_)
             | ClientMessage::GetMedia(_) => {
                 error!(
                     "{} [ CommunicationServer {} ]: This is not a MediaServer, wrong request",
                     "✗".red(),
-                    self.id
+                    self.transport.id
+                );
+            }
+        }
+    }
+
+    /// Reports a message that cannot be delivered because its recipient is not registered.
+    fn unreachable_recipient(&mut self, sender_id: NodeId, recipient_id: NodeId) {
+        self.transport
+            .send_message_to_client(&ServerMessage::UnreachableClient(sender_id), recipient_id);
+        error!(
+            "{} [ CommunicationServer {} ]: Client {} is not registered to chat",
+            "✗".red(),
+            self.transport.id,
+            recipient_id
+        );
+    }
+
+    /// Handles a message a peer server sent in reply to a federation request.
+    fn handle_server_message(&mut self, server_message: &ServerMessage, source_id: NodeId) {
+        if let ServerType(server_type) = server_message {
+            let chat = matches!(server_type, messages::high_level_messages::ServerType::Chat);
+            if self.federation.identified(source_id, chat) {
+                info!(
+                    "{}, CommunicationServer {}, Federating with chat server {}",
+                    "✔".green(),
+                    self.transport.id,
+                    source_id
                 );
+                self.announce_clients(&BTreeSet::from([source_id]));
             }
         }
+        // the control replies of peers come back as chat messages, nothing waits on them
     }
 
-    fn send_message_to_client(&mut self, server_message: &ServerMessage, destination_id: NodeId) {
-        let Ok(header) = self.router.get_source_routing_header(destination_id) else {
+    /// Tells peers which clients are registered here.
+    fn announce_clients(&mut self, peers: &BTreeSet<NodeId>) {
+        let clients = ControlMessage::Clients(self.registered_clients.clone());
+        for &peer in peers {
+            self.send_to_peer(&clients, peer);
+        }
+    }
+
+    /// Sends a control message to a peer server, as a client would.
+    fn send_to_peer(&mut self, control_message: &ControlMessage, peer: NodeId) {
+        let request = ClientMessage::SendMessage {
+            recipient_id: peer,
+            content: control_message.encode(),
+        };
+        self.transport.send_request_to_server(request, peer);
+    }
+
+    /// Handles the control messages peer servers exchange.
+    fn handle_federation_message(&mut self, control_message: ControlMessage, source_id: NodeId) {
+        if !self.federation.is_peer(source_id) {
             error!(
-                "{} [ Communication {} ]: Cannot send message, destination {} is unreachable",
+                "{} [ CommunicationServer {} ]: Federation message from {}, which is not a peer",
                 "✗".red(),
-                self.id,
-                destination_id
+                self.transport.id,
+                source_id
             );
+            let reply = ControlReply::Rejected("not a federated server".to_string());
+            self.transport.send_control_reply(&reply, source_id);
             return;
+        }
+        match control_message {
+            ControlMessage::Clients(clients) => self.federation.set_clients(source_id, clients),
+            ControlMessage::Relay {
+                sender_id,
+                recipient_id,
+                content,
+            } => {
+                if self.registered_clients.contains(&recipient_id) {
+                    let server_message = ServerMessage::MessageReceived { sender_id, content };
+                    self.transport
+                        .send_message_to_client(&server_message, recipient_id);
+                    self.transport.metrics.increment("messages_relayed_in");
+                } else {
+                    let failed = ControlMessage::RelayFailed {
+                        sender_id,
+                        recipient_id,
+                    };
+                    self.send_to_peer(&failed, source_id);
+                }
+            }
+            ControlMessage::RelayFailed {
+                sender_id,
+                recipient_id,
+            } => self.unreachable_recipient(sender_id, recipient_id),
+            _ => {}
+        }
+    }
+
+    fn reject_chat_request(&self, source_id: NodeId) {
+        error!(
+            "{} [ CommunicationServer {} ]: Chat request from client {} but chat is disabled",
+            "✗".red(),
+            self.transport.id,
+            source_id
+        );
+    }
+
+    /// Handles a control message sent by a client to the server itself.
+    fn handle_control_message(&mut self, content: &str, source_id: NodeId) {
+        let reply = match ControlMessage::parse(content) {
+            Some(ControlMessage::PublishKey(key)) => {
+                if !self.registered_clients.contains(&source_id) {
+                    ControlReply::Rejected("not registered".to_string())
+                } else if !is_valid_public_key(&key) {
+                    ControlReply::Rejected("invalid key".to_string())
+                } else {
+                    self.public_keys.insert(source_id, key);
+                    info!(
+                        "{}, CommunicationServer {}, Client {} published its public key",
+                        "✔".green(),
+                        self.transport.id,
+                        source_id
+                    );
+                    return;
+                }
+            }
+            Some(ControlMessage::GetKeys) => {
+                self.send_public_keys(source_id);
+                return;
+            }
+            Some(ControlMessage::Enable(Feature::Redirect)) => {
+                ControlReply::Rejected("not a content server".to_string())
+            }
+            Some(ControlMessage::Enable(feature)) if !self.transport.config.allows(feature) => {
+                ControlReply::Rejected(format!("{} is disabled", feature.as_str()))
+            }
+            Some(ControlMessage::Enable(feature)) => {
+                self.transport.enable(feature, source_id);
+                return;
+            }
+            Some(ControlMessage::GetCapabilities) => {
+                self.transport.send_control_reply(
+                    &ControlReply::Capabilities(self.capabilities()),
+                    source_id,
+                );
+                return;
+            }
+            Some(
+                control_message @ (ControlMessage::Clients(_)
+                | ControlMessage::Relay { .. }
+                | ControlMessage::RelayFailed { .. }),
+            ) => {
+                self.handle_federation_message(control_message, source_id);
+                return;
+            }
+            Some(ControlMessage::Digest(_)) => {
+                ControlReply::Rejected("not a content server".to_string())
+            }
+            None => ControlReply::Rejected("unknown control message".to_string()),
         };
-        for fragment_packet in self.message_factory.get_message_from_message_content(
-            FromServer(server_message.clone()),
-            &header,
-            destination_id,
-        ) {
-            self.packet_cache.insert_packet(&fragment_packet);
-            self.send_packet(fragment_packet, None);
+        error!(
+            "{} [ CommunicationServer {} ]: Control message from client {} rejected",
+            "✗".red(),
+            self.transport.id,
+            source_id
+        );
+        self.transport.send_control_reply(&reply, source_id);
+    }
+
+    /// What this server supports, as reported to clients.
+    #[must_use]
+    pub fn capabilities(&self) -> Capabilities {
+        let mut requests = vec![
+            ClientMessage::GetServerType,
+            ClientMessage::SendMessage {
+                recipient_id: self.transport.id,
+                content: String::new(),
+            },
+        ];
+        if self.roles.chat {
+            requests.extend([
+                ClientMessage::RegisterToChat,
+                ClientMessage::Logout,
+                ClientMessage::GetClientList,
+            ]);
+        }
+        if self.roles.text || self.roles.media {
+            requests.push(ClientMessage::GetFilesList);
+        }
+        if self.roles.text {
+            requests.push(ClientMessage::GetFile(String::new()));
+        }
+        if self.roles.media {
+            requests.push(ClientMessage::GetMedia(String::new()));
+        }
+        let capabilities = self.transport.capabilities(&self.server_type, &requests);
+        Capabilities {
+            roles: self
+                .roles
+                .server_types()
+                .iter()
+                .map(|server_type| server_type_name(server_type).to_string())
+                .collect(),
+            features: capabilities
+                .features
+                .into_iter()
+                .filter(|&feature| feature != Feature::Redirect)
+                .collect(),
+            encryption: self.roles.chat,
+            catalog_size: self.file_list.len(),
+            ..capabilities
         }
-        info!("Message sent to client {destination_id}: {server_message:?}");
     }
+
+    /// Sends the published public keys of the registered clients.
+    fn send_public_keys(&mut self, destination_id: NodeId) {
+        let mut keys = self
+            .public_keys
+            .iter()
+            .filter(|(id, _)| self.registered_clients.contains(*id))
+            .map(|(id, key)| (*id, key.clone()))
+            .collect::<Vec<_>>();
+        keys.sort_unstable_by_key(|(id, _)| *id);
+        self.transport
+            .send_control_reply(&ControlReply::Keys(keys), destination_id);
+    }
+}
+
+/// Checks that a published public key is base64 and of a sensible size.
+fn is_valid_public_key(key: &str) -> bool {
+    general_purpose::STANDARD
+        .decode(key)
+        .is_ok_and(|bytes| !bytes.is_empty() && bytes.len() <= MAX_PUBLIC_KEY_LEN)
 }
 
 impl ContentServer {
     #[allow(clippy::too_many_lines)]
     pub fn handle_message(&mut self, message: Message) {
-        let FromClient(content) = message.content else {
-            error!(
-                "{} [ CommunicationServer {} ]: Received message is not from a client.",
-                "✗".red(),
-                self.id
-            );
-            return;
+        let content = match message.content {
+            FromClient(content) => content,
+            FromServer(server_message) => {
+                // replies of peer servers to the requests sent for replication
+                self.handle_server_message(server_message, message.source_id);
+                return;
+            }
         };
+        let peer = self.replication.peers().contains(&message.source_id);
+        if self.transport.rate_limited(message.source_id, peer) {
+            return;
+        }
+        self.transport.metrics.client_message_handled(&content);
         match content {
             ClientMessage::GetServerType => {
                 // Retrieve and send server type to the client
                 let server_type = self.server_type;
                 let server_message = ServerType(server_type);
-                self.send_message_to_client(&server_message, message.source_id);
+                self.transport
+                    .send_message_to_client(&server_message, message.source_id);
             }
             ClientMessage::GetFilesList => {
-                let files_list = self.file_list.keys().cloned().collect();
-                self.send_message_to_client(
+                let mut files_list = self.file_list.keys().cloned().collect::<Vec<_>>();
+                for name in self.replication.names() {
+                    if !self.file_list.contains_key(name) {
+                        files_list.push(name.clone());
+                    }
+                }
+                self.transport.send_message_to_client(
                     &ServerMessage::FilesList(files_list),
                     message.source_id,
                 );
             }
             ClientMessage::GetMedia(file_name) => {
-                // println!("[MediaServer {}] received GetMedia({file_name})", self.id);
-                let file_path_t = self.file_list.get(&file_name).unwrap_or(&file_name);
-                let Ok(dir) = std::env::current_dir()
-                    .inspect_err(|e| self.print_error(&file_name, &e.to_string()))
-                else {
-                    return;
-                };
-                let file_path = dir.join("src").join("data_files").join(file_path_t);
-                let Ok(file_content) = ImageReader::open(file_path)
-                    .inspect_err(|e| self.print_error(&file_name, &e.to_string()))
-                else {
-                    return;
-                };
-                let Ok(file_media_content) = file_content.decode().inspect_err(|e| {
-                    self.print_error(&file_name, &e.to_string());
-                }) else {
-                    return;
-                };
-                let mut buf = Vec::new();
-                if file_media_content
-                    .write_to(&mut Cursor::new(&mut buf), image::ImageFormat::Jpeg)
-                    .inspect_err(|e| self.print_error(&file_name, &e.to_string()))
-                    .is_ok()
-                {
-                    let base_64 = general_purpose::STANDARD.encode(&buf);
-                    let server_message = ServerMessage::Media(file_name.clone(), base_64);
-                    self.send_message_to_client(&server_message, message.source_id);
-                }
+                self.answer_file_request(file_name, true, message.source_id);
             }
             ClientMessage::GetFile(file_name) => {
-                if let Some(file_path_t) = self.file_list.get(&file_name) {
-                    match std::env::current_dir() {
-                        Ok(dir) => {
-                            let file_path = dir.join("src").join("text_files").join(file_path_t);
-                            info!("reading file: {:?}", dir.display());
-                            match std::fs::read_to_string(file_path) {
-                                Ok(file_content) => {
-                                    let file_size = file_content.len();
-                                    let server_message = ServerMessage::File {
-                                        file_id: file_name.clone(),
-                                        size: file_size,
-                                        content: file_content,
-                                    };
-                                    self.send_message_to_client(&server_message, message.source_id);
-                                }
-                                Err(e) => {
-                                    self.print_error(&file_name, &e.to_string());
-                                }
-                            }
-                        }
-                        Err(_e) => {}
-                    }
-                } else {
-                    // self.print_error(&file_name);
-                }
+                self.answer_file_request(file_name, false, message.source_id);
             }
             ClientMessage::RegisterToChat
             | ClientMessage::Logout
             | ClientMessage::GetClientList => {
                 error!(
-                    "{} [ CommunicationServer {} ]: This is not a ChatServer, wrong request",
+                    "{} [ ContentServer {} ]: This is not a ChatServer, wrong request",
                     "✗".red(),
-                    self.id
+                    self.transport.id
                 );
             }
+            ClientMessage::SendMessage {
+                recipient_id,
+                content,
+            } if recipient_id == self.transport.id => {
+                // Messages addressed to the server itself carry control requests
+                self.handle_control_message(&content, message.source_id);
+            }
             ClientMessage::SendMessage {
                 recipient_id: _recipient_id,
                 content: _content,
             } => {
                 error!(
-                    "{} [ CommunicationServer {} ]: This is not a ChatServer, wrong request",
+                    "{} [ ContentServer {} ]: This is not a ChatServer, wrong request",
                     "✗".red(),
-                    self.id
+                    self.transport.id
                 );
             }
         }
     }
 
-    fn print_error(&self, file_name: &str, e: &String) {
-        // println!(
-        //     "{} [ ContentServer {} ]: Failed to read file {}, error: {e}",
-        //     "✗".red(),
-        //     self.id,
-        //     file_name
-        // );
+    /// What this server supports, as reported to clients.
+    #[must_use]
+    pub fn capabilities(&self) -> Capabilities {
+        let get_content = match self.server_type {
+            messages::high_level_messages::ServerType::Media => {
+                ClientMessage::GetMedia(String::new())
+            }
+            _ => ClientMessage::GetFile(String::new()),
+        };
+        let requests = [
+            ClientMessage::GetServerType,
+            ClientMessage::GetFilesList,
+            get_content,
+            ClientMessage::SendMessage {
+                recipient_id: self.transport.id,
+                content: String::new(),
+            },
+        ];
+        Capabilities {
+            catalog_size: self.file_list.len(),
+            ..self.transport.capabilities(&self.server_type, &requests)
+        }
+    }
+
+    /// Handles a control message sent by a client to the server itself.
+    fn handle_control_message(&mut self, content: &str, source_id: NodeId) {
+        let reply = match ControlMessage::parse(content) {
+            Some(ControlMessage::Enable(feature)) if !self.transport.config.allows(feature) => {
+                ControlReply::Rejected(format!("{} is disabled", feature.as_str()))
+            }
+            Some(ControlMessage::Enable(feature)) => {
+                self.transport.enable(feature, source_id);
+                return;
+            }
+            Some(ControlMessage::GetCapabilities) => {
+                self.transport.send_control_reply(
+                    &ControlReply::Capabilities(self.capabilities()),
+                    source_id,
+                );
+                return;
+            }
+            Some(ControlMessage::Digest(entries))
+                if self.replication.peers().contains(&source_id) =>
+            {
+                self.fetch_missing(&entries, source_id);
+                return;
+            }
+            Some(ControlMessage::Digest(_)) => {
+                ControlReply::Rejected("not a replication peer".to_string())
+            }
+            Some(
+                ControlMessage::PublishKey(_)
+                | ControlMessage::GetKeys
+                | ControlMessage::Clients(_)
+                | ControlMessage::Relay { .. }
+                | ControlMessage::RelayFailed { .. },
+            ) => ControlReply::Rejected("not a chat server".to_string()),
+            None => ControlReply::Rejected("unknown control message".to_string()),
+        };
         error!(
-            "{} [ ContentServer {} ]: Failed to read file {}, error: {e}",
+            "{} [ ContentServer {} ]: Control message from client {} rejected",
             "✗".red(),
-            self.id,
-            file_name
+            self.transport.id,
+            source_id
         );
+        self.transport.send_control_reply(&reply, source_id);
     }
 
-    fn send_message_to_client(&mut self, server_message: &ServerMessage, destination_id: NodeId) {
-        let Ok(header) = self.router.get_source_routing_header(destination_id) else {
-            error!(
-                "{} [ Content {} ]: Cannot send message, destination {} is unreachable",
-                "✗".red(),
-                self.id,
-                destination_id
-            );
+    /// The server to redirect a client to for a file this server does not hold, if the
+    /// client enabled redirects.
+    fn redirect_for(&self, file_name: &str, client_id: NodeId) -> Option<NodeId> {
+        if !self.transport.enabled(client_id, Feature::Redirect)
+            || self.file_list.contains_key(file_name)
+            || self.replication.get(file_name).is_some()
+        {
+            return None;
+        }
+        self.directory
+            .locate(file_name, &self.replication.peers())
+            .filter(|&node_id| node_id != self.transport.id)
+    }
+
+    /// Handles a message a peer server sent in reply to a replication request.
+    fn handle_server_message(&mut self, server_message: ServerMessage, source_id: NodeId) {
+        match server_message {
+            ServerType(server_type) => {
+                // a text server replicating media would offer it in its files list
+                let same_type =
+                    server_type_name(&server_type) == server_type_name(&self.server_type);
+                if self.replication.identified(source_id, same_type) {
+                    info!(
+                        "{}, ContentServer {}, Replicating with content server {}",
+                        "✔".green(),
+                        self.transport.id,
+                        source_id
+                    );
+                    self.replicate_to(&BTreeSet::from([source_id]));
+                }
+            }
+            server_message @ (ServerMessage::File { .. } | ServerMessage::Media(..)) => {
+                if self.replication.store(source_id, server_message) {
+                    self.transport.metrics.increment("replicas_stored");
+                    self.evict_replicas();
+                } else {
+                    warn!(
+                        "{} [ ContentServer {} ]: Dropped content server {} sent unasked",
+                        "!!!".yellow(),
+                        self.transport.id,
+                        source_id
+                    );
+                    self.transport.metrics.increment("replicas_unsolicited");
+                }
+            }
+            _ => {
+                warn!(
+                    "{} [ ContentServer {} ]: Unexpected message from server {}",
+                    "!!!".yellow(),
+                    self.transport.id,
+                    source_id
+                );
+            }
+        }
+    }
+
+    /// Records where the entries of a peer's digest are, and fetches the ones this
+    /// server should hold but does not.
+    fn fetch_missing(&mut self, entries: &[CatalogEntry], source_id: NodeId) {
+        self.directory
+            .learn(source_id, entries.iter().map(|entry| entry.name.clone()));
+        if self.replication.factor <= 1 {
             return;
-        };
-        for fragment_packet in self.message_factory.get_message_from_message_content(
-            FromServer(server_message.clone()),
-            &header,
-            destination_id,
-        ) {
-            self.packet_cache.insert_packet(&fragment_packet);
-            self.send_packet(fragment_packet, None);
         }
-        info!("Message sent to client {destination_id}: {server_message:?}");
+        let mut servers = self.replication.peers();
+        servers.extend([self.transport.id, source_id]);
+        for entry in entries {
+            let held = self.file_list.contains_key(&entry.name)
+                || self
+                    .replication
+                    .entry(&entry.name)
+                    .is_some_and(|replica| replica.digest == entry.digest);
+            let holder = holders(&entry.name, &servers, self.replication.factor)
+                .contains(&self.transport.id);
+            if !held && holder && self.replication.fetch(source_id, &entry.name) {
+                self.transport
+                    .send_request_to_server(entry.request(), source_id);
+            }
+        }
+    }
+
+    /// Drops the oldest replicas over the limit.
+    pub(crate) fn evict_replicas(&mut self) {
+        let evicted = self.replication.evict();
+        if evicted > 0 {
+            self.transport
+                .metrics
+                .add("replicas_evicted", evicted as u64);
+        }
+    }
+
+    /// Everything this server can serve, its own catalog and its replicas.
+    fn catalog_entries(&mut self) -> Vec<CatalogEntry> {
+        let mut entries = self.replication.entries();
+        for (file_name, file_path_t) in &self.file_list {
+            let entry = self.replication.catalog_entry(file_name, file_path_t, || {
+                let result = match self.server_type {
+                    messages::high_level_messages::ServerType::Media => {
+                        read_media(file_name, file_path_t, &self.transport.config.media_dir)
+                    }
+                    _ => read_file(file_name, file_path_t, &self.transport.config.text_dir),
+                };
+                result.ok().as_ref().and_then(CatalogEntry::of)
+            });
+            // unreadable files are not offered, the peers could not fetch them either
+            entries.extend(entry);
+        }
+        entries
+    }
+
+    /// Sends every peer a digest of the catalog.
+    pub fn replicate(&mut self) {
+        let peers = self.replication.peers();
+        self.replicate_to(&peers);
+    }
+
+    /// Starts a round of [`ContentServer::replicate`] every `REPLICATION_INTERVAL`.
+    pub fn replicate_if_due(&mut self) {
+        if self.replication.round_due(Instant::now()) {
+            self.replicate();
+        }
+    }
+
+    fn replicate_to(&mut self, peers: &BTreeSet<NodeId>) {
+        if peers.is_empty() {
+            return;
+        }
+        let entries = self.catalog_entries();
+        if entries.is_empty() {
+            return;
+        }
+        for &peer in peers {
+            let request = ClientMessage::SendMessage {
+                recipient_id: peer,
+                content: ControlMessage::Digest(entries.clone()).encode(),
+            };
+            self.transport.send_request_to_server(request, peer);
+        }
+    }
+
+    /// Answers a request for a file or media: redirects the client to the server
+    /// holding it, sends the replica held for a peer, or sends it from the catalog.
+    fn answer_file_request(&mut self, file_name: String, media: bool, client_id: NodeId) {
+        if let Some(node_id) = self.redirect_for(&file_name, client_id) {
+            self.transport.metrics.increment("redirects");
+            let reply = ControlReply::Redirect { file_name, node_id };
+            self.transport.send_control_reply(&reply, client_id);
+            return;
+        }
+        if !self.file_list.contains_key(&file_name) {
+            if let Some(server_message) = self.replication.get(&file_name).cloned() {
+                self.transport
+                    .send_message_to_client(&server_message, client_id);
+                return;
+            }
+        }
+        self.transport
+            .send_from_catalog(&self.file_list, file_name, media, client_id);
     }
 }
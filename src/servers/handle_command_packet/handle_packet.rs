@@ -1,54 +1,156 @@
-use crate::servers::communication_server::CommunicationServer;
-use crate::servers::content_server::ContentServer;
+use crate::servers::ack::AckKind;
+use crate::servers::capture::Direction;
+use crate::servers::commands::ServerEvent;
+use crate::servers::fec::is_parity;
+use crate::servers::reassembly::Eviction;
+use crate::servers::topology::MAX_PATHS;
+use crate::servers::transport::{ControllerEvent, Received, Transport};
 use colored::Colorize;
-use log::error;
-use messages::server_commands::{CommunicationServerEvent, ContentServerEvent};
+use log::{error, warn};
+use messages::high_level_messages::Message;
+use std::time::Instant;
 use wg_2024::network::{NodeId, SourceRoutingHeader};
 use wg_2024::packet::{
-    Ack, FloodRequest, FloodResponse, Fragment, Nack, NackType, NodeType, Packet,
+    Ack, FloodRequest, FloodResponse, Fragment, Nack, NackType, NodeType, Packet, PacketType,
 };
 
-/// Implementation for the `CommunicationServer`, handling network packet operations.
-impl CommunicationServer {
-    pub fn handle_packet(&mut self, packet: Packet) {
+/// Upper bound on the number of fragments of an incoming message.
+const MAX_MESSAGE_FRAGMENTS: u64 = 1 << 16;
+
+/// Checks the fields the servers index into, so a malformed packet cannot panic them.
+fn validate_packet(packet: &Packet) -> Result<(), &'static str> {
+    let header = &packet.routing_header;
+    match &packet.pack_type {
+        PacketType::FloodRequest(_) => return Ok(()),
+        PacketType::FloodResponse(response) if response.path_trace.is_empty() => {
+            return Err("empty path trace");
+        }
+        _ => {}
+    }
+    if header.hops.is_empty() {
+        return Err("empty route");
+    }
+    if header.hop_index >= header.hops.len() {
+        return Err("hop index out of range");
+    }
+    match &packet.pack_type {
+        PacketType::MsgFragment(fragment) => validate_fragment(fragment),
+        _ => Ok(()),
+    }
+}
+
+fn validate_fragment(fragment: &Fragment) -> Result<(), &'static str> {
+    if usize::from(fragment.length) > fragment.data.len() {
+        Err("fragment length exceeds its data")
+    } else if fragment.total_n_fragments == 0 || fragment.total_n_fragments > MAX_MESSAGE_FRAGMENTS
+    {
+        Err("invalid number of fragments")
+    } else if fragment.fragment_index >= fragment.total_n_fragments {
+        Err("fragment index out of range")
+    } else {
+        Ok(())
+    }
+}
+
+/// Route back to the source from the hops already travelled, never out of bounds.
+fn reversed_route(header: &SourceRoutingHeader) -> SourceRoutingHeader {
+    let travelled = header.hop_index.saturating_add(1).min(header.hops.len());
+    let mut hops = header.hops[..travelled].to_vec();
+    hops.reverse();
+    SourceRoutingHeader::with_first_hop(hops)
+}
+
+impl<E: ControllerEvent> Transport<E> {
+    /// Processes a packet from the network, returning what the server has to act on.
+    pub(crate) fn receive(&mut self, packet: Packet) -> Option<Received> {
+        self.capture.record(Direction::Incoming, &packet);
+        self.metrics.packet_received(&packet.pack_type);
+        if let Err(reason) = validate_packet(&packet) {
+            self.drop_malformed(packet, reason);
+            return None;
+        }
         match packet.pack_type {
-            wg_2024::packet::PacketType::MsgFragment(ref fragment) => {
-                self.process_message_fragment(&packet, fragment);
-            }
+            wg_2024::packet::PacketType::MsgFragment(ref fragment) => self
+                .process_message_fragment(&packet, fragment)
+                .map(Received::Message),
             wg_2024::packet::PacketType::Ack(ack) => {
-                self.packet_cache
-                    .take_packet((packet.session_id, ack.fragment_index));
+                let hops = &packet.routing_header.hops;
+                match AckKind::decode(ack.fragment_index) {
+                    AckKind::Single(fragment_index) => {
+                        self.handle_ack(packet.session_id, fragment_index, hops);
+                    }
+                    AckKind::Cumulative(next) => {
+                        if let Some(&peer_id) = hops.first() {
+                            self.acks.enable(peer_id);
+                        }
+                        let next = next.min(MAX_MESSAGE_FRAGMENTS);
+                        for fragment_index in self.acks.newly_acked(packet.session_id, next) {
+                            self.handle_ack(packet.session_id, fragment_index, hops);
+                        }
+                    }
+                }
+                None
             }
             wg_2024::packet::PacketType::Nack(nack) => {
-                self.handle_nack(&nack, packet.session_id, packet.routing_header.hops[0]);
+                let &source_id = packet.routing_header.hops.first()?;
+                self.handle_nack(&nack, packet.session_id, source_id)
+                    .then_some(Received::Reflood)
             }
             wg_2024::packet::PacketType::FloodRequest(request) => {
                 let response = self.get_flood_response(request, packet.session_id);
                 self.send_packet(response, None);
+                None
             }
             wg_2024::packet::PacketType::FloodResponse(response) => {
-                self.router.handle_flood_response(&response);
+                self.learn_routes(&response);
+                Some(Received::PathTrace(response.path_trace))
             }
         }
     }
 
-    /// Handles the processing of a message fragment.
-    fn process_message_fragment(&mut self, packet: &Packet, fragment: &Fragment) {
+    /// Adds a path trace to the topology and the router, without contacting anyone.
+    pub(crate) fn learn_routes(&mut self, response: &FloodResponse) {
+        self.topology.add_path_trace(&response.path_trace);
+        self.router.handle_flood_response(response);
+    }
+
+    /// Sends a flood request to every neighbour without waiting for the responses.
+    pub(crate) fn send_flood_requests(&mut self) {
+        self.metrics.increment("floods_initiated");
+        self.topology.clear();
+        let requests = self.router.get_flood_requests(self.packet_send.len());
+        let neighbours = self.packet_send.keys().copied().collect::<Vec<_>>();
+        for (neighbour_id, request) in neighbours.into_iter().zip(requests) {
+            self.send_packet(request, Some(neighbour_id));
+        }
+    }
+
+    /// Handles the processing of a message fragment, returning its message once complete.
+    fn process_message_fragment(
+        &mut self,
+        packet: &Packet,
+        fragment: &Fragment,
+    ) -> Option<Message> {
+        let &source_id = packet.routing_header.hops.first()?;
         if self.check_packet(packet, Some(fragment.fragment_index)) {
-            if let Some(message) = self.message_factory.received_fragment(
-                fragment.clone(),
+            if !self.duplicate_filter.first_seen(
+                source_id,
                 packet.session_id,
-                packet.routing_header.hops[0],
+                fragment.fragment_index,
             ) {
-                self.handle_message(message);
-            } else {
-                error!(
-                    "{} [CommunicationServer {}]: Error processing message fragment",
-                    "✗".red(),
-                    self.id
-                );
+                // already processed, the previous ack may have been lost
+                self.metrics.increment("duplicate_fragments");
+                self.send_ack(fragment.fragment_index, packet);
+                return None;
             }
             self.send_ack(fragment.fragment_index, packet);
+            let message = self.reassemble(source_id, packet.session_id, fragment.clone())?;
+            self.tracer.request_assembled(
+                packet.session_id,
+                fragment.fragment_index,
+                &packet.routing_header.hops,
+            );
+            Some(message)
         } else {
             let mut rev = packet.clone().routing_header.hops;
             rev.reverse();
@@ -61,85 +163,192 @@ impl CommunicationServer {
                 },
             );
             self.send_packet(nack, None);
+            None
         }
     }
 
-    pub fn handle_nack(&mut self, nack: &Nack, session_id: u64, source_id: NodeId) {
+    /// Handles a nack, returning whether the network should be flooded again.
+    fn handle_nack(&mut self, nack: &Nack, session_id: u64, source_id: NodeId) -> bool {
+        self.metrics.nack_received(&nack.nack_type);
+        self.route_stats.nacked(session_id, nack.fragment_index);
+        self.tracer
+            .nack_received(session_id, nack.fragment_index, &nack.nack_type);
         match nack.nack_type {
             NackType::ErrorInRouting(crashed_id) => {
                 error!(
-                    "{} [CommunicationServer {}]: error_in_routing({})",
+                    "{} [{} {}]: error_in_routing({})",
                     "✗".red(),
+                    E::SERVER,
                     self.id,
                     crashed_id
                 );
                 let () = self.router.drone_crashed(crashed_id);
-                self.resend_for_nack(session_id, nack.fragment_index, crashed_id);
+                self.topology.remove_node(crashed_id);
+                self.route_stats.forget_node(crashed_id);
+                self.resend_for_nack(session_id, nack.fragment_index, crashed_id)
             }
             NackType::DestinationIsDrone => {
                 error!(
-                    "{} [CommunicationServer {}]: Destination is a drone",
+                    "{} [{} {}]: Destination is a drone",
                     "✗".red(),
+                    E::SERVER,
                     self.id
                 );
-                self.send_controller(CommunicationServerEvent::DestinationIsDrone(self.id));
+                self.send_controller(E::destination_is_drone(self.id));
+                false
             }
             NackType::UnexpectedRecipient(id) => {
                 error!(
-                    "{} [CommunicationServer {}]: Packet dropped or unexpected recipient",
+                    "{} [{} {}]: Packet dropped or unexpected recipient",
                     "✗".red(),
+                    E::SERVER,
                     self.id
                 );
-                self.resend_for_nack(session_id, nack.fragment_index, id);
+                self.resend_for_nack(session_id, nack.fragment_index, id)
             }
             NackType::Dropped => {
-                error!(
-                    "{} [CommunicationServer {}]: Packet dropped",
-                    "✗".red(),
-                    self.id
-                );
-                self.resend_for_nack(session_id, nack.fragment_index, source_id);
+                error!("{} [{} {}]: Packet dropped", "✗".red(), E::SERVER, self.id);
+                self.send_windows.lost(session_id);
+                self.resend_for_nack(session_id, nack.fragment_index, source_id)
             }
         }
     }
 
     /// Resends a packet after receiving a nack, adjusting routing if necessary.
-    fn resend_for_nack(&mut self, session_id: u64, fragment_index: u64, nack_src: NodeId) {
+    ///
+    /// Returns whether the packet was retransmitted often enough to flood again.
+    fn resend_for_nack(&mut self, session_id: u64, fragment_index: u64, nack_src: NodeId) -> bool {
+        if is_parity(fragment_index) {
+            // parity fragments are never retransmitted, the data fragments are
+            self.metrics.increment("parity_lost");
+            return false;
+        }
         println!("[Server {}] Marked dropped {nack_src}", self.id);
         let Some((packet, freq)) = self.packet_cache.get_value((session_id, fragment_index)) else {
             println!("[Server {}] error extracting from cache ({session_id}, {fragment_index}) nack_src: {nack_src}", self.id);
-            self.send_controller(CommunicationServerEvent::ErrorPacketCache(
-                session_id,
-                fragment_index,
-            ));
-            return;
+            self.send_controller(E::error_packet_cache(session_id, fragment_index));
+            return false;
         };
         self.router.dropped_fragment(nack_src);
+        if self
+            .config
+            .max_retransmissions
+            .is_some_and(|max| freq > max.into())
+        {
+            warn!(
+                "{} [ {} {} ]: Giving up on fragment {fragment_index} of session {session_id}.",
+                "!!!".yellow(),
+                E::SERVER,
+                self.id
+            );
+            self.uncache(session_id, fragment_index);
+            self.tracer.abandoned(session_id);
+            self.metrics.increment("retransmissions_abandoned");
+            return false;
+        }
         let Some(destination) = packet.routing_header.destination() else {
-            return;
+            return false;
         };
-        let Ok(new_header) = self.router.get_source_routing_header(destination) else {
-            self.send_controller(CommunicationServerEvent::UnreachableNode(destination));
-            return;
+        let Some(route) = self.routes_to(destination).into_iter().next() else {
+            self.send_controller(E::unreachable_node(destination));
+            return false;
         };
+        let new_header = SourceRoutingHeader::with_first_hop(route);
 
         let new_packet = Packet {
             routing_header: new_header,
             ..packet
         };
+        self.metrics.increment("retransmissions");
+        self.tracer
+            .retransmitted(session_id, fragment_index, &new_packet.routing_header.hops);
         self.send_packet(new_packet, None);
+        freq > self.config.reflood_threshold.into()
+    }
 
-        if freq > 100 {
-            self.flood_network();
+    /// Routes to `destination`, best first: the drone-disjoint routes known from
+    /// flooding ranked by their statistics, or the router's route if there are none.
+    pub(crate) fn routes_to(&mut self, destination: NodeId) -> Vec<Vec<NodeId>> {
+        let routes = self.route_stats.ranked(self.topology.disjoint_paths(
+            self.id,
+            destination,
+            MAX_PATHS,
+        ));
+        if !routes.is_empty() {
+            return routes;
         }
+        self.router
+            .get_source_routing_header(destination)
+            .map(|header| vec![header.hops])
+            .unwrap_or_default()
+    }
+
+    /// Buffers `fragment` and assembles its message once every fragment has arrived.
+    fn reassemble(
+        &mut self,
+        source_id: NodeId,
+        session_id: u64,
+        fragment: Fragment,
+    ) -> Option<Message> {
+        let now = Instant::now();
+        let mut evicted = self.reassembly.expire(now);
+        let fragments = self
+            .reassembly
+            .insert(source_id, session_id, fragment, now, &mut evicted);
+        self.report_evictions(evicted);
+        fragments?
+            .into_iter()
+            .filter_map(|fragment| {
+                self.message_factory
+                    .received_fragment(fragment, session_id, source_id)
+            })
+            .last()
+    }
+
+    /// Evicts the incomplete messages past their deadline.
+    pub fn expire_reassembly(&mut self) {
+        let evicted = self.reassembly.expire(Instant::now());
+        self.report_evictions(evicted);
+    }
+
+    fn report_evictions(&mut self, evicted: Vec<Eviction>) {
+        for eviction in evicted {
+            // the sender may retransmit the message, its fragments are not duplicates
+            self.duplicate_filter
+                .forget(eviction.source_id, eviction.session_id);
+            warn!(
+                "{} [{} {}]: Evicted incomplete message (source: {}, session: {}): {}",
+                "!!!".yellow(),
+                E::SERVER,
+                self.id,
+                eviction.source_id,
+                eviction.session_id,
+                eviction.reason
+            );
+            self.metrics.increment("reassembly_evictions");
+            self.send_server_event(ServerEvent::SessionEvicted(eviction));
+        }
+    }
+
+    /// Drops a packet that cannot be processed safely, reporting it to the controller.
+    fn drop_malformed(&self, packet: Packet, reason: &str) {
+        error!(
+            "{} [{} {}]: Dropped malformed packet (session: {}): {reason}",
+            "✗".red(),
+            E::SERVER,
+            self.id,
+            packet.session_id
+        );
+        self.metrics.increment("malformed_packets");
+        self.send_server_event(ServerEvent::MalformedPacket(packet, reason.to_string()));
     }
 
     /// Checks if the packet's routing is correct for this server.
     fn check_packet(&self, packet: &Packet, fragment_index: Option<u64>) -> bool {
         let hop_index = packet.routing_header.hop_index;
-        if self.id != packet.routing_header.hops[hop_index] {
+        if packet.routing_header.hops.get(hop_index) != Some(&self.id) {
             let nack_packet = Packet {
-                routing_header: packet.routing_header.get_reversed(),
+                routing_header: reversed_route(&packet.routing_header),
                 pack_type: wg_2024::packet::PacketType::Nack(Nack {
                     fragment_index: fragment_index.unwrap_or(0),
                     nack_type: NackType::UnexpectedRecipient(self.id),
@@ -158,7 +367,7 @@ impl CommunicationServer {
         path_trace.push((self.id, NodeType::Server));
         let mut hops = path_trace.iter().map(|(id, _)| *id).collect::<Vec<u8>>();
         hops.reverse();
-        if hops.last().copied().unwrap() != flood_request.initiator_id {
+        if hops.last() != Some(&flood_request.initiator_id) {
             hops.push(flood_request.initiator_id);
         }
 
@@ -173,175 +382,109 @@ impl CommunicationServer {
         }
     }
 
-    /// Sends an acknowledgment packet back to the sender.
-    fn send_ack(&self, fragment_index: u64, packet: &Packet) {
-        let mut rev = packet.clone().routing_header.hops;
-        rev.reverse();
-        let ack_packet = Packet {
-            routing_header: SourceRoutingHeader::with_first_hop(rev),
-            session_id: packet.session_id,
-            pack_type: wg_2024::packet::PacketType::Ack(Ack { fragment_index }),
-        };
-        self.send_packet(ack_packet, None);
-    }
-}
-
-impl ContentServer {
-    pub fn handle_packet(&mut self, packet: Packet) {
-        match packet.pack_type {
-            wg_2024::packet::PacketType::MsgFragment(ref fragment) => {
-                self.process_message_fragment(&packet, fragment);
-            }
-            wg_2024::packet::PacketType::Ack(ack) => {
-                self.packet_cache
-                    .take_packet((packet.session_id, ack.fragment_index));
-            }
-            wg_2024::packet::PacketType::Nack(nack) => {
-                self.handle_nack(&nack, packet.session_id, packet.routing_header.hops[0]);
-            }
-            wg_2024::packet::PacketType::FloodRequest(request) => {
-                let response = self.get_flood_response(request, packet.session_id);
-                self.send_packet(response, None);
-            }
-            wg_2024::packet::PacketType::FloodResponse(response) => {
-                self.router.handle_flood_response(&response);
-            }
+    /// Processes the acknowledgement of one fragment the server sent.
+    fn handle_ack(&mut self, session_id: u64, fragment_index: u64, hops: &[NodeId]) {
+        self.tracer.ack_received(session_id, fragment_index, hops);
+        self.route_stats.acked(session_id, fragment_index);
+        for released in self.send_windows.acked(session_id, fragment_index) {
+            self.send_packet(released, None);
         }
+        self.uncache(session_id, fragment_index);
     }
 
-    /// Handles the processing of a message fragment.
-    fn process_message_fragment(&mut self, packet: &Packet, fragment: &Fragment) {
-        if self.check_packet(packet, Some(fragment.fragment_index)) {
-            self.send_ack(fragment.fragment_index, packet);
-            if let Some(message) = self.message_factory.received_fragment(
-                fragment.clone(),
-                packet.session_id,
-                packet.routing_header.hops[0],
-            ) {
-                self.handle_message(message);
-            }
-        } else {
-            let mut rev = packet.clone().routing_header.hops;
-            rev.reverse();
-            let nack = Packet::new_nack(
-                SourceRoutingHeader::with_first_hop(rev),
-                packet.session_id,
-                Nack {
-                    fragment_index: fragment.fragment_index,
-                    nack_type: NackType::UnexpectedRecipient(self.id),
-                },
-            );
-            self.send_packet(nack, None);
+    /// Removes a fragment from the packet cache, if it is still there, and from its
+    /// send window, so a fragment given up on does not hold the rest of its message.
+    fn uncache(&mut self, session_id: u64, fragment_index: u64) {
+        if self
+            .packet_cache
+            .take_packet((session_id, fragment_index))
+            .is_some()
+        {
+            self.pending.remove(session_id, fragment_index);
+            self.metrics.decrement_gauge("cache_size");
         }
-    }
-
-    pub fn handle_nack(&mut self, nack: &Nack, session_id: u64, source_id: NodeId) {
-        match nack.nack_type {
-            NackType::ErrorInRouting(crashed_id) => {
-                error!(
-                    "{} [ContentServer {}]: error_in_routing({})",
-                    "✗".red(),
-                    self.id,
-                    crashed_id
-                );
-                let () = self.router.drone_crashed(crashed_id);
-                self.resend_for_nack(session_id, nack.fragment_index, crashed_id);
-            }
-            NackType::DestinationIsDrone => {
-                error!(
-                    "{} [ContentServer {}]: Destination is a drone",
-                    "✗".red(),
-                    self.id
-                );
-                self.send_controller(ContentServerEvent::DestinationIsDrone(self.id));
-            }
-            NackType::UnexpectedRecipient(id) => {
-                error!(
-                    "{} [ContentServer {}]: Packet dropped or unexpected recipient",
-                    "✗".red(),
-                    self.id
-                );
-                self.resend_for_nack(session_id, nack.fragment_index, id);
-            }
-            NackType::Dropped => {
-                error!("{} [ContentServer {}]: Packet dropped", "✗".red(), self.id);
-                self.resend_for_nack(session_id, nack.fragment_index, source_id);
-            }
+        for released in self.send_windows.abandon(session_id, fragment_index) {
+            self.send_packet(released, None);
         }
     }
 
-    /// Resends a packet after receiving a nack, adjusting routing if necessary.
-    fn resend_for_nack(&mut self, session_id: u64, fragment_index: u64, nack_src: NodeId) {
-        println!("[Server {}] Marked dropped {nack_src}", self.id);
-        let Some((packet, freq)) = self.packet_cache.get_value((session_id, fragment_index)) else {
-            println!("[Server {}] error extracting from cache ({session_id}, {fragment_index}) nack_src: {nack_src}", self.id);
-            self.send_controller(ContentServerEvent::ErrorPacketCache(
-                session_id,
-                fragment_index,
-            ));
-            return;
-        };
-        self.router.dropped_fragment(nack_src);
-        let Some(destination) = packet.routing_header.destination() else {
-            return;
-        };
-        let Ok(new_header) = self.router.get_source_routing_header(destination) else {
-            self.send_controller(ContentServerEvent::UnreachableNode(destination));
-            self.send_packet(packet, None);
+    /// Forgets the least recently active send window if there are too many, dropping
+    /// the fragments it never sent: its sent ones stay cached for their nacks.
+    pub(crate) fn evict_idle_window(&mut self) {
+        let Some((session_id, unsent)) = self.send_windows.evict_idle() else {
             return;
         };
-        let new_packet = Packet {
-            routing_header: new_header,
-            ..packet
-        };
-        self.send_packet(new_packet, None);
-        if freq > 100 {
-            self.flood_network();
+        warn!(
+            "{} [ {} {} ]: Send window of session {} evicted, {} fragments never sent",
+            "!!!".yellow(),
+            E::SERVER,
+            self.id,
+            session_id,
+            unsent.len()
+        );
+        self.metrics.increment("window_evictions");
+        self.metrics.add("unsent_fragments", unsent.len() as u64);
+        if !unsent.is_empty() {
+            // the message can no longer complete
+            self.tracer.abandoned(session_id);
+        }
+        for packet in unsent {
+            self.uncache(session_id, packet.get_fragment_index());
         }
     }
 
-    /// Checks if the packet's routing is correct for this server.
-    fn check_packet(&self, packet: &Packet, fragment_index: Option<u64>) -> bool {
-        let hop_index = packet.routing_header.hop_index;
-        if self.id != packet.routing_header.hops[hop_index] {
-            let nack_packet = Packet {
-                routing_header: packet.routing_header.get_reversed(),
-                pack_type: wg_2024::packet::PacketType::Nack(Nack {
-                    fragment_index: fragment_index.unwrap_or(0),
-                    nack_type: NackType::UnexpectedRecipient(self.id),
-                }),
-                ..*packet
+    /// Drops the oldest cached fragments until the cache fits in its budget.
+    ///
+    /// Fragments in flight are kept, so their nacks can still be answered: only the ones
+    /// waiting for room in their window are dropped, and never sent.
+    pub(crate) fn enforce_cache_budget(&mut self) {
+        let Some(budget) = self.config.cache_budget else {
+            return;
+        };
+        while self.pending.len() > budget {
+            let Some((session_id, fragment_index)) = self
+                .pending
+                .keys()
+                .find(|&(session, index)| !self.send_windows.is_in_flight(session, index))
+            else {
+                return;
             };
-            self.send_packet(nack_packet, None);
-            return false;
+            self.uncache(session_id, fragment_index);
+            self.tracer.abandoned(session_id);
+            self.metrics.increment("cache_evictions");
         }
-        true
     }
 
-    /// Generates a response to a flood request.
-    fn get_flood_response(&self, flood_request: FloodRequest, session_id: u64) -> Packet {
-        let mut path_trace = flood_request.path_trace;
-        path_trace.push((self.id, NodeType::Server));
-        let mut hops = path_trace.iter().map(|(id, _)| *id).collect::<Vec<u8>>();
-        hops.reverse();
-        if hops.last().copied().unwrap() != flood_request.initiator_id {
-            hops.push(flood_request.initiator_id);
-        }
-        let flood_response = FloodResponse {
-            flood_id: flood_request.flood_id,
-            path_trace,
-        };
-        Packet {
-            routing_header: SourceRoutingHeader::with_first_hop(hops),
-            session_id,
-            pack_type: wg_2024::packet::PacketType::FloodResponse(flood_response),
+    /// Sends the cumulative acks that waited long enough for more fragments.
+    pub fn flush_due_acks(&self) {
+        for ack in self.acks.due(Instant::now()) {
+            self.send_packet(ack, None);
         }
     }
 
     /// Sends an acknowledgment packet back to the sender.
+    ///
+    /// Peers that support cumulative acks get them instead, batched.
     fn send_ack(&self, fragment_index: u64, packet: &Packet) {
         let mut rev = packet.clone().routing_header.hops;
         rev.reverse();
+        if let (Some(&peer_id), PacketType::MsgFragment(fragment)) =
+            (packet.routing_header.hops.first(), &packet.pack_type)
+        {
+            if self.acks.supports(peer_id) {
+                let ack_route = SourceRoutingHeader::with_first_hop(rev);
+                if let Some(ack_packet) = self.acks.received(
+                    peer_id,
+                    packet.session_id,
+                    fragment_index,
+                    fragment.total_n_fragments,
+                    ack_route,
+                ) {
+                    self.send_packet(ack_packet, None);
+                }
+                return;
+            }
+        }
         let ack_packet = Packet {
             routing_header: SourceRoutingHeader::with_first_hop(rev),
             session_id: packet.session_id,
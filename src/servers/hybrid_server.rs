@@ -0,0 +1,107 @@
+use crate::servers::catalog::default_catalog;
+use crate::servers::communication_server::CommunicationServer;
+use crate::servers::config::ServerConfig;
+use crossbeam_channel::{Receiver, Sender};
+use messages::high_level_messages::ServerType;
+use messages::server_commands::{CommunicationServerCommand, CommunicationServerEvent};
+use std::collections::HashMap;
+use wg_2024::network::NodeId;
+use wg_2024::packet::Packet;
+
+/// A single node serving any mix of text, media and chat.
+///
+/// It is a [`CommunicationServer`] whose [`Roles`] also enable the content requests, so
+/// small topologies do not spend a node id on each role.
+pub type HybridServer = CommunicationServer;
+
+/// The roles a server takes on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Roles {
+    /// Answers `GetFile` from the text catalog.
+    pub text: bool,
+    /// Answers `GetMedia` from the media catalog.
+    pub media: bool,
+    /// Registers clients and relays their messages.
+    pub chat: bool,
+}
+
+impl Roles {
+    pub const CHAT: Self = Self {
+        text: false,
+        media: false,
+        chat: true,
+    };
+
+    pub const ALL: Self = Self {
+        text: true,
+        media: true,
+        chat: true,
+    };
+
+    /// What `GetServerType` answers, since it can only name one role.
+    ///
+    /// Chat comes first: a client has to know a server is a chat server to register,
+    /// while it can find files on any server with `GetFilesList`.
+    #[must_use]
+    pub fn server_type(self) -> ServerType {
+        if self.chat || !(self.text || self.media) {
+            ServerType::Chat
+        } else if self.text {
+            ServerType::Text
+        } else {
+            ServerType::Media
+        }
+    }
+
+    /// Every enabled role, as in `ServerType`.
+    #[must_use]
+    pub fn server_types(self) -> Vec<ServerType> {
+        let mut server_types = Vec::new();
+        if self.text {
+            server_types.push(ServerType::Text);
+        }
+        if self.media {
+            server_types.push(ServerType::Media);
+        }
+        if self.chat {
+            server_types.push(ServerType::Chat);
+        }
+        server_types
+    }
+
+    /// The catalogs of the enabled content roles, merged.
+    #[must_use]
+    pub fn catalog(self) -> HashMap<String, String> {
+        self.server_types()
+            .iter()
+            .flat_map(default_catalog)
+            .collect()
+    }
+}
+
+impl CommunicationServer {
+    /// Builds a server taking on every role in `roles`.
+    #[must_use]
+    pub fn hybrid(
+        id: NodeId,
+        packet_recv: Receiver<Packet>,
+        packet_send: HashMap<NodeId, Sender<Packet>>,
+        controller_send: Sender<CommunicationServerEvent>,
+        controller_recv: Receiver<CommunicationServerCommand>,
+        roles: Roles,
+        config: ServerConfig,
+    ) -> Self {
+        let mut server = Self::new(
+            id,
+            packet_recv,
+            packet_send,
+            controller_send,
+            controller_recv,
+            config,
+        );
+        server.server_type = roles.server_type();
+        server.file_list = roles.catalog();
+        server.roles = roles;
+        server
+    }
+}
@@ -0,0 +1,152 @@
+use messages::high_level_messages::{ClientMessage, ServerMessage};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use wg_2024::network::NodeId;
+use wg_2024::packet::{NackType, PacketType};
+
+/// Counters and gauges describing a server's behaviour.
+///
+/// Recording only needs `&self` so it can happen on the send path, which borrows the
+/// server immutably.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    counters: RefCell<BTreeMap<String, u64>>,
+    gauges: RefCell<BTreeMap<String, u64>>,
+}
+
+/// Point-in-time copy of a server's metrics, sent to the controller.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    pub node_id: NodeId,
+    pub counters: BTreeMap<String, u64>,
+    pub gauges: BTreeMap<String, u64>,
+}
+
+impl Metrics {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn increment(&self, name: &str) {
+        self.add(name, 1);
+    }
+
+    pub fn add(&self, name: &str, value: u64) {
+        *self
+            .counters
+            .borrow_mut()
+            .entry(name.to_string())
+            .or_default() += value;
+    }
+
+    pub fn set_gauge(&self, name: &str, value: u64) {
+        self.gauges.borrow_mut().insert(name.to_string(), value);
+    }
+
+    pub fn increment_gauge(&self, name: &str) {
+        *self
+            .gauges
+            .borrow_mut()
+            .entry(name.to_string())
+            .or_default() += 1;
+    }
+
+    pub fn decrement_gauge(&self, name: &str) {
+        let mut gauges = self.gauges.borrow_mut();
+        let gauge = gauges.entry(name.to_string()).or_default();
+        *gauge = gauge.saturating_sub(1);
+    }
+
+    #[must_use]
+    pub fn counter(&self, name: &str) -> u64 {
+        self.counters.borrow().get(name).copied().unwrap_or(0)
+    }
+
+    #[must_use]
+    pub fn gauge(&self, name: &str) -> u64 {
+        self.gauges.borrow().get(name).copied().unwrap_or(0)
+    }
+
+    pub fn packet_received(&self, pack_type: &PacketType) {
+        self.increment(&format!("packets_received.{}", packet_kind(pack_type)));
+    }
+
+    pub fn packet_sent(&self, pack_type: &PacketType) {
+        self.increment(&format!("packets_sent.{}", packet_kind(pack_type)));
+    }
+
+    pub fn nack_received(&self, nack_type: &NackType) {
+        self.increment(&format!("nacks_received.{}", nack_kind(nack_type)));
+    }
+
+    pub fn client_message_handled(&self, message: &ClientMessage) {
+        self.increment(&format!(
+            "messages_handled.{}",
+            client_message_kind(message)
+        ));
+    }
+
+    pub fn server_message_sent(&self, message: &ServerMessage) {
+        self.increment(&format!("messages_sent.{}", server_message_kind(message)));
+    }
+
+    #[must_use]
+    pub fn snapshot(&self, node_id: NodeId) -> MetricsSnapshot {
+        MetricsSnapshot {
+            node_id,
+            counters: self.counters.borrow().clone(),
+            gauges: self.gauges.borrow().clone(),
+        }
+    }
+}
+
+#[must_use]
+pub fn packet_kind(pack_type: &PacketType) -> &'static str {
+    match pack_type {
+        PacketType::MsgFragment(_) => "msg_fragment",
+        PacketType::Ack(_) => "ack",
+        PacketType::Nack(_) => "nack",
+        PacketType::FloodRequest(_) => "flood_request",
+        PacketType::FloodResponse(_) => "flood_response",
+    }
+}
+
+#[must_use]
+pub fn nack_kind(nack_type: &NackType) -> &'static str {
+    match nack_type {
+        NackType::ErrorInRouting(_) => "error_in_routing",
+        NackType::DestinationIsDrone => "destination_is_drone",
+        NackType::UnexpectedRecipient(_) => "unexpected_recipient",
+        NackType::Dropped => "dropped",
+    }
+}
+
+#[must_use]
+pub fn client_message_kind(message: &ClientMessage) -> &'static str {
+    match message {
+        ClientMessage::GetServerType => "get_server_type",
+        ClientMessage::RegisterToChat => "register_to_chat",
+        ClientMessage::Logout => "logout",
+        ClientMessage::GetClientList => "get_client_list",
+        ClientMessage::SendMessage { .. } => "send_message",
+        ClientMessage::GetFilesList => "get_files_list",
+        ClientMessage::GetFile(_) => "get_file",
+        ClientMessage::GetMedia(_) => "get_media",
+    }
+}
+
+#[must_use]
+pub fn server_message_kind(message: &ServerMessage) -> &'static str {
+    match message {
+        ServerMessage::ServerType(_) => "server_type",
+        ServerMessage::SuccessfulRegistration => "successful_registration",
+        ServerMessage::SuccessfullLogOut => "successful_logout",
+        ServerMessage::ClientList(_) => "client_list",
+        ServerMessage::MessageReceived { .. } => "message_received",
+        ServerMessage::UnreachableClient(_) => "unreachable_client",
+        ServerMessage::FilesList(_) => "files_list",
+        ServerMessage::File { .. } => "file",
+        ServerMessage::Media(..) => "media",
+    }
+}
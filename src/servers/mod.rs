@@ -1,4 +1,30 @@
+pub mod ack;
+pub mod capabilities;
+pub mod capture;
+pub mod catalog;
+pub mod commands;
 pub mod communication_server;
+pub mod compression;
+pub mod config;
 pub mod content_server;
+pub mod control_message;
+pub mod dedup;
+pub mod directory;
+pub mod fec;
+pub mod federation;
 mod handle_command_packet;
+pub mod hybrid_server;
+pub mod metrics;
+pub mod pending;
+pub mod rate_limit;
+pub mod reassembly;
+pub mod replication;
+pub mod route_stats;
+pub mod scheduler;
 mod send_functions;
+pub mod server;
+pub mod snapshot;
+pub mod topology;
+pub mod trace;
+pub mod transport;
+pub mod window;
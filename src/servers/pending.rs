@@ -0,0 +1,50 @@
+use std::collections::BTreeMap;
+use wg_2024::packet::Packet;
+
+/// The fragments held in the packet cache until they are acknowledged.
+///
+/// `PacketCache` cannot be listed, so the server keeps this copy next to it to save
+/// the fragments in snapshots and put them back in the cache on restore.
+#[derive(Debug, Default)]
+pub struct PendingFragments {
+    packets: BTreeMap<(u64, u64), Packet>,
+}
+
+impl PendingFragments {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, packet: &Packet) {
+        self.packets.insert(
+            (packet.session_id, packet.get_fragment_index()),
+            packet.clone(),
+        );
+    }
+
+    pub fn remove(&mut self, session_id: u64, fragment_index: u64) {
+        self.packets.remove(&(session_id, fragment_index));
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.packets.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.packets.is_empty()
+    }
+
+    /// Session and fragment index of the cached fragments, oldest first, assuming
+    /// session ids grow over time.
+    pub fn keys(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.packets.keys().copied()
+    }
+
+    /// The cached fragments, by session and fragment index.
+    pub fn packets(&self) -> impl Iterator<Item = &Packet> {
+        self.packets.values()
+    }
+}
@@ -0,0 +1,39 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use wg_2024::network::NodeId;
+
+/// Length of the windows requests are counted in.
+pub const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Counts the requests of each client, to refuse the ones over the configured rate.
+///
+/// Requests are counted in fixed windows of [`RATE_WINDOW`] starting at the first
+/// request of each client.
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    windows: HashMap<NodeId, (Instant, u32)>,
+}
+
+impl RateLimiter {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Counts a request of `client`, returning whether it is within `limit` per window.
+    ///
+    /// Without a limit every request is allowed and nothing is counted.
+    pub fn allow(&mut self, client: NodeId, limit: Option<u32>, now: Instant) -> bool {
+        let Some(limit) = limit else {
+            self.windows.clear();
+            return true;
+        };
+        let (start, count) = self.windows.entry(client).or_insert((now, 0));
+        if now.saturating_duration_since(*start) >= RATE_WINDOW {
+            *start = now;
+            *count = 0;
+        }
+        *count = count.saturating_add(1);
+        *count <= limit
+    }
+}
@@ -0,0 +1,243 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::time::{Duration, Instant};
+use wg_2024::network::NodeId;
+use wg_2024::packet::Fragment;
+
+/// Bytes carried by a full fragment.
+const FRAGMENT_SIZE: u64 = 128;
+
+/// How often expired sessions are looked for.
+pub const REASSEMBLY_SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Bounds on the incoming messages a server is reassembling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReassemblyLimits {
+    /// Time a message has to be completed, from its first fragment.
+    pub deadline: Duration,
+    /// Incomplete messages a single source may have at once.
+    pub max_sessions_per_source: usize,
+    /// Largest message accepted, in bytes.
+    pub max_message_size: u64,
+}
+
+impl Default for ReassemblyLimits {
+    fn default() -> Self {
+        Self {
+            deadline: Duration::from_secs(30),
+            max_sessions_per_source: 16,
+            max_message_size: 1 << 20,
+        }
+    }
+}
+
+/// Why an incomplete message was discarded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionReason {
+    /// Not completed before the deadline.
+    Expired,
+    /// The oldest message of a source that started one too many.
+    TooManySessions,
+    /// Larger than the maximum message size.
+    TooLarge,
+    /// Its fragments disagree on the number of fragments.
+    Inconsistent,
+}
+
+impl fmt::Display for EvictionReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            Self::Expired => "reassembly deadline expired",
+            Self::TooManySessions => "too many concurrent sessions",
+            Self::TooLarge => "message too large",
+            Self::Inconsistent => "inconsistent number of fragments",
+        };
+        f.write_str(reason)
+    }
+}
+
+/// An incomplete message that was discarded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Eviction {
+    pub source_id: NodeId,
+    pub session_id: u64,
+    pub reason: EvictionReason,
+}
+
+/// How far along an incomplete incoming message is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    pub source_id: NodeId,
+    pub session_id: u64,
+    pub received: u64,
+    pub total_n_fragments: u64,
+    /// Time since its first fragment arrived.
+    pub age: Duration,
+}
+
+#[derive(Debug)]
+struct Session {
+    started: Instant,
+    total_n_fragments: u64,
+    fragments: BTreeMap<u64, Fragment>,
+}
+
+/// Buffers the fragments of incoming messages until they are complete.
+///
+/// The message factory keeps incomplete messages forever, so fragments are only handed
+/// to it once every fragment of their message has arrived.
+#[derive(Debug)]
+pub struct Reassembly {
+    limits: ReassemblyLimits,
+    sessions: HashMap<(NodeId, u64), Session>,
+    next_sweep: Option<Instant>,
+}
+
+impl Default for Reassembly {
+    fn default() -> Self {
+        Self::new(ReassemblyLimits::default())
+    }
+}
+
+impl Reassembly {
+    #[must_use]
+    pub fn new(limits: ReassemblyLimits) -> Self {
+        Self {
+            limits,
+            sessions: HashMap::new(),
+            next_sweep: None,
+        }
+    }
+
+    #[must_use]
+    pub fn limits(&self) -> ReassemblyLimits {
+        self.limits
+    }
+
+    /// Applies new limits to the messages buffered from now on.
+    pub fn set_limits(&mut self, limits: ReassemblyLimits) {
+        self.limits = limits;
+    }
+
+    /// Buffers `fragment`, returning every fragment of its message once it is complete.
+    ///
+    /// The evictions caused by the fragment are appended to `evicted`.
+    pub fn insert(
+        &mut self,
+        source_id: NodeId,
+        session_id: u64,
+        fragment: Fragment,
+        now: Instant,
+        evicted: &mut Vec<Eviction>,
+    ) -> Option<Vec<Fragment>> {
+        let key = (source_id, session_id);
+        let evict = |reason| Eviction {
+            source_id,
+            session_id,
+            reason,
+        };
+        if fragment.total_n_fragments.saturating_mul(FRAGMENT_SIZE) > self.limits.max_message_size {
+            self.sessions.remove(&key);
+            evicted.push(evict(EvictionReason::TooLarge));
+            return None;
+        }
+        if let Some(session) = self.sessions.get(&key) {
+            if session.total_n_fragments != fragment.total_n_fragments {
+                self.sessions.remove(&key);
+                evicted.push(evict(EvictionReason::Inconsistent));
+                return None;
+            }
+        } else {
+            evicted.extend(self.make_room(source_id));
+        }
+
+        let session = self.sessions.entry(key).or_insert_with(|| Session {
+            started: now,
+            total_n_fragments: fragment.total_n_fragments,
+            fragments: BTreeMap::new(),
+        });
+        session.fragments.insert(fragment.fragment_index, fragment);
+        if session.fragments.len() as u64 == session.total_n_fragments {
+            let session = self.sessions.remove(&key)?;
+            return Some(session.fragments.into_values().collect());
+        }
+        None
+    }
+
+    /// Evicts the oldest session of `source_id` if it cannot start another one.
+    fn make_room(&mut self, source_id: NodeId) -> Option<Eviction> {
+        let open = self
+            .sessions
+            .keys()
+            .filter(|(source, _)| *source == source_id)
+            .count();
+        if open < self.limits.max_sessions_per_source {
+            return None;
+        }
+        let (&key, _) = self
+            .sessions
+            .iter()
+            .filter(|((source, _), _)| *source == source_id)
+            .min_by_key(|(_, session)| session.started)?;
+        self.sessions.remove(&key);
+        Some(Eviction {
+            source_id,
+            session_id: key.1,
+            reason: EvictionReason::TooManySessions,
+        })
+    }
+
+    /// Evicts the sessions past their deadline.
+    ///
+    /// Sessions are only looked at once every [`REASSEMBLY_SWEEP_INTERVAL`], so this is
+    /// cheap enough to call on every packet.
+    pub fn expire(&mut self, now: Instant) -> Vec<Eviction> {
+        if self.next_sweep.is_some_and(|next| now < next) {
+            return Vec::new();
+        }
+        self.next_sweep = Some(now + REASSEMBLY_SWEEP_INTERVAL);
+        let deadline = self.limits.deadline;
+        let mut evicted = Vec::new();
+        self.sessions.retain(|&(source_id, session_id), session| {
+            let alive = now.saturating_duration_since(session.started) < deadline;
+            if !alive {
+                evicted.push(Eviction {
+                    source_id,
+                    session_id,
+                    reason: EvictionReason::Expired,
+                });
+            }
+            alive
+        });
+        evicted
+    }
+
+    /// Number of incomplete messages.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    /// The incomplete messages, by source and session.
+    #[must_use]
+    pub fn progress(&self, now: Instant) -> Vec<Progress> {
+        let mut progress = self
+            .sessions
+            .iter()
+            .map(|(&(source_id, session_id), session)| Progress {
+                source_id,
+                session_id,
+                received: session.fragments.len() as u64,
+                total_n_fragments: session.total_n_fragments,
+                age: now.saturating_duration_since(session.started),
+            })
+            .collect::<Vec<_>>();
+        progress.sort_unstable_by_key(|progress| (progress.source_id, progress.session_id));
+        progress
+    }
+}
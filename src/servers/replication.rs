@@ -0,0 +1,272 @@
+use messages::high_level_messages::{ClientMessage, ServerMessage};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::time::{Duration, Instant};
+use wg_2024::network::NodeId;
+
+/// Servers each file is kept on, counting the one it comes from.
+pub const DEFAULT_REPLICATION_FACTOR: usize = 2;
+
+/// Time between two rounds of catalog digests.
+pub const REPLICATION_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Replicas a server keeps by default.
+pub const DEFAULT_MAX_REPLICAS: usize = 256;
+
+/// One file or media of a catalog digest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CatalogEntry {
+    pub name: String,
+    /// Whether it is fetched with `GetMedia` rather than `GetFile`.
+    pub media: bool,
+    /// Hash of the content, so replicas that changed are fetched again.
+    pub digest: u64,
+}
+
+impl CatalogEntry {
+    /// The entry describing a `File` or `Media` message.
+    #[must_use]
+    pub fn of(message: &ServerMessage) -> Option<Self> {
+        let (name, media, content) = match message {
+            ServerMessage::File {
+                file_id, content, ..
+            } => (file_id, false, content),
+            ServerMessage::Media(name, content) => (name, true, content),
+            _ => return None,
+        };
+        Some(Self {
+            name: name.clone(),
+            media,
+            digest: digest(content),
+        })
+    }
+
+    /// The request fetching the entry from a server that holds it.
+    #[must_use]
+    pub fn request(&self) -> ClientMessage {
+        if self.media {
+            ClientMessage::GetMedia(self.name.clone())
+        } else {
+            ClientMessage::GetFile(self.name.clone())
+        }
+    }
+
+    #[must_use]
+    pub fn encode(&self) -> String {
+        let kind = if self.media { "media" } else { "text" };
+        format!("{},{kind},{:016x}", self.name, self.digest)
+    }
+
+    #[must_use]
+    pub fn parse(encoded: &str) -> Option<Self> {
+        let mut fields = encoded.rsplitn(3, ',');
+        let digest = u64::from_str_radix(fields.next()?, 16).ok()?;
+        let media = match fields.next()? {
+            "media" => true,
+            "text" => false,
+            _ => return None,
+        };
+        let name = fields.next().filter(|name| !name.is_empty())?;
+        Some(Self {
+            name: name.to_string(),
+            media,
+            digest,
+        })
+    }
+}
+
+/// FNV-1a hash of a content, stable across builds unlike `DefaultHasher`.
+#[must_use]
+pub fn digest(content: &str) -> u64 {
+    content.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// The `factor` servers among `servers` that should hold the file `name`.
+///
+/// Rendezvous hashing: every server ranks the candidates the same way, and when one
+/// disappears only its files move.
+#[must_use]
+pub fn holders(name: &str, servers: &BTreeSet<NodeId>, factor: usize) -> Vec<NodeId> {
+    let mut ranked = servers
+        .iter()
+        .map(|&id| (digest(&format!("{name}@{id}")), id))
+        .collect::<Vec<_>>();
+    ranked.sort_unstable_by(|a, b| b.cmp(a));
+    ranked.into_iter().take(factor).map(|(_, id)| id).collect()
+}
+
+/// Replicas held by a content server, and the peers it replicates with.
+///
+/// Peers are the servers seen in flood path traces that answered `GetServerType` with
+/// the type of this server, so text and media stay apart. Every `interval` the server
+/// sends each peer a digest of its catalog, and a peer fetches the entries it should hold but misses with the
+/// usual `GetFile` and `GetMedia` requests, so files survive the loss of a server.
+///
+/// Only the replies to those fetches are stored, and only `max_replicas` of them: the
+/// oldest replicas are dropped first.
+#[derive(Debug)]
+pub struct Replication {
+    pub factor: usize,
+    /// Time between two rounds, [`REPLICATION_INTERVAL`] by default.
+    pub interval: Duration,
+    /// Replicas kept, [`DEFAULT_MAX_REPLICAS`] by default; also bounds the fetches
+    /// waiting for their reply.
+    pub max_replicas: usize,
+    /// Servers whose type is known, to whether they replicate with this one.
+    known: BTreeMap<NodeId, bool>,
+    /// Servers seen since the last flood.
+    present: BTreeSet<NodeId>,
+    /// Entries of the own catalog by file name and path, `None` if unreadable.
+    catalog: HashMap<(String, String), Option<CatalogEntry>>,
+    replicas: HashMap<String, (CatalogEntry, ServerMessage)>,
+    /// Names of the replicas, oldest first.
+    stored: VecDeque<String>,
+    /// Fetches sent and not answered yet, by peer and file name.
+    fetching: BTreeSet<(NodeId, String)>,
+    last_round: Option<Instant>,
+}
+
+impl Default for Replication {
+    fn default() -> Self {
+        Self::new(DEFAULT_REPLICATION_FACTOR)
+    }
+}
+
+impl Replication {
+    #[must_use]
+    pub fn new(factor: usize) -> Self {
+        Self {
+            factor,
+            interval: REPLICATION_INTERVAL,
+            max_replicas: DEFAULT_MAX_REPLICAS,
+            known: BTreeMap::new(),
+            present: BTreeSet::new(),
+            catalog: HashMap::new(),
+            replicas: HashMap::new(),
+            stored: VecDeque::new(),
+            fetching: BTreeSet::new(),
+            last_round: None,
+        }
+    }
+
+    /// Forgets which servers are present, before a new flood.
+    pub fn clear_present(&mut self) {
+        self.present.clear();
+    }
+
+    /// Records a server seen in a path trace, returning whether its type must be asked.
+    pub fn discovered(&mut self, id: NodeId) -> bool {
+        self.present.insert(id);
+        !self.known.contains_key(&id)
+    }
+
+    /// Records whether a server of known type replicates with this one, returning
+    /// whether it became a peer.
+    pub fn identified(&mut self, id: NodeId, peer: bool) -> bool {
+        self.known.insert(id, peer) != Some(peer) && peer
+    }
+
+    /// Servers of the same type present since the last flood.
+    #[must_use]
+    pub fn peers(&self) -> BTreeSet<NodeId> {
+        self.present
+            .iter()
+            .copied()
+            .filter(|id| self.known.get(id) == Some(&true))
+            .collect()
+    }
+
+    /// Records a fetch of `name` from `peer`, returning whether it may be sent: the
+    /// fetches waiting for their reply are bounded too.
+    pub fn fetch(&mut self, peer: NodeId, name: &str) -> bool {
+        if self.fetching.len() >= self.max_replicas {
+            return false;
+        }
+        self.fetching.insert((peer, name.to_string()));
+        true
+    }
+
+    /// Stores a `File` or `Media` sent by `source_id`, returning whether it answered a
+    /// fetch; anything else is dropped.
+    pub fn store(&mut self, source_id: NodeId, message: ServerMessage) -> bool {
+        let Some(entry) = CatalogEntry::of(&message) else {
+            return false;
+        };
+        if !self.fetching.remove(&(source_id, entry.name.clone())) {
+            return false;
+        }
+        let name = entry.name.clone();
+        if self
+            .replicas
+            .insert(name.clone(), (entry, message))
+            .is_some()
+        {
+            self.stored.retain(|stored| *stored != name);
+        }
+        self.stored.push_back(name);
+        true
+    }
+
+    /// Drops the oldest replicas over `max_replicas`, returning how many.
+    pub fn evict(&mut self) -> usize {
+        let mut evicted = 0;
+        while self.replicas.len() > self.max_replicas {
+            let Some(name) = self.stored.pop_front() else {
+                break;
+            };
+            self.replicas.remove(&name);
+            evicted += 1;
+        }
+        evicted
+    }
+
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&ServerMessage> {
+        self.replicas.get(name).map(|(_, message)| message)
+    }
+
+    /// The entry of a replica, digested when it was stored.
+    #[must_use]
+    pub fn entry(&self, name: &str) -> Option<&CatalogEntry> {
+        self.replicas.get(name).map(|(entry, _)| entry)
+    }
+
+    #[must_use]
+    pub fn entries(&self) -> Vec<CatalogEntry> {
+        self.replicas
+            .values()
+            .map(|(entry, _)| entry.clone())
+            .collect()
+    }
+
+    /// The entry of a file of the own catalog, `read` only the first time it is asked
+    /// for: reading re-encodes media, far too slow to repeat every round. A file changed
+    /// on disk keeps its first digest.
+    pub fn catalog_entry(
+        &mut self,
+        name: &str,
+        path: &str,
+        read: impl FnOnce() -> Option<CatalogEntry>,
+    ) -> Option<CatalogEntry> {
+        self.catalog
+            .entry((name.to_string(), path.to_string()))
+            .or_insert_with(read)
+            .clone()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.replicas.keys()
+    }
+
+    /// Whether a round of digests is due, starting it if so.
+    pub fn round_due(&mut self, now: Instant) -> bool {
+        let due = self
+            .last_round
+            .is_none_or(|last| now.saturating_duration_since(last) >= self.interval);
+        if due {
+            self.last_round = Some(now);
+        }
+        due
+    }
+}
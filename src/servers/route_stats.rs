@@ -0,0 +1,159 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+use wg_2024::network::NodeId;
+
+/// Fragments waiting for an ack that are remembered before the oldest is forgotten.
+const MAX_IN_FLIGHT: usize = 65_536;
+
+/// Round-trip time assumed per hop when no path was measured yet.
+const UNMEASURED_HOP_RTT: Duration = Duration::from_millis(1);
+
+/// Round-trip times are never taken as shorter than this, so a clock too coarse to
+/// measure them cannot make a path look free.
+const MIN_RTT: Duration = Duration::from_micros(1);
+
+/// Paths whose cost exceeds the best one by this factor are not striped across.
+const MAX_COST_RATIO: f64 = 4.0;
+
+/// Delivery statistics of one path.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PathStats {
+    pub sent: u64,
+    pub acked: u64,
+    pub nacked: u64,
+    /// Smoothed round-trip time, from fragment sent to ack received.
+    pub srtt: Option<Duration>,
+}
+
+impl PathStats {
+    /// Estimated fraction of fragments delivered, optimistic for unused paths.
+    #[must_use]
+    pub fn delivery_ratio(&self) -> f64 {
+        (self.acked + 1) as f64 / (self.acked + self.nacked + 1) as f64
+    }
+
+    fn record_rtt(&mut self, rtt: Duration) {
+        // same smoothing as TCP, srtt = 7/8 srtt + 1/8 rtt
+        self.srtt = Some(self.srtt.map_or(rtt, |srtt| (srtt * 7 + rtt) / 8));
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    in_flight: BTreeMap<(u64, u64), (Vec<NodeId>, Instant)>, //(session, fragment) to (path, sent at)
+    paths: HashMap<Vec<NodeId>, PathStats>,
+}
+
+/// Tracks, per path, how many fragments were acked or nacked and how long acks took.
+///
+/// Route choice uses it to prefer paths that deliver reliably and fast: the cost of a
+/// path is its round-trip time divided by its delivery ratio, which is the expected
+/// time to get a fragment through. Recording only needs `&self`.
+#[derive(Debug, Default)]
+pub struct RouteStats {
+    inner: RefCell<Inner>,
+}
+
+impl RouteStats {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a fragment sent over `path`.
+    pub fn sent(&self, session_id: u64, fragment_index: u64, path: &[NodeId]) {
+        let mut inner = self.inner.borrow_mut();
+        inner.paths.entry(path.to_vec()).or_default().sent += 1;
+        inner.in_flight.insert(
+            (session_id, fragment_index),
+            (path.to_vec(), Instant::now()),
+        );
+        if inner.in_flight.len() > MAX_IN_FLIGHT {
+            inner.in_flight.pop_first();
+        }
+    }
+
+    /// Records the ack of a fragment, measuring the round-trip time of its path.
+    pub fn acked(&self, session_id: u64, fragment_index: u64) {
+        let inner = &mut *self.inner.borrow_mut();
+        let Some((path, sent_at)) = inner.in_flight.remove(&(session_id, fragment_index)) else {
+            return;
+        };
+        let stats = inner.paths.entry(path).or_default();
+        stats.acked += 1;
+        stats.record_rtt(sent_at.elapsed());
+    }
+
+    /// Records the nack of a fragment as a loss on its path.
+    pub fn nacked(&self, session_id: u64, fragment_index: u64) {
+        let inner = &mut *self.inner.borrow_mut();
+        if let Some((path, _)) = inner.in_flight.remove(&(session_id, fragment_index)) {
+            inner.paths.entry(path).or_default().nacked += 1;
+        }
+    }
+
+    /// Forgets the paths through a node that crashed.
+    pub fn forget_node(&self, id: NodeId) {
+        self.inner
+            .borrow_mut()
+            .paths
+            .retain(|path, _| !path.contains(&id));
+    }
+
+    #[must_use]
+    pub fn path(&self, path: &[NodeId]) -> PathStats {
+        self.inner
+            .borrow()
+            .paths
+            .get(path)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Expected time to get a fragment through `path`, in seconds.
+    ///
+    /// A path that was never measured is assumed to take `hop_rtt` per hop.
+    #[must_use]
+    pub fn cost(&self, path: &[NodeId], hop_rtt: Duration) -> f64 {
+        let stats = self.path(path);
+        let rtt = stats.srtt.unwrap_or(hop_rtt * hops(path)).max(MIN_RTT);
+        rtt.as_secs_f64() / stats.delivery_ratio()
+    }
+
+    /// `paths` from cheapest to most expensive, without the ones far worse than the best.
+    #[must_use]
+    pub fn ranked(&self, paths: Vec<Vec<NodeId>>) -> Vec<Vec<NodeId>> {
+        // unmeasured paths are compared with the fastest measured one, hop for hop,
+        // rounding up so they never undercut it by a rounding error
+        let hop_rtt = paths
+            .iter()
+            .filter_map(|path| Some(per_hop(self.path(path).srtt?, hops(path))))
+            .min()
+            .unwrap_or(UNMEASURED_HOP_RTT);
+        let mut ranked = paths
+            .into_iter()
+            .map(|path| (self.cost(&path, hop_rtt), path))
+            .collect::<Vec<_>>();
+        // equally expensive paths are ordered by their nodes, so the choice is stable
+        ranked.sort_by(|(a, a_path), (b, b_path)| a.total_cmp(b).then_with(|| a_path.cmp(b_path)));
+        let Some(&(best, _)) = ranked.first() else {
+            return Vec::new();
+        };
+        ranked
+            .into_iter()
+            .filter(|(cost, _)| *cost <= best * MAX_COST_RATIO)
+            .map(|(_, path)| path)
+            .collect()
+    }
+}
+
+/// `rtt` split evenly across `hops`, rounded up to the nanosecond.
+fn per_hop(rtt: Duration, hops: u32) -> Duration {
+    let nanos = rtt.as_nanos().div_ceil(u128::from(hops.max(1)));
+    Duration::from_nanos(u64::try_from(nanos).unwrap_or(u64::MAX))
+}
+
+fn hops(path: &[NodeId]) -> u32 {
+    u32::try_from(path.len().saturating_sub(1)).unwrap_or(u32::MAX)
+}
@@ -0,0 +1,260 @@
+use crossbeam_channel::{Sender, TrySendError};
+use messages::high_level_messages::ServerMessage;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::time::Duration;
+use wg_2024::network::NodeId;
+use wg_2024::packet::{Packet, PacketType};
+
+/// Packets of one class that may wait for a single neighbour.
+pub const DEFAULT_QUEUE_CAPACITY: usize = 16_384;
+
+/// How often queued packets are retried when the server is otherwise idle.
+pub const OUTBOUND_FLUSH_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Bulk sessions remembered before the oldest is forgotten.
+const MAX_BULK_SESSIONS: usize = 4096;
+
+/// Traffic classes, sent in order of priority.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum TrafficClass {
+    /// Acks, nacks and flooding.
+    Control,
+    /// Chat and short replies.
+    Interactive,
+    /// Files and media.
+    Bulk,
+}
+
+impl TrafficClass {
+    pub const ALL: [Self; 3] = [Self::Control, Self::Interactive, Self::Bulk];
+
+    /// Class of a packet whose session was not marked as bulk.
+    #[must_use]
+    pub fn of_packet(pack_type: &PacketType) -> Self {
+        match pack_type {
+            PacketType::MsgFragment(_) => Self::Interactive,
+            PacketType::Ack(_)
+            | PacketType::Nack(_)
+            | PacketType::FloodRequest(_)
+            | PacketType::FloodResponse(_) => Self::Control,
+        }
+    }
+
+    /// Class of the fragments of a message sent to a client.
+    #[must_use]
+    pub fn of_message(message: &ServerMessage) -> Self {
+        match message {
+            ServerMessage::File { .. } | ServerMessage::Media(..) => Self::Bulk,
+            _ => Self::Interactive,
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Packets of one class waiting for one neighbour, one queue per session.
+#[derive(Debug, Default)]
+struct ClassQueue {
+    sessions: VecDeque<(u64, VecDeque<Packet>)>,
+    len: usize,
+}
+
+impl ClassQueue {
+    fn push(&mut self, packet: Packet) {
+        self.len += 1;
+        match self
+            .sessions
+            .iter_mut()
+            .find(|(session_id, _)| *session_id == packet.session_id)
+        {
+            Some((_, packets)) => packets.push_back(packet),
+            None => self
+                .sessions
+                .push_back((packet.session_id, VecDeque::from([packet]))),
+        }
+    }
+
+    /// Sends packets until the channel is full, taking one from each session in turn.
+    ///
+    /// Returns `false` if the channel is full.
+    fn flush(&mut self, sender: &Sender<Packet>, failed: &mut Vec<Packet>) -> bool {
+        while let Some((session_id, mut packets)) = self.sessions.pop_front() {
+            let Some(packet) = packets.pop_front() else {
+                continue;
+            };
+            match sender.try_send(packet) {
+                Ok(()) => self.len -= 1,
+                Err(TrySendError::Full(packet)) => {
+                    packets.push_front(packet);
+                    self.sessions.push_front((session_id, packets));
+                    return false;
+                }
+                Err(TrySendError::Disconnected(packet)) => {
+                    self.len -= 1;
+                    failed.push(packet);
+                }
+            }
+            if !packets.is_empty() {
+                self.sessions.push_back((session_id, packets));
+            }
+        }
+        true
+    }
+
+    fn drain(&mut self) -> impl Iterator<Item = Packet> + '_ {
+        self.len = 0;
+        self.sessions.drain(..).flat_map(|(_, packets)| packets)
+    }
+}
+
+/// Packets waiting for one neighbour, one queue per traffic class.
+#[derive(Debug, Default)]
+struct NeighbourQueue {
+    classes: [ClassQueue; 3],
+}
+
+impl NeighbourQueue {
+    /// Sends the packets of each class only once the classes before it are empty.
+    fn flush(&mut self, sender: &Sender<Packet>, failed: &mut Vec<Packet>) {
+        for class in &mut self.classes {
+            if !class.flush(sender, failed) {
+                return;
+            }
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.classes.iter().map(|class| class.len).sum()
+    }
+}
+
+/// Queues outgoing packets per neighbour and sends them without ever blocking.
+///
+/// Each neighbour has a bounded queue per [`TrafficClass`], drained with `try_send`
+/// whenever the server flushes. A class is only sent once the classes before it are
+/// empty, and sessions of the same class take turns, so acks and chat are never stuck
+/// behind a large media response. Fragments are interactive unless their session was
+/// marked as bulk with [`OutboundScheduler::set_bulk`]. Queuing only needs `&self`.
+#[derive(Debug)]
+pub struct OutboundScheduler {
+    capacity: usize,
+    queues: RefCell<BTreeMap<NodeId, NeighbourQueue>>,
+    bulk_sessions: RefCell<(HashSet<u64>, VecDeque<u64>)>,
+}
+
+impl Default for OutboundScheduler {
+    fn default() -> Self {
+        Self::new(DEFAULT_QUEUE_CAPACITY)
+    }
+}
+
+impl OutboundScheduler {
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            queues: RefCell::new(BTreeMap::new()),
+            bulk_sessions: RefCell::default(),
+        }
+    }
+
+    /// Changes the number of packets of each class queued per neighbour; packets
+    /// already queued over it are still sent.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity.max(1);
+    }
+
+    /// Marks the fragments of `session_id` as bulk traffic, retransmissions included.
+    pub fn set_bulk(&self, session_id: u64) {
+        let (sessions, order) = &mut *self.bulk_sessions.borrow_mut();
+        if !sessions.insert(session_id) {
+            return;
+        }
+        order.push_back(session_id);
+        if order.len() > MAX_BULK_SESSIONS {
+            if let Some(oldest) = order.pop_front() {
+                sessions.remove(&oldest);
+            }
+        }
+    }
+
+    /// Class `packet` is queued in.
+    #[must_use]
+    pub fn class_of(&self, packet: &Packet) -> TrafficClass {
+        match TrafficClass::of_packet(&packet.pack_type) {
+            TrafficClass::Interactive
+                if self.bulk_sessions.borrow().0.contains(&packet.session_id) =>
+            {
+                TrafficClass::Bulk
+            }
+            class => class,
+        }
+    }
+
+    /// Queues `packet` for `neighbour_id`.
+    ///
+    /// # Errors
+    /// Gives the packet back if the queue of its class is full for the neighbour.
+    pub fn enqueue(&self, neighbour_id: NodeId, packet: Packet) -> Result<(), Box<Packet>> {
+        let class = self.class_of(&packet);
+        let mut queues = self.queues.borrow_mut();
+        let queue = &mut queues.entry(neighbour_id).or_default().classes[class.index()];
+        if queue.len >= self.capacity {
+            return Err(Box::new(packet));
+        }
+        queue.push(packet);
+        Ok(())
+    }
+
+    /// Sends as many queued packets as the neighbours' channels accept.
+    ///
+    /// Returns the packets whose neighbour has no channel or a disconnected one.
+    pub fn flush(&self, senders: &HashMap<NodeId, Sender<Packet>>) -> Vec<Packet> {
+        let mut failed = Vec::new();
+        self.queues.borrow_mut().retain(|neighbour_id, queue| {
+            match senders.get(neighbour_id) {
+                Some(sender) => queue.flush(sender, &mut failed),
+                None => {
+                    for class in &mut queue.classes {
+                        failed.extend(class.drain());
+                    }
+                }
+            }
+            queue.len() > 0
+        });
+        failed
+    }
+
+    /// Number of packets waiting for `neighbour_id`.
+    #[must_use]
+    pub fn queued_for(&self, neighbour_id: NodeId) -> usize {
+        self.queues
+            .borrow()
+            .get(&neighbour_id)
+            .map_or(0, NeighbourQueue::len)
+    }
+
+    /// Number of packets of `class` waiting for any neighbour.
+    #[must_use]
+    pub fn queued_in(&self, class: TrafficClass) -> usize {
+        self.queues
+            .borrow()
+            .values()
+            .map(|queue| queue.classes[class.index()].len)
+            .sum()
+    }
+
+    /// Number of packets waiting for any neighbour.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.queues.borrow().values().map(NeighbourQueue::len).sum()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
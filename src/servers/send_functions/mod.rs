@@ -1,219 +1,303 @@
-use crate::servers::communication_server::CommunicationServer;
-use crate::servers::content_server::ContentServer;
+use crate::servers::capture::Direction;
+use crate::servers::commands::ServerEvent;
+use crate::servers::compression::{compress_message, escape_message};
+use crate::servers::control_message::{ControlReply, Feature};
+use crate::servers::fec::{with_parity, FEC_MIN_FRAGMENTS};
+use crate::servers::scheduler::TrafficClass;
+use crate::servers::topology::MULTIPATH_MIN_FRAGMENTS;
+use crate::servers::transport::{ControllerEvent, Transport};
 use colored::Colorize;
-use crossbeam_channel::Sender;
 use log::{error, info};
-use messages::server_commands::{CommunicationServerEvent, ContentServerEvent};
-use wg_2024::network::NodeId;
-use wg_2024::packet::Packet;
+use messages::high_level_messages::MessageContent::{FromClient, FromServer};
+use messages::high_level_messages::{ClientMessage, MessageContent, ServerMessage};
+use wg_2024::network::{NodeId, SourceRoutingHeader};
+use wg_2024::packet::{Packet, PacketType};
 
-impl CommunicationServer {
-    pub fn send_packet(&self, msg: Packet, sender: Option<&Sender<Packet>>) {
+impl<E: ControllerEvent> Transport<E> {
+    pub fn send_packet(&self, msg: Packet, neighbour_id: Option<NodeId>) {
+        self.capture.record(Direction::Outgoing, &msg);
+        self.metrics.packet_sent(&msg.pack_type);
         match msg.pack_type {
             wg_2024::packet::PacketType::Ack(_)
             | wg_2024::packet::PacketType::Nack(_)
             | wg_2024::packet::PacketType::FloodResponse(_) => self.send_or_shortcut(msg),
             wg_2024::packet::PacketType::FloodRequest(_) => {
-                let Some(sender) = sender else { return };
-                self.send_to_sender(msg, sender);
+                let Some(neighbour_id) = neighbour_id else {
+                    return;
+                };
+                self.send_to_neighbour_id(msg, neighbour_id);
             }
             wg_2024::packet::PacketType::MsgFragment(_) => {
                 let Some(dest) = msg.routing_header.current_hop() else {
                     error!(
-                        "{} [CommunicationServer {}] error taking next_hop",
+                        "{} [{} {}] error taking next_hop",
                         "✗".red(),
+                        E::SERVER,
                         self.id
                     );
                     return;
                 };
                 info!(
-                    "{} [CommunicationServer {}] sending packet to neighbour {}",
+                    "{} [{} {}] sending packet to neighbour {}",
                     "✓".green(),
+                    E::SERVER,
                     self.id,
                     dest
                 );
+                self.tracer.sent(
+                    msg.session_id,
+                    msg.get_fragment_index(),
+                    &msg.routing_header.hops,
+                );
+                self.route_stats.sent(
+                    msg.session_id,
+                    msg.get_fragment_index(),
+                    &msg.routing_header.hops,
+                );
                 self.send_to_neighbour_id(msg, dest);
             }
         }
     }
 
-    pub fn send_to_sender(&self, msg: Packet, sender: &Sender<Packet>) {
-        info!(
-            "{} [CommunicationServer {}] sending packet",
-            "✓".green(),
-            self.id
+    /// Reports a packet that could not be handed to its neighbour.
+    fn report_send_error(&self, msg: Packet) {
+        error!(
+            "{} [{} {}] error in sending packet (session: {}, fragment: {})",
+            "✗".red(),
+            E::SERVER,
+            self.id,
+            msg.session_id,
+            msg.get_fragment_index()
         );
-        sender
-            .send(msg)
-            .inspect_err(|e| {
-                self.send_controller(CommunicationServerEvent::SendError(e.clone()));
-                error!(
-                    "{} [CommunicationServer {}] error in sending packet (session: {}, fragment: {})",
-                    "✗".red(),
-                    self.id,
-                    e.0.session_id,
-                    e.0.get_fragment_index()
-                );
-            })
-            .ok();
+        self.send_controller(E::send_error(msg));
+    }
+
+    /// Hands queued packets to the neighbours whose channels have room.
+    pub fn flush_outbound(&self) {
+        for msg in self.scheduler.flush(&self.packet_send) {
+            match msg.pack_type {
+                wg_2024::packet::PacketType::Ack(_)
+                | wg_2024::packet::PacketType::Nack(_)
+                | wg_2024::packet::PacketType::FloodResponse(_) => {
+                    self.send_controller(E::shortcut(msg));
+                }
+                _ => self.report_send_error(msg),
+            }
+        }
+        self.metrics
+            .set_gauge("outbound_queue", self.scheduler.len() as u64);
     }
 
     fn send_to_neighbour_id(&self, msg: Packet, neighbour_id: NodeId) {
-        let Some(sender) = self.packet_send.get(&neighbour_id) else {
+        if !self.packet_send.contains_key(&neighbour_id) {
             error!(
-                "{} [CommunicationServer {} ]: Cannot send message, destination {neighbour_id} is unreachable",
+                "{} [ {} {} ]: Cannot send message, destination {neighbour_id} is unreachable",
                 "✗".red(),
+                E::SERVER,
                 self.id,
             );
             return;
-        };
-        self.send_to_sender(msg, sender);
+        }
+        if let Err(msg) = self.scheduler.enqueue(neighbour_id, msg) {
+            self.metrics.increment("outbound_overflow");
+            self.report_send_error(*msg);
+        }
+        self.flush_outbound();
     }
 
     fn send_or_shortcut(&self, msg: Packet) {
         info!(
-            "{} [CommunicationServer {}] sending packet {:?}",
+            "{} [{} {}] sending packet {:?}",
             "✓".green(),
+            E::SERVER,
             self.id,
             msg
         );
-        match self.get_sender(&msg) {
-            Some(sender) => {
-                sender
-                    .send(msg)
-                    .inspect_err(|e| {
-                        self.send_controller(CommunicationServerEvent::ControllerShortcut(
-                            e.0.clone(),
-                        ));
-                    })
-                    .ok();
-            }
-            None => self.send_controller(CommunicationServerEvent::ControllerShortcut(msg)),
+        let Some(neighbour_id) = self.next_hop(&msg) else {
+            self.send_controller(E::shortcut(msg));
+            return;
+        };
+        if let Err(msg) = self.scheduler.enqueue(neighbour_id, msg) {
+            self.metrics.increment("outbound_overflow");
+            self.send_controller(E::shortcut(*msg));
         }
+        self.flush_outbound();
     }
-    fn get_sender(&self, packet: &Packet) -> Option<Sender<Packet>> {
-        Some(
-            self.packet_send
-                .get(
-                    packet
-                        .routing_header
-                        .hops
-                        .get(packet.routing_header.hop_index)?,
-                )?
-                .clone(),
-        )
+    /// Neighbour the packet goes to next, if the server is connected to it.
+    fn next_hop(&self, packet: &Packet) -> Option<NodeId> {
+        let hop = *packet
+            .routing_header
+            .hops
+            .get(packet.routing_header.hop_index)?;
+        self.packet_send.contains_key(&hop).then_some(hop)
     }
 
-    pub fn send_controller(&self, msg: CommunicationServerEvent) {
+    pub fn send_controller(&self, msg: E) {
         self.controller_send
             .send(msg)
             .inspect_err(|e| {
                 error!(
-                    "{} [CommunicationServer {}] error in sending to sim-controller. Message: [{:?}]",
+                    "{} [{} {}] error in sending to sim-controller. Message: [{:?}]",
                     "✗".red(),
+                    E::SERVER,
                     self.id,
                     e.0
                 );
             })
             .ok();
     }
-}
-impl ContentServer {
-    pub fn send_packet(&self, msg: Packet, sender: Option<&Sender<Packet>>) {
-        match msg.pack_type {
-            wg_2024::packet::PacketType::Ack(_)
-            | wg_2024::packet::PacketType::Nack(_)
-            | wg_2024::packet::PacketType::FloodResponse(_) => self.send_or_shortcut(msg),
-            wg_2024::packet::PacketType::FloodRequest(_) => {
-                let Some(sender) = sender else { return };
-                self.send_to_sender(msg, sender);
-            }
-            wg_2024::packet::PacketType::MsgFragment(_) => {
-                let Some(dest) = msg.routing_header.current_hop() else {
-                    error!(
-                        "{} [ContentServer {}] error taking next_hop",
-                        "✗".red(),
-                        self.id
-                    );
-                    return;
-                };
-                info!(
-                    "{} [ContentServer {}] sending packet to neighbour {}",
-                    "✓".green(),
-                    self.id,
-                    dest
-                );
-                self.send_to_neighbour_id(msg, dest);
-            }
-        }
-    }
 
-    pub fn send_to_sender(&self, msg: Packet, sender: &Sender<Packet>) {
-        info!("{} [ContentServer {}] sending packet", "✓".green(), self.id);
-        sender
-            .send(msg)
+    /// Sends an event on the channel attached with `attach_server_channels`, if any.
+    pub fn send_server_event(&self, event: ServerEvent) {
+        let Some(server_event_send) = &self.server_event_send else {
+            return;
+        };
+        server_event_send
+            .send(event)
             .inspect_err(|e| {
-                self.send_controller(ContentServerEvent::SendError(e.clone()));
                 error!(
-                    "{} [ContentServer {}] error in sending packet (session: {}, fragment: {})",
+                    "{} [{} {}] error in sending server event. Event: [{:?}]",
                     "✗".red(),
+                    E::SERVER,
                     self.id,
-                    e.0.session_id,
-                    e.0.get_fragment_index()
+                    e.0
                 );
             })
             .ok();
     }
 
-    fn send_to_neighbour_id(&self, msg: Packet, neighbour_id: NodeId) {
-        let Some(sender) = self.packet_send.get(&neighbour_id) else {
+    pub(crate) fn send_control_reply(&mut self, reply: &ControlReply, destination_id: NodeId) {
+        let server_message = ServerMessage::MessageReceived {
+            sender_id: self.id,
+            content: reply.encode(),
+        };
+        self.send_message_to_client(&server_message, destination_id);
+    }
+
+    pub(crate) fn send_message_to_client(
+        &mut self,
+        server_message: &ServerMessage,
+        destination_id: NodeId,
+    ) {
+        let compressed = self.compressed_for(destination_id, server_message);
+        let server_message = compressed.as_ref().unwrap_or(server_message);
+        let class = TrafficClass::of_message(server_message);
+        if self.send_content(FromServer(server_message.clone()), class, destination_id) {
+            self.metrics.server_message_sent(server_message);
+            // chat content is never logged, it may be end-to-end encrypted
+            info!("Message sent to client {destination_id}");
+        }
+    }
+
+    /// Sends a request to another server, as a client would.
+    pub(crate) fn send_request_to_server(
+        &mut self,
+        request: ClientMessage,
+        destination_id: NodeId,
+    ) {
+        if self.send_content(
+            FromClient(request),
+            TrafficClass::Interactive,
+            destination_id,
+        ) {
+            info!("Request sent to server {destination_id}");
+        }
+    }
+
+    /// Fragments and sends a message, returning whether the destination was reachable.
+    fn send_content(
+        &mut self,
+        content: MessageContent,
+        class: TrafficClass,
+        destination_id: NodeId,
+    ) -> bool {
+        let routes = self.routes_to(destination_id);
+        let Some(route) = routes.first() else {
             error!(
-                "{} [ContentServer {} ]: Cannot send message, destination {neighbour_id} is unreachable",
+                "{} [ {} {} ]: Cannot send message, destination {} is unreachable",
                 "✗".red(),
+                E::SERVER,
                 self.id,
+                destination_id
             );
-            return;
+            return false;
         };
-        self.send_to_sender(msg, sender);
-    }
-
-    fn send_or_shortcut(&self, msg: Packet) {
-        match self.get_sender(&msg) {
-            Some(sender) => {
-                sender
-                    .send(msg)
-                    .inspect_err(|e| {
-                        self.send_controller(ContentServerEvent::ControllerShortcut(e.0.clone()));
-                    })
-                    .ok();
+        let header = SourceRoutingHeader::with_first_hop(route.clone());
+        let loss = 1.0 - self.route_stats.path(route).delivery_ratio();
+        let fragment_packets =
+            self.message_factory
+                .get_message_from_message_content(content, &header, destination_id);
+        if let Some(first) = fragment_packets.first() {
+            if class == TrafficClass::Bulk {
+                self.scheduler.set_bulk(first.session_id);
             }
-            None => self.send_controller(ContentServerEvent::ControllerShortcut(msg)),
+            self.tracer.fragmented(
+                first.session_id,
+                fragment_packets.len() as u64,
+                &header.hops,
+            );
+        }
+        let paths = if fragment_packets.len() >= MULTIPATH_MIN_FRAGMENTS {
+            routes
+        } else {
+            Vec::new()
+        };
+        let session_id = fragment_packets.first().map(|packet| packet.session_id);
+        let mut queued = Vec::with_capacity(fragment_packets.len());
+        for (i, mut fragment_packet) in fragment_packets.into_iter().enumerate() {
+            // stripe large messages across routes sharing no drone
+            if paths.len() > 1 {
+                fragment_packet.routing_header =
+                    SourceRoutingHeader::with_first_hop(paths[i % paths.len()].clone());
+            }
+            if let PacketType::MsgFragment(fragment) = &fragment_packet.pack_type {
+                self.metrics.add("bytes_served", u64::from(fragment.length));
+            }
+            self.packet_cache.insert_packet(&fragment_packet);
+            self.pending.insert(&fragment_packet);
+            self.metrics.increment_gauge("cache_size");
+            queued.push(fragment_packet);
+        }
+        if let Some(block) = self.parity_block(destination_id, queued.len(), loss) {
+            let data_fragments = queued.len();
+            queued = with_parity(queued, block);
+            self.metrics
+                .add("parity_fragments", (queued.len() - data_fragments) as u64);
         }
+        if let Some(session_id) = session_id {
+            // the rest is released by the acks, as the window allows
+            for fragment_packet in self.send_windows.push(session_id, queued) {
+                self.send_packet(fragment_packet, None);
+            }
+            self.evict_idle_window();
+        }
+        self.enforce_cache_budget();
+        true
     }
-    fn get_sender(&self, packet: &Packet) -> Option<Sender<Packet>> {
-        Some(
-            self.packet_send
-                .get(
-                    packet
-                        .routing_header
-                        .hops
-                        .get(packet.routing_header.hop_index)?,
-                )?
-                .clone(),
-        )
+
+    /// `server_message` as sent to a client that enabled compression: compressed if that
+    /// helps, escaped if its content could be taken for a compressed one.
+    fn compressed_for(
+        &self,
+        destination_id: NodeId,
+        server_message: &ServerMessage,
+    ) -> Option<ServerMessage> {
+        if !self.enabled(destination_id, Feature::Deflate) {
+            return None;
+        }
+        let Some(compressed) = compress_message(server_message) else {
+            return escape_message(server_message);
+        };
+        self.metrics.increment("compressed_messages");
+        Some(compressed)
     }
 
-    pub fn send_controller(&self, msg: ContentServerEvent) {
-        self.controller_send
-            .send(msg)
-            .inspect_err(|e| {
-                error!(
-                    "{} [ContentServer {}] error in sending to sim-controller. Message: [{:?}]",
-                    "✗".red(),
-                    self.id,
-                    e.0
-                );
-            })
-            .ok();
+    /// Data fragments per parity fragment in a message of `fragments` fragments, if the
+    /// client enabled forward error correction and the message is large enough.
+    fn parity_block(&self, destination_id: NodeId, fragments: usize, loss: f64) -> Option<u64> {
+        if !self.enabled(destination_id, Feature::Fec) || fragments < FEC_MIN_FRAGMENTS {
+            return None;
+        }
+        self.fec_policy.block_size(loss)
     }
 }
@@ -0,0 +1,277 @@
+use crate::servers::commands::{ServerCommand, ServerEvent};
+use crate::servers::config::ServerConfig;
+use crate::servers::scheduler::OUTBOUND_FLUSH_INTERVAL;
+use crate::servers::snapshot::{Autosnapshot, ServerSnapshot};
+use crate::servers::transport::{ControllerEvent, Received, Transport};
+use colored::Colorize;
+use crossbeam_channel::{select_biased, Receiver, Sender};
+use log::error;
+use messages::high_level_messages::Message;
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use std::thread;
+use std::time::Instant;
+use wg_2024::network::NodeId;
+use wg_2024::packet::{NodeType, Packet};
+
+/// A server on top of a [`Transport`]: what it serves, and how it answers.
+///
+/// The transport does the networking the same way for every server; the provided
+/// methods run a server and answer the [`ServerCommand`]s through the required ones.
+pub trait Server {
+    /// Event reported to the simulation controller.
+    type Event: ControllerEvent;
+    /// Command sent by the simulation controller.
+    type Command;
+
+    fn transport(&self) -> &Transport<Self::Event>;
+    fn transport_mut(&mut self) -> &mut Transport<Self::Event>;
+    fn controller_recv(&self) -> &Receiver<Self::Command>;
+
+    fn handle_command(&mut self, command: Self::Command);
+    fn handle_message(&mut self, message: Message);
+
+    /// Asks the servers of a path trace for their type, if this server looks for peers.
+    fn discover_servers(&mut self, path_trace: &[(NodeId, NodeType)]);
+
+    /// Forgets which peers answered the last flood, before flooding again.
+    fn forget_present_servers(&mut self);
+
+    /// Clients registered to chat.
+    fn clients(&self) -> Vec<NodeId>;
+
+    /// File name to file path, for every file the server serves.
+    fn catalog(&self) -> BTreeMap<String, String>;
+
+    #[must_use]
+    fn snapshot(&self) -> ServerSnapshot;
+
+    /// Restores what the server keeps on top of the transport.
+    fn restore_state(&mut self, snapshot: ServerSnapshot);
+
+    /// Applies what the server uses of a new configuration, once the transport did.
+    fn config_applied(&mut self) {}
+
+    /// Periodic work of the server, between packets.
+    fn on_interval(&mut self) {}
+
+    fn id(&self) -> NodeId {
+        self.transport().id
+    }
+
+    fn run(&mut self) {
+        // a server restored from a snapshot already knows its routes, only its peers
+        if self.transport().topology.path_traces().is_empty() {
+            self.flood_network();
+        } else {
+            self.resume();
+        }
+        loop {
+            self.housekeeping();
+            select_biased! {
+                recv(self.transport().packet_recv) -> packet => {
+                    if let Ok(packet) = packet {
+                        self.handle_packet(packet);
+                    }
+                },
+                recv(self.controller_recv()) -> command => {
+                    if let Ok(command) = command {
+                        self.handle_command(command);
+                    }
+                },
+                recv(self.transport().server_command_recv) -> command => {
+                    if let Ok(command) = command {
+                        self.handle_server_command(command);
+                    }
+                },
+                default(OUTBOUND_FLUSH_INTERVAL) => {},
+            }
+        }
+    }
+
+    /// The periodic work of [`Server::run`]: expiring reassemblies, the server's own
+    /// work, sending the due acks and what neighbours made room for, and snapshots.
+    fn housekeeping(&mut self) {
+        self.transport_mut().expire_reassembly();
+        self.on_interval();
+        self.transport().flush_due_acks();
+        self.transport().flush_outbound();
+        self.autosnapshot_if_due();
+    }
+
+    /// Attaches the channels carrying [`ServerCommand`]s and [`ServerEvent`]s.
+    fn attach_server_channels(
+        &mut self,
+        event_send: Sender<ServerEvent>,
+        command_recv: Receiver<ServerCommand>,
+    ) {
+        self.transport_mut()
+            .attach_server_channels(event_send, command_recv);
+    }
+
+    fn handle_packet(&mut self, packet: Packet) {
+        match self.transport_mut().receive(packet) {
+            Some(Received::Message(message)) => self.handle_message(message),
+            Some(Received::PathTrace(path_trace)) => self.discover_servers(&path_trace),
+            Some(Received::Reflood) => self.flood_network(),
+            None => {}
+        }
+    }
+
+    fn flood_network(&mut self) {
+        self.send_flood_requests();
+        thread::sleep(self.transport().config.flood_wait());
+    }
+
+    /// Sends a flood request to every neighbour without waiting for the responses.
+    fn send_flood_requests(&mut self) {
+        self.forget_present_servers();
+        self.transport_mut().send_flood_requests();
+    }
+
+    /// Picks up where a restored server left off, once running: asks the servers of
+    /// the known path traces for their type and sends the pending fragments again.
+    fn resume(&mut self) {
+        for path_trace in self.transport().topology.path_traces().to_vec() {
+            self.discover_servers(&path_trace);
+        }
+        self.transport_mut().release_windows();
+    }
+
+    /// Replaces the configuration of the running server.
+    fn apply_config(&mut self, config: ServerConfig) {
+        self.transport_mut().apply_config(config);
+        self.config_applied();
+    }
+
+    /// Handles the commands shared by both servers.
+    fn handle_server_command(&mut self, command: ServerCommand) {
+        let id = self.id();
+        let transport = self.transport_mut();
+        match command {
+            ServerCommand::GetMetrics => {
+                let snapshot = transport.metrics.snapshot(id);
+                transport.send_server_event(ServerEvent::Metrics(snapshot));
+            }
+            ServerCommand::ExportTrace(path) => {
+                let result = transport
+                    .tracer
+                    .export(id, &path)
+                    .map(|()| path)
+                    .map_err(|e| e.to_string());
+                transport.send_server_event(ServerEvent::TraceExported(result));
+            }
+            ServerCommand::StartCapture(path) => {
+                let result = transport
+                    .capture
+                    .start(&path)
+                    .map(|()| path)
+                    .map_err(|e| e.to_string());
+                transport.send_server_event(ServerEvent::CaptureStarted(result));
+            }
+            ServerCommand::StopCapture => {
+                let result = transport.capture.stop().map_err(|e| e.to_string());
+                transport.send_server_event(ServerEvent::CaptureStopped(result));
+            }
+            ServerCommand::SaveSnapshot(path) => {
+                let result = self
+                    .save_snapshot(&path)
+                    .map(|()| path)
+                    .map_err(|e| e.to_string());
+                self.transport()
+                    .send_server_event(ServerEvent::SnapshotSaved(result));
+            }
+            ServerCommand::SetAutosnapshot(autosnapshot) => {
+                transport.autosnapshot =
+                    autosnapshot.map(|(path, interval)| Autosnapshot::new(path, interval));
+            }
+            ServerCommand::Reconfigure(changes) => {
+                let result = transport.config.updated(&changes).map(|config| {
+                    self.apply_config(config);
+                    self.transport().config.clone()
+                });
+                self.transport()
+                    .send_server_event(ServerEvent::Reconfigured(result));
+            }
+            ServerCommand::GetNeighbours => {
+                let mut neighbours = transport.packet_send.keys().copied().collect::<Vec<_>>();
+                neighbours.sort_unstable();
+                transport.send_server_event(ServerEvent::Neighbours(neighbours));
+            }
+            ServerCommand::GetTopology => {
+                transport.send_server_event(ServerEvent::Topology(transport.topology.clone()));
+            }
+            ServerCommand::GetClients => {
+                let clients = self.clients();
+                self.transport()
+                    .send_server_event(ServerEvent::Clients(clients));
+            }
+            ServerCommand::GetCatalog => {
+                let catalog = self.catalog();
+                self.transport()
+                    .send_server_event(ServerEvent::Catalog(catalog));
+            }
+            ServerCommand::GetPendingCache => {
+                let packets = transport.pending.packets().cloned().collect();
+                transport.send_server_event(ServerEvent::PendingCache(packets));
+            }
+            ServerCommand::GetReassemblies => {
+                let progress = transport.reassembly.progress(Instant::now());
+                transport.send_server_event(ServerEvent::Reassemblies(progress));
+            }
+        }
+    }
+
+    /// Saves a snapshot of the server to `path`.
+    ///
+    /// # Errors
+    /// Returns the I/O error if the file cannot be written.
+    fn save_snapshot(&self, path: &Path) -> io::Result<()> {
+        self.snapshot().save(path)?;
+        self.transport().metrics.increment("snapshots_saved");
+        Ok(())
+    }
+
+    /// Restores the state of a previous run of this server.
+    ///
+    /// The routes are rebuilt from the saved path traces, so `run` does not flood again,
+    /// and peers are found again by `run`.
+    ///
+    /// # Errors
+    /// Returns an error if the snapshot was taken on another node.
+    fn restore(&mut self, snapshot: ServerSnapshot) -> io::Result<()> {
+        snapshot.check_id(self.id())?;
+        self.transport_mut().restore(&snapshot);
+        self.restore_state(snapshot);
+        Ok(())
+    }
+
+    /// Restores the snapshot saved at `path`.
+    ///
+    /// # Errors
+    /// Returns an error if the snapshot cannot be read or was taken on another node.
+    fn restore_from(&mut self, path: &Path) -> io::Result<()> {
+        self.restore(ServerSnapshot::load(path)?)
+    }
+
+    /// Saves the periodic snapshot, if one is due.
+    fn autosnapshot_if_due(&mut self) {
+        let Some(autosnapshot) = self.transport_mut().autosnapshot.as_mut() else {
+            return;
+        };
+        if !autosnapshot.due(Instant::now()) {
+            return;
+        }
+        let path = autosnapshot.path.clone();
+        if let Err(e) = self.save_snapshot(&path) {
+            error!(
+                "{} [ {} {} ]: Failed to save snapshot to {}: {e}",
+                "✗".red(),
+                Self::Event::SERVER,
+                self.id(),
+                path.display()
+            );
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use crate::servers::control_message::Feature;
+use crate::servers::transport::{ControllerEvent, Transport};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use wg_2024::network::NodeId;
+use wg_2024::packet::{FloodResponse, NodeType, Packet};
+
+/// Version of the snapshot format; snapshots written by another version are refused.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// The state a server keeps across a restart.
+///
+/// Routes are saved as the flood response path traces they were learned from, so a
+/// restored server rebuilds its router without flooding.
+///
+/// What servers learn from each other is not saved: federation peers and their
+/// clients, replication peers, the directory and replicas. Restoring sends nothing;
+/// once running, a restored server asks the servers of its path traces for their type
+/// again, and peers offer their catalogs and replicas at the next replication round.
+///
+/// Pending fragments are sent again once running, from the start of their window, since
+/// it is not known which of them reached their destination.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerSnapshot {
+    pub version: u32,
+    pub id: NodeId,
+    pub registered_clients: Vec<NodeId>,
+    pub public_keys: BTreeMap<NodeId, String>,
+    pub client_features: BTreeMap<NodeId, BTreeSet<Feature>>,
+    /// File name to file path.
+    pub file_list: BTreeMap<String, String>,
+    pub path_traces: Vec<Vec<(NodeId, NodeType)>>,
+    /// Fragments sent and not acknowledged yet.
+    pub pending: Vec<Packet>,
+}
+
+impl ServerSnapshot {
+    /// Writes the snapshot to `path` as bincode, replacing the previous one atomically.
+    ///
+    /// # Errors
+    /// Returns the I/O error if the file cannot be written.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let bytes = bincode::serialize(self).map_err(io::Error::other)?;
+        let partial = path.with_extension("partial");
+        fs::write(&partial, bytes)?;
+        fs::rename(partial, path)
+    }
+
+    /// Reads a snapshot written by [`ServerSnapshot::save`].
+    ///
+    /// # Errors
+    /// Returns an error if the file cannot be read, is malformed or has another version.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let snapshot: Self = bincode::deserialize(&fs::read(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported snapshot version {}", snapshot.version),
+            ));
+        }
+        Ok(snapshot)
+    }
+
+    pub(crate) fn check_id(&self, id: NodeId) -> io::Result<()> {
+        if self.id == id {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("snapshot of node {} restored on node {id}", self.id),
+            ))
+        }
+    }
+}
+
+/// Saves a snapshot to the same file at a regular interval.
+#[derive(Debug, Clone)]
+pub struct Autosnapshot {
+    pub path: PathBuf,
+    pub interval: Duration,
+    last: Instant,
+}
+
+impl Autosnapshot {
+    #[must_use]
+    pub fn new(path: PathBuf, interval: Duration) -> Self {
+        Self {
+            path,
+            interval,
+            last: Instant::now(),
+        }
+    }
+
+    /// Whether a snapshot is due, starting the next interval if it is.
+    pub(crate) fn due(&mut self, now: Instant) -> bool {
+        if now.duration_since(self.last) < self.interval {
+            return false;
+        }
+        self.last = now;
+        true
+    }
+}
+
+impl<E: ControllerEvent> Transport<E> {
+    /// A snapshot of what the transport keeps, for the server to complete.
+    #[must_use]
+    pub(crate) fn snapshot(&self) -> ServerSnapshot {
+        ServerSnapshot {
+            version: SNAPSHOT_VERSION,
+            id: self.id,
+            registered_clients: Vec::new(),
+            public_keys: BTreeMap::new(),
+            client_features: self.client_features.clone().into_iter().collect(),
+            file_list: BTreeMap::new(),
+            path_traces: self.topology.path_traces().to_vec(),
+            pending: self.pending.packets().cloned().collect(),
+        }
+    }
+
+    /// Restores the client features, routes and pending fragments of `snapshot`, and the
+    /// send windows of the pending fragments.
+    pub(crate) fn restore(&mut self, snapshot: &ServerSnapshot) {
+        self.client_features = snapshot.client_features.clone().into_iter().collect();
+        self.topology.clear();
+        for path_trace in &snapshot.path_traces {
+            self.learn_routes(&FloodResponse {
+                flood_id: 0,
+                path_trace: path_trace.clone(),
+            });
+        }
+        let mut sessions = BTreeMap::<u64, Vec<Packet>>::new();
+        for packet in &snapshot.pending {
+            self.packet_cache.insert_packet(packet);
+            self.pending.insert(packet);
+            self.metrics.increment_gauge("cache_size");
+            sessions
+                .entry(packet.session_id)
+                .or_default()
+                .push(packet.clone());
+        }
+        // windows start over, sending nothing until the server runs
+        for (session_id, fragments) in sessions {
+            self.send_windows.queue(session_id, fragments);
+            self.evict_idle_window();
+        }
+    }
+
+    /// Sends the restored fragments that fit in their window; the acks release the rest.
+    pub(crate) fn release_windows(&mut self) {
+        for packet in self.send_windows.release_all() {
+            self.send_packet(packet, None);
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use wg_2024::network::NodeId;
+use wg_2024::packet::NodeType;
+
+/// Largest number of routes a message is striped across.
+pub const MAX_PATHS: usize = 3;
+
+/// Messages with fewer fragments than this are sent over a single route.
+pub const MULTIPATH_MIN_FRAGMENTS: usize = 8;
+
+/// The network as seen by the server, rebuilt from the path traces of flood responses.
+///
+/// The router only hands out one route per destination; this keeps the links around so
+/// large messages can be striped across several routes that share no drone.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Topology {
+    links: BTreeMap<NodeId, BTreeSet<NodeId>>,
+    drones: BTreeSet<NodeId>,
+    /// The path traces the links come from, so the router can be rebuilt from them.
+    path_traces: Vec<Vec<(NodeId, NodeType)>>,
+}
+
+impl Topology {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forgets everything, before a new flood.
+    pub fn clear(&mut self) {
+        self.links.clear();
+        self.drones.clear();
+        self.path_traces.clear();
+    }
+
+    /// Adds the nodes and links of a flood response path trace.
+    pub fn add_path_trace(&mut self, path_trace: &[(NodeId, NodeType)]) {
+        for (id, node_type) in path_trace {
+            if matches!(node_type, NodeType::Drone) {
+                self.drones.insert(*id);
+            }
+        }
+        for pair in path_trace.windows(2) {
+            let (a, b) = (pair[0].0, pair[1].0);
+            if a != b {
+                self.links.entry(a).or_default().insert(b);
+                self.links.entry(b).or_default().insert(a);
+            }
+        }
+        if !self.path_traces.iter().any(|known| known == path_trace) {
+            self.path_traces.push(path_trace.to_vec());
+        }
+    }
+
+    /// The nodes each node is known to be linked to.
+    #[must_use]
+    pub fn links(&self) -> &BTreeMap<NodeId, BTreeSet<NodeId>> {
+        &self.links
+    }
+
+    /// The nodes known to be drones.
+    #[must_use]
+    pub fn drones(&self) -> &BTreeSet<NodeId> {
+        &self.drones
+    }
+
+    /// The path traces still valid, in the order they were added.
+    #[must_use]
+    pub fn path_traces(&self) -> &[Vec<(NodeId, NodeType)>] {
+        &self.path_traces
+    }
+
+    /// Removes a node that crashed or was disconnected, with all its links.
+    pub fn remove_node(&mut self, id: NodeId) {
+        if let Some(neighbours) = self.links.remove(&id) {
+            for neighbour in neighbours {
+                if let Some(links) = self.links.get_mut(&neighbour) {
+                    links.remove(&id);
+                }
+            }
+        }
+        self.drones.remove(&id);
+        self.path_traces
+            .retain(|path_trace| path_trace.iter().all(|&(node, _)| node != id));
+    }
+
+    /// Removes the link between two nodes.
+    pub fn remove_link(&mut self, a: NodeId, b: NodeId) {
+        for (from, to) in [(a, b), (b, a)] {
+            if let Some(links) = self.links.get_mut(&from) {
+                links.remove(&to);
+            }
+        }
+        self.path_traces.retain(|path_trace| {
+            !path_trace
+                .windows(2)
+                .any(|pair| (pair[0].0, pair[1].0) == (a, b) || (pair[0].0, pair[1].0) == (b, a))
+        });
+    }
+
+    /// Up to `max` routes from `from` to `to` that have no drone in common, shortest first.
+    ///
+    /// Routes are found greedily, each avoiding the drones of the ones before it, so
+    /// fewer than the possible maximum may be returned on some topologies.
+    #[must_use]
+    pub fn disjoint_paths(&self, from: NodeId, to: NodeId, max: usize) -> Vec<Vec<NodeId>> {
+        let mut paths = Vec::new();
+        let mut used = BTreeSet::new();
+        while paths.len() < max {
+            let Some(path) = self.shortest_path(from, to, &used) else {
+                break;
+            };
+            let direct = path.len() <= 2;
+            used.extend(path[1..path.len() - 1].iter().copied());
+            paths.push(path);
+            if direct {
+                break;
+            }
+        }
+        paths
+    }
+
+    /// Breadth-first search through drones not in `avoid`.
+    fn shortest_path(
+        &self,
+        from: NodeId,
+        to: NodeId,
+        avoid: &BTreeSet<NodeId>,
+    ) -> Option<Vec<NodeId>> {
+        let mut previous = HashMap::new();
+        let mut queue = VecDeque::from([from]);
+        while let Some(node) = queue.pop_front() {
+            for &next in self.links.get(&node).into_iter().flatten() {
+                if next == from || previous.contains_key(&next) {
+                    continue;
+                }
+                if next == to {
+                    let mut path = vec![to, node];
+                    let mut current = node;
+                    while let Some(&before) = previous.get(&current) {
+                        path.push(before);
+                        current = before;
+                    }
+                    path.reverse();
+                    return Some(path);
+                }
+                if self.drones.contains(&next) && !avoid.contains(&next) {
+                    previous.insert(next, node);
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+}
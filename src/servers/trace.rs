@@ -0,0 +1,309 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write as _;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use wg_2024::network::NodeId;
+use wg_2024::packet::NackType;
+
+use crate::servers::fec::is_parity;
+use crate::servers::metrics::nack_kind;
+
+/// Number of events kept in memory before the oldest ones are discarded.
+pub const DEFAULT_TRACE_CAPACITY: usize = 65_536;
+
+/// Outgoing messages followed before the oldest is forgotten, for the ones neither
+/// acknowledged nor given up on.
+pub const MAX_TRACED_SESSIONS: usize = 1024;
+
+/// Step of a message's lifecycle recorded by the [`MessageTracer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceEventKind {
+    /// An incoming request was reassembled from its fragments.
+    RequestAssembled,
+    /// An outgoing message was split into fragments.
+    Fragmented,
+    /// A fragment was handed to a neighbour.
+    Sent,
+    AckReceived,
+    /// Carries the kind of nack, as named in the metrics.
+    NackReceived(&'static str),
+    /// A fragment was sent again after a nack.
+    Retransmitted,
+    /// Every fragment of an outgoing message has been acknowledged.
+    Completed,
+}
+
+/// One recorded step for a `(session_id, fragment_index)` pair.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceEvent {
+    pub session_id: u64,
+    pub fragment_index: u64,
+    pub kind: TraceEventKind,
+    pub route: Vec<NodeId>,
+    /// Time since the tracer was created.
+    pub elapsed: Duration,
+    /// For acks, time since the fragment was first sent. For completions, time since
+    /// the message was fragmented.
+    pub latency: Option<Duration>,
+}
+
+/// Records the lifecycle of the messages going through a server.
+///
+/// Outgoing messages are followed until every fragment is acknowledged, or until the
+/// server gives up on one of them: the message can no longer complete, and the later
+/// acks of its other fragments are not recorded.
+///
+/// Like [`Metrics`](crate::servers::metrics::Metrics), recording only needs `&self`.
+#[derive(Debug)]
+pub struct MessageTracer {
+    start: Instant,
+    capacity: usize,
+    events: RefCell<VecDeque<TraceEvent>>,
+    first_sent: RefCell<HashMap<(u64, u64), Instant>>,
+    sessions: RefCell<HashMap<u64, (Instant, u64)>>, //fragmentation time and fragments left
+}
+
+impl Default for MessageTracer {
+    fn default() -> Self {
+        Self::new(DEFAULT_TRACE_CAPACITY)
+    }
+}
+
+impl MessageTracer {
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            start: Instant::now(),
+            capacity,
+            events: RefCell::new(VecDeque::new()),
+            first_sent: RefCell::new(HashMap::new()),
+            sessions: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Changes the number of events kept, dropping the oldest ones over it.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        let events = self.events.get_mut();
+        while events.len() > capacity {
+            events.pop_front();
+        }
+    }
+
+    pub fn request_assembled(&self, session_id: u64, fragment_index: u64, route: &[NodeId]) {
+        self.record(
+            session_id,
+            fragment_index,
+            TraceEventKind::RequestAssembled,
+            route,
+            None,
+        );
+    }
+
+    pub fn fragmented(&self, session_id: u64, n_fragments: u64, route: &[NodeId]) {
+        let oldest = {
+            let mut sessions = self.sessions.borrow_mut();
+            sessions.insert(session_id, (Instant::now(), n_fragments));
+            if sessions.len() > MAX_TRACED_SESSIONS {
+                sessions
+                    .iter()
+                    .min_by_key(|(_, (fragmented_at, _))| *fragmented_at)
+                    .map(|(&oldest, _)| oldest)
+            } else {
+                None
+            }
+        };
+        if let Some(oldest) = oldest {
+            self.forget(oldest);
+        }
+        for fragment_index in 0..n_fragments {
+            self.record(
+                session_id,
+                fragment_index,
+                TraceEventKind::Fragmented,
+                route,
+                None,
+            );
+        }
+    }
+
+    /// Records a fragment handed to a neighbour.
+    ///
+    /// Parity fragments are not traced: their acks must not count towards completion,
+    /// which is only reached once every data fragment is acknowledged.
+    pub fn sent(&self, session_id: u64, fragment_index: u64, route: &[NodeId]) {
+        if is_parity(fragment_index) {
+            return;
+        }
+        self.first_sent
+            .borrow_mut()
+            .entry((session_id, fragment_index))
+            .or_insert_with(Instant::now);
+        self.record(
+            session_id,
+            fragment_index,
+            TraceEventKind::Sent,
+            route,
+            None,
+        );
+    }
+
+    pub fn retransmitted(&self, session_id: u64, fragment_index: u64, route: &[NodeId]) {
+        self.record(
+            session_id,
+            fragment_index,
+            TraceEventKind::Retransmitted,
+            route,
+            None,
+        );
+    }
+
+    pub fn nack_received(&self, session_id: u64, fragment_index: u64, nack_type: &NackType) {
+        if is_parity(fragment_index) {
+            return;
+        }
+        self.record(
+            session_id,
+            fragment_index,
+            TraceEventKind::NackReceived(nack_kind(nack_type)),
+            &[],
+            None,
+        );
+    }
+
+    /// Records an ack, and the completion of the message if it was the last one missing.
+    pub fn ack_received(&self, session_id: u64, fragment_index: u64, route: &[NodeId]) {
+        let Some(first_sent) = self
+            .first_sent
+            .borrow_mut()
+            .remove(&(session_id, fragment_index))
+        else {
+            // ack for a fragment that is not tracked or already acknowledged
+            return;
+        };
+        self.record(
+            session_id,
+            fragment_index,
+            TraceEventKind::AckReceived,
+            route,
+            Some(first_sent.elapsed()),
+        );
+
+        let mut sessions = self.sessions.borrow_mut();
+        let Some((fragmented_at, left)) = sessions.get_mut(&session_id) else {
+            return;
+        };
+        *left = left.saturating_sub(1);
+        if *left == 0 {
+            let latency = fragmented_at.elapsed();
+            sessions.remove(&session_id);
+            drop(sessions);
+            self.record(
+                session_id,
+                fragment_index,
+                TraceEventKind::Completed,
+                route,
+                Some(latency),
+            );
+        }
+    }
+
+    /// Stops following a message the server gave up on a fragment of.
+    pub fn abandoned(&self, session_id: u64) {
+        self.forget(session_id);
+    }
+
+    fn forget(&self, session_id: u64) {
+        self.sessions.borrow_mut().remove(&session_id);
+        self.first_sent
+            .borrow_mut()
+            .retain(|&(session, _), _| session != session_id);
+    }
+
+    /// Number of outgoing messages followed.
+    #[must_use]
+    pub fn traced_sessions(&self) -> usize {
+        self.sessions.borrow().len()
+    }
+
+    fn record(
+        &self,
+        session_id: u64,
+        fragment_index: u64,
+        kind: TraceEventKind,
+        route: &[NodeId],
+        latency: Option<Duration>,
+    ) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut events = self.events.borrow_mut();
+        if events.len() == self.capacity {
+            events.pop_front();
+        }
+        events.push_back(TraceEvent {
+            session_id,
+            fragment_index,
+            kind,
+            route: route.to_vec(),
+            elapsed: self.start.elapsed(),
+            latency,
+        });
+    }
+
+    #[must_use]
+    pub fn events(&self) -> Vec<TraceEvent> {
+        self.events.borrow().iter().cloned().collect()
+    }
+
+    /// Serializes the recorded events as JSON lines, one event per line.
+    #[must_use]
+    pub fn to_json_lines(&self, node_id: NodeId) -> String {
+        let mut out = String::new();
+        for event in self.events.borrow().iter() {
+            let route = event
+                .route
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(",");
+            let _ = write!(
+                out,
+                "{{\"node_id\":{node_id},\"session_id\":{},\"fragment_index\":{},\"event\":\"{}\",\"route\":[{route}],\"elapsed_us\":{}",
+                event.session_id,
+                event.fragment_index,
+                event_name(event.kind),
+                event.elapsed.as_micros()
+            );
+            if let TraceEventKind::NackReceived(nack_type) = event.kind {
+                let _ = write!(out, ",\"nack_type\":\"{nack_type}\"");
+            }
+            if let Some(latency) = event.latency {
+                let _ = write!(out, ",\"latency_us\":{}", latency.as_micros());
+            }
+            out.push_str("}\n");
+        }
+        out
+    }
+
+    /// Writes the recorded events to `path` as JSON lines.
+    ///
+    /// # Errors
+    /// Returns the I/O error if the file cannot be written.
+    pub fn export(&self, node_id: NodeId, path: &Path) -> std::io::Result<()> {
+        std::fs::write(path, self.to_json_lines(node_id))
+    }
+}
+
+fn event_name(kind: TraceEventKind) -> &'static str {
+    match kind {
+        TraceEventKind::RequestAssembled => "request_assembled",
+        TraceEventKind::Fragmented => "fragmented",
+        TraceEventKind::Sent => "sent",
+        TraceEventKind::AckReceived => "ack_received",
+        TraceEventKind::NackReceived(_) => "nack_received",
+        TraceEventKind::Retransmitted => "retransmitted",
+        TraceEventKind::Completed => "completed",
+    }
+}
@@ -0,0 +1,187 @@
+use crate::servers::ack::AckAggregator;
+use crate::servers::capture::PacketCapture;
+use crate::servers::commands::{ServerCommand, ServerEvent};
+use crate::servers::config::ServerConfig;
+use crate::servers::control_message::Feature;
+use crate::servers::dedup::DuplicateFilter;
+use crate::servers::fec::FecPolicy;
+use crate::servers::metrics::Metrics;
+use crate::servers::pending::PendingFragments;
+use crate::servers::rate_limit::RateLimiter;
+use crate::servers::reassembly::Reassembly;
+use crate::servers::route_stats::RouteStats;
+use crate::servers::scheduler::OutboundScheduler;
+use crate::servers::snapshot::Autosnapshot;
+use crate::servers::topology::Topology;
+use crate::servers::trace::MessageTracer;
+use crate::servers::window::SendWindows;
+use assembler::HighLevelMessageFactory;
+use crossbeam_channel::{never, Receiver, SendError, Sender};
+use messages::high_level_messages::Message;
+use messages::server_commands::{CommunicationServerEvent, ContentServerEvent};
+use packet_cache::PacketCache;
+use source_routing::Router;
+use std::collections::{BTreeSet, HashMap};
+use wg_2024::network::NodeId;
+use wg_2024::packet::{NodeType, Packet};
+
+/// The events a server reports to the simulation controller, which `messages` defines
+/// once per server type.
+pub trait ControllerEvent: std::fmt::Debug {
+    /// Name of the server in the log lines.
+    const SERVER: &'static str;
+
+    fn send_error(packet: Packet) -> Self;
+    fn shortcut(packet: Packet) -> Self;
+    fn destination_is_drone(id: NodeId) -> Self;
+    fn error_packet_cache(session_id: u64, fragment_index: u64) -> Self;
+    fn unreachable_node(id: NodeId) -> Self;
+}
+
+impl ControllerEvent for CommunicationServerEvent {
+    const SERVER: &'static str = "CommunicationServer";
+
+    fn send_error(packet: Packet) -> Self {
+        Self::SendError(SendError(packet))
+    }
+
+    fn shortcut(packet: Packet) -> Self {
+        Self::ControllerShortcut(packet)
+    }
+
+    fn destination_is_drone(id: NodeId) -> Self {
+        Self::DestinationIsDrone(id)
+    }
+
+    fn error_packet_cache(session_id: u64, fragment_index: u64) -> Self {
+        Self::ErrorPacketCache(session_id, fragment_index)
+    }
+
+    fn unreachable_node(id: NodeId) -> Self {
+        Self::UnreachableNode(id)
+    }
+}
+
+impl ControllerEvent for ContentServerEvent {
+    const SERVER: &'static str = "ContentServer";
+
+    fn send_error(packet: Packet) -> Self {
+        Self::SendError(SendError(packet))
+    }
+
+    fn shortcut(packet: Packet) -> Self {
+        Self::ControllerShortcut(packet)
+    }
+
+    fn destination_is_drone(id: NodeId) -> Self {
+        Self::DestinationIsDrone(id)
+    }
+
+    fn error_packet_cache(session_id: u64, fragment_index: u64) -> Self {
+        Self::ErrorPacketCache(session_id, fragment_index)
+    }
+
+    fn unreachable_node(id: NodeId) -> Self {
+        Self::UnreachableNode(id)
+    }
+}
+
+/// What the server has to act on after the transport processed a packet.
+pub(crate) enum Received {
+    /// A message whose last fragment arrived.
+    Message(Message),
+    /// The path trace of a flood response, once its routes are learned.
+    PathTrace(Vec<(NodeId, NodeType)>),
+    /// A fragment was retransmitted often enough to flood again.
+    Reflood,
+}
+
+/// What both servers need to exchange messages over the network: routing, fragments
+/// in flight, reassembly, and the recorders watching them.
+///
+/// The servers only add what they serve on top of it, see
+/// [`Server`](crate::servers::server::Server).
+pub struct Transport<E> {
+    pub id: NodeId,
+    pub config: ServerConfig,
+    pub router: Router,
+    pub message_factory: HighLevelMessageFactory,
+    pub packet_cache: PacketCache, //cache for the packets
+    pub pending: PendingFragments, //the cached packets, for snapshots
+    pub packet_recv: Receiver<Packet>,
+    pub packet_send: HashMap<NodeId, Sender<Packet>>,
+    pub controller_send: Sender<E>,
+    pub server_event_send: Option<Sender<ServerEvent>>,
+    pub server_command_recv: Receiver<ServerCommand>,
+    pub metrics: Metrics,
+    pub tracer: MessageTracer,
+    pub capture: PacketCapture,
+    pub duplicate_filter: DuplicateFilter,
+    pub reassembly: Reassembly,
+    pub scheduler: OutboundScheduler,
+    pub topology: Topology,
+    pub route_stats: RouteStats,
+    pub send_windows: SendWindows,
+    pub acks: AckAggregator,
+    pub client_features: HashMap<NodeId, BTreeSet<Feature>>,
+    pub fec_policy: FecPolicy,
+    pub rate_limiter: RateLimiter,
+    pub autosnapshot: Option<Autosnapshot>,
+}
+
+impl<E: ControllerEvent> Transport<E> {
+    #[must_use]
+    pub fn new(
+        id: NodeId,
+        packet_recv: Receiver<Packet>,
+        packet_send: HashMap<NodeId, Sender<Packet>>,
+        controller_send: Sender<E>,
+        config: ServerConfig,
+    ) -> Self {
+        Self {
+            id,
+            router: Router::new(id, NodeType::Server),
+            message_factory: HighLevelMessageFactory::new(id, NodeType::Server),
+            packet_cache: PacketCache::new(),
+            pending: PendingFragments::new(),
+            packet_recv,
+            packet_send,
+            controller_send,
+            server_event_send: None,
+            server_command_recv: never(),
+            metrics: Metrics::new(),
+            tracer: MessageTracer::new(config.trace_capacity),
+            capture: PacketCapture::new(),
+            duplicate_filter: DuplicateFilter::new(config.dedup_window),
+            reassembly: Reassembly::new(config.reassembly_limits()),
+            scheduler: OutboundScheduler::new(config.queue_capacity),
+            topology: Topology::new(),
+            route_stats: RouteStats::new(),
+            send_windows: SendWindows::new(),
+            acks: AckAggregator::new(),
+            client_features: HashMap::new(),
+            fec_policy: config.fec_policy,
+            rate_limiter: RateLimiter::new(),
+            autosnapshot: None,
+            config,
+        }
+    }
+
+    /// Attaches the channels carrying [`ServerCommand`]s and [`ServerEvent`]s.
+    pub fn attach_server_channels(
+        &mut self,
+        event_send: Sender<ServerEvent>,
+        command_recv: Receiver<ServerCommand>,
+    ) {
+        self.server_event_send = Some(event_send);
+        self.server_command_recv = command_recv;
+    }
+
+    /// Whether `client` enabled `feature`.
+    #[must_use]
+    pub fn enabled(&self, client: NodeId, feature: Feature) -> bool {
+        self.client_features
+            .get(&client)
+            .is_some_and(|features| features.contains(&feature))
+    }
+}
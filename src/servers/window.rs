@@ -0,0 +1,233 @@
+use crate::servers::fec::is_parity;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use wg_2024::packet::Packet;
+
+/// Window a session starts with, in fragments.
+pub const INITIAL_WINDOW: f64 = 4.0;
+
+/// Smallest window a session can shrink to, in fragments.
+pub const MIN_WINDOW: f64 = 1.0;
+
+/// Slow start threshold a session starts with, in fragments.
+const INITIAL_SSTHRESH: f64 = 64.0;
+
+/// Sessions tracked before the least recently active is forgotten.
+pub const MAX_SESSIONS: usize = 1024;
+
+/// Congestion window of one outgoing session.
+#[derive(Debug)]
+pub struct SendWindow {
+    cwnd: f64,
+    ssthresh: f64,
+    in_flight: BTreeSet<u64>,
+    pending: VecDeque<Packet>,
+    /// When the session was last pushed to, acked or lost, in calls to the windows.
+    last_active: u64,
+}
+
+impl Default for SendWindow {
+    fn default() -> Self {
+        Self {
+            cwnd: INITIAL_WINDOW,
+            ssthresh: INITIAL_SSTHRESH,
+            in_flight: BTreeSet::new(),
+            pending: VecDeque::new(),
+            last_active: 0,
+        }
+    }
+}
+
+impl SendWindow {
+    /// Window size, in fragments.
+    #[must_use]
+    pub fn size(&self) -> f64 {
+        self.cwnd
+    }
+
+    /// Fragments sent and not acknowledged yet.
+    #[must_use]
+    pub fn in_flight(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    /// Fragments waiting for room in the window.
+    #[must_use]
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    fn on_ack(&mut self, fragment_index: u64) {
+        if !self.in_flight.remove(&fragment_index) {
+            return;
+        }
+        if self.cwnd < self.ssthresh {
+            self.cwnd += 1.0;
+        } else {
+            self.cwnd += 1.0 / self.cwnd;
+        }
+    }
+
+    /// Forgets a fragment that will never be acked, sent or not.
+    fn abandon(&mut self, fragment_index: u64) {
+        if !self.in_flight.remove(&fragment_index) {
+            self.pending
+                .retain(|packet| packet.get_fragment_index() != fragment_index);
+        }
+    }
+
+    fn on_loss(&mut self) {
+        self.ssthresh = (self.cwnd / 2.0).max(MIN_WINDOW);
+        self.cwnd = self.ssthresh;
+    }
+
+    /// Takes the pending fragments that fit in the window.
+    fn release(&mut self) -> Vec<Packet> {
+        let mut released = Vec::new();
+        while (self.in_flight.len() as f64) < self.cwnd.floor() {
+            let Some(packet) = self.pending.pop_front() else {
+                break;
+            };
+            // parity fragments are not acknowledged reliably, they take no room
+            if !is_parity(packet.get_fragment_index()) {
+                self.in_flight.insert(packet.get_fragment_index());
+            }
+            released.push(packet);
+        }
+        released
+    }
+
+    fn is_done(&self) -> bool {
+        self.in_flight.is_empty() && self.pending.is_empty()
+    }
+}
+
+/// Sliding send windows of the messages the server is sending, one per session.
+///
+/// A session only has as many fragments in flight as its window allows; the others wait
+/// here. The window grows by one fragment per ack until the slow start threshold, then
+/// by one fragment per window, and halves on a loss, like TCP's congestion avoidance.
+///
+/// Parity fragments are released in order with the data fragments but take no room in
+/// the window, since they are never retransmitted and their acks are not waited for.
+/// They escape congestion control: a window of `n` fragments puts up to `n` plus one
+/// parity fragment per block in flight.
+///
+/// Past [`MAX_SESSIONS`] the least recently active session is forgotten, see
+/// [`SendWindows::evict_idle`].
+#[derive(Debug, Default)]
+pub struct SendWindows {
+    sessions: BTreeMap<u64, SendWindow>,
+    activity: u64,
+}
+
+impl SendWindows {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queues the fragments of a new message, returning the ones that can go now.
+    pub fn push(&mut self, session_id: u64, fragments: Vec<Packet>) -> Vec<Packet> {
+        self.queue(session_id, fragments);
+        self.sessions
+            .get_mut(&session_id)
+            .map(SendWindow::release)
+            .unwrap_or_default()
+    }
+
+    /// Queues the fragments of a message without releasing any, until
+    /// [`SendWindows::release_all`].
+    pub fn queue(&mut self, session_id: u64, fragments: Vec<Packet>) {
+        self.activity += 1;
+        let window = self.sessions.entry(session_id).or_default();
+        window.last_active = self.activity;
+        window.pending.extend(fragments);
+    }
+
+    /// Takes the pending fragments that fit in any window.
+    pub fn release_all(&mut self) -> Vec<Packet> {
+        self.sessions
+            .values_mut()
+            .flat_map(SendWindow::release)
+            .collect()
+    }
+
+    /// Forgets the least recently active session if there are more than
+    /// [`MAX_SESSIONS`], returning its id and the fragments it never sent.
+    pub fn evict_idle(&mut self) -> Option<(u64, Vec<Packet>)> {
+        if self.sessions.len() <= MAX_SESSIONS {
+            return None;
+        }
+        let (&session_id, _) = self
+            .sessions
+            .iter()
+            .min_by_key(|(_, window)| window.last_active)?;
+        let window = self.sessions.remove(&session_id)?;
+        Some((session_id, window.pending.into()))
+    }
+
+    /// The window of a session, marked as the most recently active.
+    fn touch(&mut self, session_id: u64) -> Option<&mut SendWindow> {
+        let window = self.sessions.get_mut(&session_id)?;
+        self.activity += 1;
+        window.last_active = self.activity;
+        Some(window)
+    }
+
+    /// Records the ack of a fragment, returning the fragments it makes room for.
+    pub fn acked(&mut self, session_id: u64, fragment_index: u64) -> Vec<Packet> {
+        let Some(window) = self.touch(session_id) else {
+            return Vec::new();
+        };
+        window.on_ack(fragment_index);
+        let released = window.release();
+        if window.is_done() {
+            self.sessions.remove(&session_id);
+        }
+        released
+    }
+
+    /// Gives up on a fragment that will never be acked, returning the fragments its room
+    /// in the window goes to.
+    ///
+    /// A fragment still waiting for room is never sent.
+    pub fn abandon(&mut self, session_id: u64, fragment_index: u64) -> Vec<Packet> {
+        let Some(window) = self.sessions.get_mut(&session_id) else {
+            return Vec::new();
+        };
+        window.abandon(fragment_index);
+        let released = window.release();
+        if window.is_done() {
+            self.sessions.remove(&session_id);
+        }
+        released
+    }
+
+    /// Shrinks the window of a session that lost a fragment.
+    ///
+    /// The lost fragment stays in flight, since it is retransmitted right away.
+    pub fn lost(&mut self, session_id: u64) {
+        if let Some(window) = self.touch(session_id) {
+            window.on_loss();
+        }
+    }
+
+    #[must_use]
+    pub fn get(&self, session_id: u64) -> Option<&SendWindow> {
+        self.sessions.get(&session_id)
+    }
+
+    /// Whether a fragment was sent and is waiting for its ack.
+    #[must_use]
+    pub fn is_in_flight(&self, session_id: u64, fragment_index: u64) -> bool {
+        self.sessions
+            .get(&session_id)
+            .is_some_and(|window| window.in_flight.contains(&fragment_index))
+    }
+
+    /// Fragments waiting for room in any window.
+    #[must_use]
+    pub fn pending(&self) -> usize {
+        self.sessions.values().map(SendWindow::pending).sum()
+    }
+}
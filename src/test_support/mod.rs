@@ -0,0 +1,553 @@
+//! Deterministic in-process harness for testing the servers.
+//!
+//! A [`NetworkBuilder`] describes a topology of simulated drones and clients around a
+//! single server. The resulting [`MockNetwork`] drives everything on the calling
+//! thread in discrete ticks: packets the server sends on its crossbeam channels are
+//! picked up, moved hop by hop through the drones, and delivered back to the server by
+//! calling `handle_packet`. Drops are drawn from a seeded `StdRng`, so a test run is
+//! fully reproducible.
+
+mod nodes;
+
+pub use nodes::DroneConfig;
+
+use crate::servers::communication_server::CommunicationServer;
+use crate::servers::config::ServerConfig;
+use crate::servers::content_server::ContentServer;
+use crate::servers::control_message::{ControlMessage, ControlReply};
+use crate::servers::hybrid_server::{HybridServer, Roles};
+use crate::servers::server::Server;
+use crossbeam_channel::{unbounded, Receiver};
+use messages::high_level_messages::MessageContent::{FromClient, FromServer};
+use messages::high_level_messages::{ClientMessage, Message, ServerMessage, ServerType};
+use nodes::{Forward, MockClient, MockDrone};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use wg_2024::network::{NodeId, SourceRoutingHeader};
+use wg_2024::packet::{FloodResponse, NodeType, Packet, PacketType};
+
+/// Number of ticks the helpers wait for a reply before giving up.
+pub const DEFAULT_TIMEOUT_TICKS: u64 = 10_000;
+
+/// The server of [`NetworkBuilder::single_drone`].
+pub const SERVER: NodeId = 1;
+/// The drone of [`NetworkBuilder::single_drone`].
+pub const DRONE: NodeId = 10;
+/// The client of [`NetworkBuilder::single_drone`].
+pub const CLIENT: NodeId = 20;
+/// The client of [`NetworkBuilder::with_peer`] standing in for another server: the
+/// harness only simulates one.
+pub const PEER: NodeId = 30;
+
+/// Describes the simulated network around a server.
+#[derive(Debug, Clone, Default)]
+pub struct NetworkBuilder {
+    seed: u64,
+    drones: BTreeMap<NodeId, DroneConfig>,
+    clients: BTreeSet<NodeId>,
+    links: Vec<(NodeId, NodeId)>,
+    config: ServerConfig,
+}
+
+impl NetworkBuilder {
+    #[must_use]
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            ..Self::default()
+        }
+    }
+
+    /// The smallest network: [`SERVER`] and [`CLIENT`] both linked to [`DRONE`].
+    ///
+    /// More clients can be added around the drone with `client` and `link`.
+    #[must_use]
+    pub fn single_drone(seed: u64) -> Self {
+        Self::new(seed)
+            .drone(DRONE, DroneConfig::default())
+            .client(CLIENT)
+            .link(SERVER, DRONE)
+            .link(DRONE, CLIENT)
+    }
+
+    /// [`NetworkBuilder::single_drone`] with [`PEER`] linked to the drone too.
+    #[must_use]
+    pub fn with_peer(seed: u64) -> Self {
+        Self::single_drone(seed).client(PEER).link(DRONE, PEER)
+    }
+
+    #[must_use]
+    pub fn drone(mut self, id: NodeId, config: DroneConfig) -> Self {
+        self.drones.insert(id, config);
+        self
+    }
+
+    #[must_use]
+    pub fn client(mut self, id: NodeId) -> Self {
+        self.clients.insert(id);
+        self
+    }
+
+    /// Configures the server that will be built.
+    #[must_use]
+    pub fn config(mut self, config: ServerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Adds a bidirectional link, which may involve the server.
+    #[must_use]
+    pub fn link(mut self, a: NodeId, b: NodeId) -> Self {
+        self.links.push((a, b));
+        self
+    }
+
+    /// Builds the network around a `ContentServer` of the given type.
+    #[must_use]
+    pub fn build_content_server(
+        self,
+        id: NodeId,
+        server_type: ServerType,
+    ) -> MockNetwork<ContentServer> {
+        let (packet_send, outboxes) = self.server_channels(id);
+        let (controller_send, events) = unbounded();
+        let (_, controller_recv) = unbounded();
+        let (_, packet_recv) = unbounded();
+        let server = ContentServer::new(
+            id,
+            packet_recv,
+            packet_send,
+            controller_send,
+            controller_recv,
+            server_type,
+            self.config.clone(),
+        );
+        MockNetwork::new(self, server, outboxes, events)
+    }
+
+    /// Builds the network around a `CommunicationServer`.
+    #[must_use]
+    pub fn build_communication_server(self, id: NodeId) -> MockNetwork<CommunicationServer> {
+        let (packet_send, outboxes) = self.server_channels(id);
+        let (controller_send, events) = unbounded();
+        let (_, controller_recv) = unbounded();
+        let (_, packet_recv) = unbounded();
+        let server = CommunicationServer::new(
+            id,
+            packet_recv,
+            packet_send,
+            controller_send,
+            controller_recv,
+            self.config.clone(),
+        );
+        MockNetwork::new(self, server, outboxes, events)
+    }
+
+    /// Builds the network around a hybrid server taking on `roles`.
+    #[must_use]
+    pub fn build_hybrid_server(self, id: NodeId, roles: Roles) -> MockNetwork<HybridServer> {
+        let (packet_send, outboxes) = self.server_channels(id);
+        let (controller_send, events) = unbounded();
+        let (_, controller_recv) = unbounded();
+        let (_, packet_recv) = unbounded();
+        let server = HybridServer::hybrid(
+            id,
+            packet_recv,
+            packet_send,
+            controller_send,
+            controller_recv,
+            roles,
+            self.config.clone(),
+        );
+        MockNetwork::new(self, server, outboxes, events)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn server_channels(
+        &self,
+        id: NodeId,
+    ) -> (
+        HashMap<NodeId, crossbeam_channel::Sender<Packet>>,
+        Vec<(NodeId, Receiver<Packet>)>,
+    ) {
+        let mut packet_send = HashMap::new();
+        let mut outboxes = Vec::new();
+        for neighbour in self.neighbours_of(id) {
+            let (send, recv) = unbounded();
+            packet_send.insert(neighbour, send);
+            outboxes.push((neighbour, recv));
+        }
+        (packet_send, outboxes)
+    }
+
+    fn neighbours_of(&self, id: NodeId) -> BTreeSet<NodeId> {
+        self.links
+            .iter()
+            .filter_map(|&(a, b)| match (a == id, b == id) {
+                (true, false) => Some(b),
+                (false, true) => Some(a),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// A server wired to simulated drones and clients.
+pub struct MockNetwork<S: Server> {
+    pub server: S,
+    /// Events the server sent to the simulation controller.
+    pub events: Receiver<S::Event>,
+    outboxes: Vec<(NodeId, Receiver<Packet>)>,
+    drones: BTreeMap<NodeId, MockDrone>,
+    clients: BTreeMap<NodeId, MockClient>,
+    in_flight: BTreeMap<(u64, u64), (NodeId, Packet)>, //(delivery tick, sequence) to (receiver, packet)
+    tick: u64,
+    sequence: u64,
+    rng: StdRng,
+}
+
+impl<S: Server> MockNetwork<S> {
+    fn new(
+        builder: NetworkBuilder,
+        server: S,
+        outboxes: Vec<(NodeId, Receiver<Packet>)>,
+        events: Receiver<S::Event>,
+    ) -> Self {
+        let mut drones = builder
+            .drones
+            .iter()
+            .map(|(&id, &config)| (id, MockDrone::new(id, config)))
+            .collect::<BTreeMap<_, _>>();
+        let mut clients = builder
+            .clients
+            .iter()
+            .map(|&id| (id, MockClient::new(id)))
+            .collect::<BTreeMap<_, _>>();
+        for &(a, b) in &builder.links {
+            for (from, to) in [(a, b), (b, a)] {
+                if let Some(drone) = drones.get_mut(&from) {
+                    drone.neighbours.insert(to);
+                }
+                if let Some(client) = clients.get_mut(&from) {
+                    client.neighbours.insert(to);
+                }
+            }
+        }
+        Self {
+            server,
+            events,
+            outboxes,
+            drones,
+            clients,
+            in_flight: BTreeMap::new(),
+            tick: 0,
+            sequence: 0,
+            rng: StdRng::seed_from_u64(builder.seed),
+        }
+    }
+
+    #[must_use]
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Floods from the server and runs until the network is idle.
+    pub fn flood(&mut self) {
+        self.server.send_flood_requests();
+        self.run_until_idle(DEFAULT_TIMEOUT_TICKS);
+    }
+
+    /// Makes a drone crash, or come back, from now on.
+    pub fn set_crashed(&mut self, drone: NodeId, crashed: bool) {
+        if let Some(drone) = self.drones.get_mut(&drone) {
+            drone.config.crashed = crashed;
+        }
+    }
+
+    pub fn set_drop_rate(&mut self, drone: NodeId, drop_rate: f64) {
+        if let Some(drone) = self.drones.get_mut(&drone) {
+            drone.config.drop_rate = drop_rate;
+        }
+    }
+
+    /// Fragments `message` at `client` and sends it to the server over the shortest
+    /// path of working drones.
+    ///
+    /// # Panics
+    /// Panics if `client` is not part of the network or cannot reach the server.
+    pub fn send_request(&mut self, client: NodeId, message: ClientMessage) {
+        self.send_request_to(client, self.server.id(), message);
+    }
+
+    /// Like [`send_request`](Self::send_request), with an explicit destination.
+    ///
+    /// # Panics
+    /// Panics if `client` is not part of the network or cannot reach `destination`.
+    pub fn send_request_to(&mut self, client: NodeId, destination: NodeId, message: ClientMessage) {
+        let hops = self
+            .shortest_path(client, destination)
+            .unwrap_or_else(|| panic!("client {client} cannot reach {destination}"));
+        let header = SourceRoutingHeader::with_first_hop(hops);
+        let mock_client = self
+            .clients
+            .get_mut(&client)
+            .unwrap_or_else(|| panic!("{client} is not a client"));
+        let packets = mock_client.factory.get_message_from_message_content(
+            FromClient(message),
+            &header,
+            destination,
+        );
+        mock_client.unacked.extend(packets.iter().cloned());
+        for packet in packets {
+            if let Some(next) = packet.routing_header.current_hop() {
+                self.schedule(next, packet, 1);
+            }
+        }
+    }
+
+    /// Runs the network until `client` has reassembled a message, returning it.
+    pub fn await_message(&mut self, client: NodeId, max_ticks: u64) -> Option<Message> {
+        let deadline = self.tick + max_ticks;
+        while self.tick < deadline {
+            if let Some(message) = self
+                .clients
+                .get_mut(&client)
+                .and_then(|client| client.inbox.pop_front())
+            {
+                return Some(message);
+            }
+            self.step();
+        }
+        None
+    }
+
+    /// Sends a request and waits for the first message it brings back.
+    pub fn request(&mut self, client: NodeId, message: ClientMessage) -> Option<Message> {
+        self.send_request(client, message);
+        self.await_message(client, DEFAULT_TIMEOUT_TICKS)
+    }
+
+    /// Messages `client` reassembled and nobody awaited yet.
+    pub fn drain_inbox(&mut self, client: NodeId) -> Vec<Message> {
+        self.clients
+            .get_mut(&client)
+            .map(|client| client.inbox.drain(..).collect())
+            .unwrap_or_default()
+    }
+
+    /// Sends a control message from `client` to the server and runs until idle.
+    pub fn send_control(&mut self, client: NodeId, control_message: &ControlMessage) {
+        self.send_request(
+            client,
+            ClientMessage::SendMessage {
+                recipient_id: self.server.id(),
+                content: control_message.encode(),
+            },
+        );
+        self.run_until_idle(DEFAULT_TIMEOUT_TICKS);
+    }
+
+    /// Control replies of the server among the messages `client` did not take yet.
+    pub fn control_replies(&mut self, client: NodeId) -> Vec<ControlReply> {
+        let server_id = self.server.id();
+        self.drain_inbox(client)
+            .into_iter()
+            .filter_map(|message| match message.content {
+                FromServer(ServerMessage::MessageReceived { sender_id, content })
+                    if sender_id == server_id =>
+                {
+                    ControlReply::parse(&content)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Runs until idle and returns the requests `client` received, such as the ones a
+    /// server sends to [`PEER`].
+    pub fn requests_to(&mut self, client: NodeId) -> Vec<ClientMessage> {
+        self.run_until_idle(DEFAULT_TIMEOUT_TICKS);
+        self.drain_inbox(client)
+            .into_iter()
+            .filter_map(|message| match message.content {
+                FromClient(request) => Some(request),
+                FromServer(_) => None,
+            })
+            .collect()
+    }
+
+    /// Hands the server a flood response from `server`, making it a server seen in a
+    /// path trace.
+    ///
+    /// # Panics
+    /// Panics if the server cannot reach `server`.
+    pub fn discover_server(&mut self, server: NodeId) {
+        let hops = self
+            .shortest_path(self.server.id(), server)
+            .unwrap_or_else(|| panic!("server cannot reach {server}"));
+        let path_trace = hops
+            .iter()
+            .map(|&id| {
+                let node_type = if self.drones.contains_key(&id) {
+                    NodeType::Drone
+                } else {
+                    NodeType::Server
+                };
+                (id, node_type)
+            })
+            .collect();
+        let mut back = hops;
+        back.reverse();
+        let packet = Packet::new_flood_response(
+            SourceRoutingHeader {
+                hop_index: back.len() - 1,
+                hops: back,
+            },
+            0,
+            FloodResponse {
+                flood_id: 0,
+                path_trace,
+            },
+        );
+        self.server.handle_packet(packet);
+    }
+
+    /// Runs ticks until nothing is in flight, for at most `max_ticks`.
+    pub fn run_until_idle(&mut self, max_ticks: u64) {
+        let deadline = self.tick + max_ticks;
+        self.collect_outboxes();
+        while !self.in_flight.is_empty() && self.tick < deadline {
+            self.step();
+        }
+    }
+
+    /// Advances the simulation by one tick, after the commands and periodic work the
+    /// server handles between packets when running.
+    pub fn step(&mut self) {
+        while let Ok(command) = self.server.transport().server_command_recv.try_recv() {
+            self.server.handle_server_command(command);
+        }
+        self.server.housekeeping();
+        self.collect_outboxes();
+        self.tick += 1;
+        while let Some(entry) = self.in_flight.first_entry() {
+            if entry.key().0 > self.tick {
+                break;
+            }
+            let (receiver, packet) = entry.remove();
+            self.deliver(receiver, packet);
+            self.collect_outboxes();
+        }
+    }
+
+    fn collect_outboxes(&mut self) {
+        let sent = self
+            .outboxes
+            .iter()
+            .flat_map(|(neighbour, outbox)| outbox.try_iter().map(|packet| (*neighbour, packet)))
+            .collect::<Vec<_>>();
+        for (neighbour, packet) in sent {
+            self.schedule(neighbour, packet, 1);
+        }
+    }
+
+    fn deliver(&mut self, receiver: NodeId, packet: Packet) {
+        if receiver == self.server.id() {
+            self.server.handle_packet(packet);
+            return;
+        }
+        let forwards = if let Some(config) = self.drones.get(&receiver).map(|drone| drone.config) {
+            if config.crashed {
+                return;
+            }
+            let is_fragment = matches!(packet.pack_type, PacketType::MsgFragment(_));
+            let drop = is_fragment && self.rng.gen_bool(config.drop_rate.clamp(0.0, 1.0));
+            let duplicate = is_fragment && self.rng.gen_bool(config.duplicate_rate.clamp(0.0, 1.0));
+            let crashed = self
+                .drones
+                .values()
+                .filter(|drone| drone.config.crashed)
+                .map(|drone| drone.id)
+                .collect::<BTreeSet<_>>();
+            self.drones
+                .get_mut(&receiver)
+                .map(|drone| drone.receive(packet, |id| !crashed.contains(&id), drop))
+                .unwrap_or_default()
+                .into_iter()
+                .flat_map(|forward| match forward {
+                    Forward::To(next, packet) if duplicate => vec![
+                        (Forward::To(next, packet.clone()), config.latency),
+                        (Forward::To(next, packet), config.latency),
+                    ],
+                    forward => vec![(forward, config.latency)],
+                })
+                .collect()
+        } else if let Some(client) = self.clients.get_mut(&receiver) {
+            client
+                .receive(packet)
+                .into_iter()
+                .map(|f| (f, 1))
+                .collect::<Vec<_>>()
+        } else {
+            Vec::new()
+        };
+        for (forward, latency) in forwards {
+            match forward {
+                Forward::To(next, packet) => self.schedule(next, packet, latency),
+                Forward::Shortcut(packet) => {
+                    if let Some(destination) = packet.routing_header.destination() {
+                        self.schedule(destination, packet, 1);
+                    }
+                }
+            }
+        }
+    }
+
+    fn schedule(&mut self, receiver: NodeId, packet: Packet, latency: u64) {
+        self.sequence += 1;
+        self.in_flight.insert(
+            (self.tick + latency.max(1), self.sequence),
+            (receiver, packet),
+        );
+    }
+
+    /// Breadth-first search over working drones.
+    fn shortest_path(&self, from: NodeId, to: NodeId) -> Option<Vec<NodeId>> {
+        let neighbours = |id: NodeId| -> BTreeSet<NodeId> {
+            if let Some(drone) = self.drones.get(&id) {
+                drone.neighbours.clone()
+            } else if let Some(client) = self.clients.get(&id) {
+                client.neighbours.clone()
+            } else {
+                self.outboxes.iter().map(|(id, _)| *id).collect()
+            }
+        };
+        let mut previous = BTreeMap::from([(from, from)]);
+        let mut queue = VecDeque::from([from]);
+        while let Some(id) = queue.pop_front() {
+            if id == to {
+                let mut hops = vec![to];
+                let mut current = to;
+                while current != from {
+                    current = previous[&current];
+                    hops.push(current);
+                }
+                hops.reverse();
+                return Some(hops);
+            }
+            // only working drones relay packets
+            let relays = self.drones.get(&id).is_some_and(|d| !d.config.crashed);
+            if id != from && !relays {
+                continue;
+            }
+            for next in neighbours(id) {
+                if let std::collections::btree_map::Entry::Vacant(e) = previous.entry(next) {
+                    e.insert(id);
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+}
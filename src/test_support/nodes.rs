@@ -0,0 +1,260 @@
+use assembler::HighLevelMessageFactory;
+use messages::high_level_messages::Message;
+use std::collections::{BTreeSet, HashSet, VecDeque};
+use wg_2024::network::{NodeId, SourceRoutingHeader};
+use wg_2024::packet::{
+    Ack, FloodRequest, FloodResponse, Nack, NackType, NodeType, Packet, PacketType,
+};
+
+/// Behaviour of a simulated drone.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DroneConfig {
+    /// Probability in `[0, 1]` of dropping a message fragment.
+    pub drop_rate: f64,
+    /// Probability in `[0, 1]` of forwarding a message fragment twice.
+    pub duplicate_rate: f64,
+    /// Ticks a packet spends in flight after leaving this drone.
+    pub latency: u64,
+    pub crashed: bool,
+}
+
+impl Default for DroneConfig {
+    fn default() -> Self {
+        Self {
+            drop_rate: 0.0,
+            duplicate_rate: 0.0,
+            latency: 1,
+            crashed: false,
+        }
+    }
+}
+
+impl DroneConfig {
+    #[must_use]
+    pub fn with_drop_rate(drop_rate: f64) -> Self {
+        Self {
+            drop_rate,
+            ..Self::default()
+        }
+    }
+
+    #[must_use]
+    pub fn with_duplicate_rate(duplicate_rate: f64) -> Self {
+        Self {
+            duplicate_rate,
+            ..Self::default()
+        }
+    }
+}
+
+/// What a simulated node wants done with a packet after processing it.
+#[derive(Debug)]
+pub(crate) enum Forward {
+    /// Deliver to a neighbour.
+    To(NodeId, Packet),
+    /// Deliver straight to the destination, like the simulation controller shortcut
+    /// does for packets that must not be lost.
+    Shortcut(Packet),
+}
+
+#[derive(Debug)]
+pub(crate) struct MockDrone {
+    pub id: NodeId,
+    pub config: DroneConfig,
+    pub neighbours: BTreeSet<NodeId>,
+    seen_floods: HashSet<(NodeId, u64)>,
+}
+
+impl MockDrone {
+    pub fn new(id: NodeId, config: DroneConfig) -> Self {
+        Self {
+            id,
+            config,
+            neighbours: BTreeSet::new(),
+            seen_floods: HashSet::new(),
+        }
+    }
+
+    /// Processes a packet, `drop` telling whether a fragment is dropped.
+    pub fn receive(
+        &mut self,
+        packet: Packet,
+        is_neighbour_alive: impl Fn(NodeId) -> bool,
+        drop: bool,
+    ) -> Vec<Forward> {
+        if let PacketType::FloodRequest(request) = packet.pack_type {
+            return self.flood(request, packet.session_id);
+        }
+        let is_fragment = matches!(packet.pack_type, PacketType::MsgFragment(_));
+        let position = packet.routing_header.hop_index;
+        if packet.routing_header.hops.get(position) != Some(&self.id) {
+            return self.error(&packet, NackType::UnexpectedRecipient(self.id));
+        }
+        let Some(&next) = packet.routing_header.hops.get(position + 1) else {
+            return self.error(&packet, NackType::DestinationIsDrone);
+        };
+        if !self.neighbours.contains(&next) || !is_neighbour_alive(next) {
+            return self.error(&packet, NackType::ErrorInRouting(next));
+        }
+        if is_fragment && drop {
+            return self.error(&packet, NackType::Dropped);
+        }
+        let mut packet = packet;
+        packet.routing_header.hop_index += 1;
+        vec![Forward::To(next, packet)]
+    }
+
+    /// Answers a routing error with a nack for fragments, shortcuts anything else.
+    fn error(&self, packet: &Packet, nack_type: NackType) -> Vec<Forward> {
+        let PacketType::MsgFragment(fragment) = &packet.pack_type else {
+            return vec![Forward::Shortcut(packet.clone())];
+        };
+        let position = packet.routing_header.hop_index;
+        let mut hops = packet
+            .routing_header
+            .hops
+            .iter()
+            .take(position)
+            .copied()
+            .collect::<Vec<_>>();
+        hops.push(self.id);
+        hops.reverse();
+        let nack = Packet::new_nack(
+            SourceRoutingHeader::with_first_hop(hops),
+            packet.session_id,
+            Nack {
+                fragment_index: fragment.fragment_index,
+                nack_type,
+            },
+        );
+        match nack.routing_header.current_hop() {
+            Some(next) => vec![Forward::To(next, nack)],
+            None => vec![],
+        }
+    }
+
+    fn flood(&mut self, mut request: FloodRequest, session_id: u64) -> Vec<Forward> {
+        let sender = request.path_trace.last().map(|(id, _)| *id);
+        request.path_trace.push((self.id, NodeType::Drone));
+        let first_time = self
+            .seen_floods
+            .insert((request.initiator_id, request.flood_id));
+        let targets = self
+            .neighbours
+            .iter()
+            .copied()
+            .filter(|id| Some(*id) != sender)
+            .collect::<Vec<_>>();
+        if !first_time || targets.is_empty() {
+            return vec![flood_response(&request, session_id)];
+        }
+        targets
+            .into_iter()
+            .map(|id| {
+                let packet = Packet {
+                    routing_header: SourceRoutingHeader::empty_route(),
+                    session_id,
+                    pack_type: PacketType::FloodRequest(request.clone()),
+                };
+                Forward::To(id, packet)
+            })
+            .collect()
+    }
+}
+
+/// Builds the response to `request`, whose path trace already ends with the responder.
+fn flood_response(request: &FloodRequest, session_id: u64) -> Forward {
+    let mut hops = request
+        .path_trace
+        .iter()
+        .map(|(id, _)| *id)
+        .collect::<Vec<_>>();
+    hops.reverse();
+    let response = Packet {
+        routing_header: SourceRoutingHeader::with_first_hop(hops),
+        session_id,
+        pack_type: PacketType::FloodResponse(FloodResponse {
+            flood_id: request.flood_id,
+            path_trace: request.path_trace.clone(),
+        }),
+    };
+    match response.routing_header.current_hop() {
+        Some(next) => Forward::To(next, response),
+        None => Forward::Shortcut(response),
+    }
+}
+
+pub(crate) struct MockClient {
+    pub id: NodeId,
+    pub neighbours: BTreeSet<NodeId>,
+    pub factory: HighLevelMessageFactory,
+    pub inbox: VecDeque<Message>,
+    /// Fragments sent and not acknowledged yet, resent as they are on a nack.
+    pub unacked: Vec<Packet>,
+}
+
+impl MockClient {
+    pub fn new(id: NodeId) -> Self {
+        Self {
+            id,
+            neighbours: BTreeSet::new(),
+            factory: HighLevelMessageFactory::new(id, NodeType::Client),
+            inbox: VecDeque::new(),
+            unacked: Vec::new(),
+        }
+    }
+
+    pub fn receive(&mut self, packet: Packet) -> Vec<Forward> {
+        match packet.pack_type {
+            PacketType::FloodRequest(mut request) => {
+                request.path_trace.push((self.id, NodeType::Client));
+                vec![flood_response(&request, packet.session_id)]
+            }
+            PacketType::MsgFragment(ref fragment) => {
+                let source = packet.routing_header.hops.first().copied().unwrap_or(0);
+                let mut hops = packet.routing_header.hops.clone();
+                hops.reverse();
+                let ack = Packet {
+                    routing_header: SourceRoutingHeader::with_first_hop(hops),
+                    session_id: packet.session_id,
+                    pack_type: PacketType::Ack(Ack {
+                        fragment_index: fragment.fragment_index,
+                    }),
+                };
+                if let Some(message) =
+                    self.factory
+                        .received_fragment(fragment.clone(), packet.session_id, source)
+                {
+                    self.inbox.push_back(message);
+                }
+                match ack.routing_header.current_hop() {
+                    Some(next) => vec![Forward::To(next, ack)],
+                    None => vec![],
+                }
+            }
+            PacketType::Ack(ack) => {
+                self.unacked.retain(|sent| {
+                    sent.session_id != packet.session_id
+                        || sent.get_fragment_index() != ack.fragment_index
+                });
+                vec![]
+            }
+            PacketType::Nack(nack) => self
+                .unacked
+                .iter()
+                .find(|sent| {
+                    sent.session_id == packet.session_id
+                        && sent.get_fragment_index() == nack.fragment_index
+                })
+                .and_then(|sent| {
+                    Some(Forward::To(
+                        sent.routing_header.current_hop()?,
+                        sent.clone(),
+                    ))
+                })
+                .into_iter()
+                .collect(),
+            PacketType::FloodResponse(_) => vec![],
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use communication_server::capabilities::{Capabilities, PROTOCOL_VERSION};
+use communication_server::control_message::{ControlMessage, ControlReply, Feature};
+use communication_server::server::Server;
+use communication_server::test_support::{MockNetwork, NetworkBuilder, CLIENT, SERVER};
+use messages::high_level_messages::ServerType;
+
+fn builder() -> NetworkBuilder {
+    NetworkBuilder::single_drone(1)
+}
+
+fn capabilities<S: Server>(network: &mut MockNetwork<S>) -> Capabilities {
+    network.flood();
+    network.send_control(CLIENT, &ControlMessage::GetCapabilities);
+    network
+        .control_replies(CLIENT)
+        .into_iter()
+        .find_map(|reply| match reply {
+            ControlReply::Capabilities(capabilities) => Some(capabilities),
+            _ => None,
+        })
+        .expect("no capabilities")
+}
+
+#[test]
+fn encodes_capabilities_round_trip() {
+    let capabilities = Capabilities {
+        node_id: 3,
+        server_type: "media".to_string(),
+        roles: vec!["media".to_string()],
+        protocol_version: PROTOCOL_VERSION,
+        requests: vec!["get_server_type".to_string(), "get_media".to_string()],
+        features: vec![Feature::Deflate],
+        encryption: false,
+        max_upload_size: 4096,
+        catalog_size: 5,
+    };
+    assert_eq!(
+        Capabilities::parse(&capabilities.encode()),
+        Some(capabilities.clone())
+    );
+    // fields added by newer servers are skipped
+    let newer = format!("{};colour=blue", capabilities.encode());
+    assert_eq!(Capabilities::parse(&newer), Some(capabilities));
+    assert_eq!(Capabilities::parse("type=text"), None);
+}
+
+#[test]
+fn content_server_describes_its_catalog() {
+    let mut network = builder().build_content_server(SERVER, ServerType::Text);
+    let capabilities = capabilities(&mut network);
+    assert_eq!(capabilities, network.server.capabilities());
+    assert_eq!(capabilities.node_id, SERVER);
+    assert_eq!(capabilities.server_type, "text");
+    assert_eq!(capabilities.catalog_size, 5);
+    assert!(capabilities.requests.contains(&"get_file".to_string()));
+    assert!(!capabilities.encryption);
+    assert_eq!(capabilities.features, Feature::ALL);
+}
+
+#[test]
+fn chat_server_advertises_key_exchange() {
+    let mut network = builder().build_communication_server(SERVER);
+    let capabilities = capabilities(&mut network);
+    assert_eq!(capabilities.server_type, "chat");
+    assert_eq!(capabilities.protocol_version, PROTOCOL_VERSION);
+    assert!(capabilities
+        .requests
+        .contains(&"register_to_chat".to_string()));
+    assert!(capabilities.encryption);
+    assert_eq!(capabilities.catalog_size, 0);
+}
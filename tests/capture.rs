@@ -0,0 +1,77 @@
+use communication_server::capture::{read_capture, replay, CaptureRecord, Direction};
+use communication_server::communication_server::CommunicationServer;
+use communication_server::test_support::{MockNetwork, NetworkBuilder, CLIENT, DRONE, SERVER};
+use messages::high_level_messages::ClientMessage;
+use std::path::{Path, PathBuf};
+
+const ALICE: u8 = CLIENT;
+const BOB: u8 = 21;
+
+fn network() -> MockNetwork<CommunicationServer> {
+    let mut network = NetworkBuilder::single_drone(1)
+        .client(BOB)
+        .link(DRONE, BOB)
+        .build_communication_server(SERVER);
+    network.flood();
+    network
+}
+
+fn capture_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("capture-{}-{name}.bin", std::process::id()))
+}
+
+/// The packets the server sent, in order.
+fn outgoing(path: &Path) -> Vec<String> {
+    let records = read_capture(path).unwrap();
+    std::fs::remove_file(path).unwrap();
+    records
+        .iter()
+        .filter(|record| record.direction == Direction::Outgoing)
+        .map(|record| format!("{:?}", record.packet))
+        .collect()
+}
+
+#[test]
+fn replays_a_capture_into_a_fresh_server() {
+    let mut original = network();
+    let path = capture_path("original");
+    original.server.transport.capture.start(&path).unwrap();
+    for client in [ALICE, BOB] {
+        original
+            .request(client, ClientMessage::RegisterToChat)
+            .expect("no reply");
+    }
+    original.send_request(
+        ALICE,
+        ClientMessage::SendMessage {
+            recipient_id: BOB,
+            content: "hello".to_string(),
+        },
+    );
+    original.await_message(BOB, 10_000).expect("no message");
+    original.run_until_idle(10_000);
+    let captured = original.server.transport.capture.stop().unwrap();
+    let records: Vec<CaptureRecord> = read_capture(&path).unwrap();
+    assert_eq!(records.len() as u64, captured);
+    assert!(records
+        .windows(2)
+        .all(|pair| pair[0].elapsed_us <= pair[1].elapsed_us));
+    let expected = outgoing(&path);
+    assert!(!expected.is_empty());
+
+    // the same packets in give the same packets out
+    let mut fresh = network();
+    let replay_path = capture_path("replay");
+    fresh.server.transport.capture.start(&replay_path).unwrap();
+    let replayed = replay(&records, &mut fresh.server);
+    fresh.server.transport.capture.stop().unwrap();
+    assert_eq!(
+        replayed,
+        records
+            .iter()
+            .filter(|record| record.direction == Direction::Incoming)
+            .count()
+    );
+    assert_eq!(fresh.server.registered_clients, [ALICE, BOB]);
+    assert_eq!(outgoing(&replay_path), expected);
+}
@@ -0,0 +1,242 @@
+use base64::{engine::general_purpose, Engine as _};
+use communication_server::commands::{ServerCommand, ServerEvent};
+use communication_server::communication_server::CommunicationServer;
+use communication_server::control_message::{ControlMessage, ControlReply};
+use communication_server::reassembly::{
+    EvictionReason, Reassembly, ReassemblyLimits, REASSEMBLY_SWEEP_INTERVAL,
+};
+use communication_server::server::Server;
+use communication_server::test_support::{DroneConfig, MockNetwork, NetworkBuilder};
+use crossbeam_channel::unbounded;
+use messages::high_level_messages::MessageContent::FromServer;
+use messages::high_level_messages::{ClientMessage, ServerMessage};
+use std::time::Duration;
+use wg_2024::network::SourceRoutingHeader;
+use wg_2024::packet::{Fragment, Packet};
+
+const SERVER: u8 = 1;
+const ALICE: u8 = 20;
+const BOB: u8 = 21;
+
+fn network(seed: u64, drone: DroneConfig) -> MockNetwork<CommunicationServer> {
+    let mut network = NetworkBuilder::new(seed)
+        .drone(10, drone)
+        .drone(11, drone)
+        .client(ALICE)
+        .client(BOB)
+        .link(SERVER, 10)
+        .link(SERVER, 11)
+        .link(10, ALICE)
+        .link(11, BOB)
+        .link(10, 11)
+        .build_communication_server(SERVER);
+    network.flood();
+    network
+}
+
+fn register(network: &mut MockNetwork<CommunicationServer>, client: u8) {
+    let reply = network
+        .request(client, ClientMessage::RegisterToChat)
+        .expect("no reply");
+    assert!(matches!(
+        reply.content,
+        FromServer(ServerMessage::SuccessfulRegistration)
+    ));
+}
+
+#[test]
+fn registers_and_lists_clients() {
+    let mut network = network(1, DroneConfig::default());
+    register(&mut network, ALICE);
+    register(&mut network, BOB);
+    let reply = network
+        .request(ALICE, ClientMessage::GetClientList)
+        .expect("no reply");
+    let FromServer(ServerMessage::ClientList(mut clients)) = reply.content else {
+        panic!("unexpected reply {:?}", reply.content);
+    };
+    clients.sort_unstable();
+    assert_eq!(clients, [ALICE, BOB]);
+}
+
+#[test]
+fn delivers_messages_between_registered_clients() {
+    let mut network = network(2, DroneConfig::with_drop_rate(0.2));
+    register(&mut network, ALICE);
+    register(&mut network, BOB);
+    network.send_request(
+        ALICE,
+        ClientMessage::SendMessage {
+            recipient_id: BOB,
+            content: "hello".to_string(),
+        },
+    );
+    let message = network.await_message(BOB, 10_000).expect("no message");
+    let FromServer(ServerMessage::MessageReceived { sender_id, content }) = message.content else {
+        panic!("unexpected message {:?}", message.content);
+    };
+    assert_eq!(sender_id, ALICE);
+    assert_eq!(content, "hello");
+}
+
+#[test]
+fn reports_metrics_on_the_command_channel() {
+    let mut network = network(1, DroneConfig::default());
+    let (event_send, event_recv) = unbounded();
+    let (command_send, command_recv) = unbounded();
+    network
+        .server
+        .attach_server_channels(event_send, command_recv);
+    register(&mut network, ALICE);
+
+    command_send.send(ServerCommand::GetMetrics).unwrap();
+    network.step();
+    let Some(ServerEvent::Metrics(metrics)) = event_recv
+        .try_iter()
+        .find(|event| matches!(event, ServerEvent::Metrics(_)))
+    else {
+        panic!("no metrics");
+    };
+    assert_eq!(metrics.node_id, SERVER);
+    assert_eq!(
+        metrics
+            .counters
+            .get("messages_sent.successful_registration"),
+        Some(&1)
+    );
+}
+
+#[test]
+fn logout_unregisters_the_client() {
+    let mut network = network(3, DroneConfig::default());
+    register(&mut network, ALICE);
+    let reply = network
+        .request(ALICE, ClientMessage::Logout)
+        .expect("no reply");
+    assert!(matches!(
+        reply.content,
+        FromServer(ServerMessage::SuccessfullLogOut)
+    ));
+    assert!(network.server.registered_clients.is_empty());
+}
+
+#[test]
+fn publishes_public_keys_with_the_client_list() {
+    let mut network = network(4, DroneConfig::default());
+    register(&mut network, ALICE);
+    let key = general_purpose::STANDARD.encode([7; 32]);
+    network.send_control(ALICE, &ControlMessage::PublishKey(key.clone()));
+
+    network.send_request(BOB, ClientMessage::GetClientList);
+    network.run_until_idle(10_000);
+    assert_eq!(
+        network.control_replies(BOB),
+        [ControlReply::Keys(vec![(ALICE, key)])]
+    );
+}
+
+#[test]
+fn duplicated_fragments_deliver_a_message_once() {
+    let mut network = NetworkBuilder::new(5)
+        .drone(10, DroneConfig::with_duplicate_rate(1.0))
+        .drone(11, DroneConfig::default())
+        .client(ALICE)
+        .client(BOB)
+        .link(SERVER, 10)
+        .link(SERVER, 11)
+        .link(10, ALICE)
+        .link(11, BOB)
+        .build_communication_server(SERVER);
+    network.flood();
+    register(&mut network, ALICE);
+    register(&mut network, BOB);
+    network.send_request(
+        ALICE,
+        ClientMessage::SendMessage {
+            recipient_id: BOB,
+            content: "only once".to_string(),
+        },
+    );
+    network.run_until_idle(10_000);
+
+    assert_eq!(network.drain_inbox(BOB).len(), 1);
+    assert!(
+        network
+            .server
+            .transport
+            .metrics
+            .counter("duplicate_fragments")
+            > 0
+    );
+}
+
+#[test]
+fn evicts_messages_over_the_size_limit() {
+    let mut network = network(6, DroneConfig::default());
+    let (event_send, event_recv) = unbounded();
+    let (_command_send, command_recv) = unbounded();
+    network
+        .server
+        .attach_server_channels(event_send, command_recv);
+    register(&mut network, ALICE);
+    register(&mut network, BOB);
+    network.server.transport.reassembly = Reassembly::new(ReassemblyLimits {
+        max_message_size: 1024,
+        ..ReassemblyLimits::default()
+    });
+    network.send_request(
+        ALICE,
+        ClientMessage::SendMessage {
+            recipient_id: BOB,
+            content: "x".repeat(4096),
+        },
+    );
+    network.run_until_idle(10_000);
+
+    assert!(network.drain_inbox(BOB).is_empty());
+    assert!(network.server.transport.reassembly.is_empty());
+    assert!(event_recv.try_iter().any(|event| matches!(
+        event,
+        ServerEvent::SessionEvicted(eviction)
+            if eviction.source_id == ALICE && eviction.reason == EvictionReason::TooLarge
+    )));
+}
+
+#[test]
+fn accepts_retransmissions_of_an_expired_message() {
+    let mut network = network(7, DroneConfig::default());
+    network.server.transport.reassembly = Reassembly::new(ReassemblyLimits {
+        deadline: Duration::from_millis(1),
+        ..ReassemblyLimits::default()
+    });
+    // first of two fragments, the second never arrives
+    let fragment = Packet::new_fragment(
+        SourceRoutingHeader {
+            hop_index: 2,
+            hops: vec![ALICE, 10, SERVER],
+        },
+        7,
+        Fragment {
+            fragment_index: 0,
+            total_n_fragments: 2,
+            length: 128,
+            data: [0; 128],
+        },
+    );
+    network.server.handle_packet(fragment.clone());
+    std::thread::sleep(REASSEMBLY_SWEEP_INTERVAL);
+    network.server.transport.expire_reassembly();
+    assert!(network.server.transport.reassembly.is_empty());
+
+    // the sender starts the message over, it is not a duplicate
+    network.server.handle_packet(fragment);
+    assert_eq!(
+        network
+            .server
+            .transport
+            .metrics
+            .counter("duplicate_fragments"),
+        0
+    );
+    assert_eq!(network.server.transport.reassembly.len(), 1);
+}
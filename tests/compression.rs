@@ -0,0 +1,167 @@
+use base64::{engine::general_purpose, Engine as _};
+use communication_server::communication_server::CommunicationServer;
+use communication_server::compression::{
+    compress, compress_message, decode, decompress, escape, DEFLATE_PREFIX, MIN_COMPRESSED_LEN,
+    RAW_PREFIX,
+};
+use communication_server::control_message::{ControlMessage, Feature};
+use communication_server::test_support::{MockNetwork, NetworkBuilder, CLIENT, DRONE, SERVER};
+use messages::high_level_messages::MessageContent::FromServer;
+use messages::high_level_messages::{ClientMessage, ServerMessage};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const ALICE: u8 = CLIENT;
+const BOB: u8 = 21;
+
+fn html() -> String {
+    "<p>The quick brown fox jumps over the lazy dog.</p>\n".repeat(40)
+}
+
+fn network() -> MockNetwork<CommunicationServer> {
+    let mut network = NetworkBuilder::single_drone(1)
+        .client(BOB)
+        .link(DRONE, BOB)
+        .build_communication_server(SERVER);
+    network.flood();
+    network
+}
+
+/// The network with both clients registered and compression enabled for Bob.
+fn deflate_network() -> MockNetwork<CommunicationServer> {
+    let mut network = network();
+    for client in [ALICE, BOB] {
+        network.request(client, ClientMessage::RegisterToChat);
+    }
+    network.send_control(BOB, &ControlMessage::Enable(Feature::Deflate));
+    network.drain_inbox(BOB);
+    network
+}
+
+/// Chat contents received by `client`.
+fn received(network: &mut MockNetwork<CommunicationServer>, client: u8) -> Vec<String> {
+    network
+        .drain_inbox(client)
+        .into_iter()
+        .filter_map(|reply| match reply.content {
+            FromServer(ServerMessage::MessageReceived { sender_id, content })
+                if sender_id != SERVER =>
+            {
+                Some(content)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn compresses_text_round_trip() {
+    let compressed = compress(&html()).expect("not compressed");
+    assert!(compressed.starts_with(DEFLATE_PREFIX));
+    assert!(compressed.len() < html().len() / 4);
+    assert_eq!(decompress(&compressed), Some(html()));
+    assert_eq!(decompress(&html()), None);
+}
+
+#[test]
+fn leaves_short_and_incompressible_contents_alone() {
+    assert_eq!(compress(&"a".repeat(MIN_COMPRESSED_LEN - 1)), None);
+    // base64 of random bytes, like an encrypted chat message
+    let mut bytes = [0; 600];
+    StdRng::seed_from_u64(1).fill(&mut bytes[..]);
+    let noise = general_purpose::STANDARD.encode(bytes);
+    assert_eq!(compress(&noise), None);
+    assert!(compress_message(&ServerMessage::Media("m".to_string(), html())).is_none());
+}
+
+#[test]
+fn skips_chat_contents_that_look_encrypted() {
+    let ciphertext = general_purpose::STANDARD.encode("a".repeat(600));
+    assert!(compress(&ciphertext).is_some());
+    let message = ServerMessage::MessageReceived {
+        sender_id: 20,
+        content: ciphertext,
+    };
+    assert!(compress_message(&message).is_none());
+}
+
+#[test]
+fn escapes_contents_that_look_compressed() {
+    assert_eq!(escape(&html()), None);
+    for content in [
+        format!("{DEFLATE_PREFIX}not base64"),
+        format!("{RAW_PREFIX}x"),
+    ] {
+        let escaped = escape(&content).expect("not escaped");
+        assert_eq!(decode(&escaped), Some(content));
+    }
+    assert_eq!(decode(&html()), Some(html()));
+    assert_eq!(decode(&compress(&html()).unwrap()), Some(html()));
+}
+
+#[test]
+fn keeps_the_uncompressed_file_size() {
+    let message = ServerMessage::File {
+        file_id: "file1".to_string(),
+        size: html().len(),
+        content: html(),
+    };
+    let Some(ServerMessage::File { size, content, .. }) = compress_message(&message) else {
+        panic!("not compressed");
+    };
+    assert_eq!(size, html().len());
+    assert_eq!(decompress(&content), Some(html()));
+}
+
+#[test]
+fn compresses_only_for_clients_that_enable_it() {
+    let mut network = deflate_network();
+    for recipient_id in [ALICE, BOB] {
+        let sender = if recipient_id == ALICE { BOB } else { ALICE };
+        network.send_request(
+            sender,
+            ClientMessage::SendMessage {
+                recipient_id,
+                content: html(),
+            },
+        );
+    }
+    network.run_until_idle(10_000);
+    assert_eq!(received(&mut network, ALICE), [html()]);
+    let to_bob = received(&mut network, BOB);
+    assert_eq!(to_bob.len(), 1);
+    assert_eq!(decompress(&to_bob[0]), Some(html()));
+    assert_eq!(
+        network
+            .server
+            .transport
+            .metrics
+            .counter("compressed_messages"),
+        1
+    );
+}
+
+#[test]
+fn delivers_chat_contents_that_look_compressed_unchanged() {
+    let mut network = deflate_network();
+    let content = format!("{DEFLATE_PREFIX}hello");
+    network.send_request(
+        ALICE,
+        ClientMessage::SendMessage {
+            recipient_id: BOB,
+            content: content.clone(),
+        },
+    );
+    network.run_until_idle(10_000);
+    let to_bob = received(&mut network, BOB);
+    assert_eq!(to_bob.len(), 1);
+    assert_eq!(decode(&to_bob[0]), Some(content));
+    assert_eq!(
+        network
+            .server
+            .transport
+            .metrics
+            .counter("compressed_messages"),
+        0
+    );
+}
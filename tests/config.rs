@@ -0,0 +1,135 @@
+use communication_server::config::ServerConfig;
+use communication_server::control_message::{ControlMessage, ControlReply, Feature};
+use communication_server::fec::FecPolicy;
+use communication_server::test_support::{NetworkBuilder, CLIENT, DRONE, SERVER};
+use messages::high_level_messages::MessageContent::FromServer;
+use messages::high_level_messages::{ClientMessage, ServerMessage, ServerType};
+
+fn builder(config: ServerConfig) -> NetworkBuilder {
+    NetworkBuilder::single_drone(1).config(config)
+}
+
+#[test]
+fn parses_toml_over_the_defaults() {
+    assert_eq!(ServerConfig::from_toml(""), Ok(ServerConfig::default()));
+    let config = ServerConfig::from_toml(
+        r#"
+        flood_wait_ms = 500
+        reflood_threshold = 20
+        text_dir = "/srv/text"
+        features = ["deflate", "redirect"]
+        federation = false
+        fec_policy = { fixed = 4 }
+        "#,
+    )
+    .expect("invalid config");
+    assert_eq!(config.flood_wait_ms, 500);
+    assert_eq!(config.reflood_threshold, 20);
+    assert_eq!(config.text_dir.to_str(), Some("/srv/text"));
+    assert_eq!(config.features, [Feature::Deflate, Feature::Redirect]);
+    assert!(!config.federation);
+    assert_eq!(config.fec_policy, FecPolicy::Fixed(4));
+    assert_eq!(
+        config.queue_capacity,
+        ServerConfig::default().queue_capacity
+    );
+}
+
+#[test]
+fn refuses_invalid_configs() {
+    for toml in [
+        "reflood_threshold = 0",
+        "replication_factor = 0",
+        "text_dir = \"\"",
+        "reflood_threshold = 70000",
+        "features = [\"telepathy\"]",
+        "flood_sleep_ms = 10",
+        "fec_policy = \"always\"",
+        "fec_policy = { fixed = 0 }",
+    ] {
+        assert!(ServerConfig::from_toml(toml).is_err(), "accepted {toml}");
+    }
+    let error = ServerConfig::from_toml("dedup_window = 0").unwrap_err();
+    assert_eq!(error, "dedup_window must be greater than zero");
+}
+
+#[test]
+fn serves_files_from_the_configured_directory() {
+    let dir = std::env::temp_dir().join(format!("config-text-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("file1.html"), "<p>configured</p>").unwrap();
+    let path = dir.join("server.toml");
+    std::fs::write(&path, format!("text_dir = {:?}", dir.to_str().unwrap())).unwrap();
+    let config = ServerConfig::load(&path).expect("invalid config");
+
+    let mut network = builder(config).build_content_server(SERVER, ServerType::Text);
+    network.flood();
+    let reply = network
+        .request(CLIENT, ClientMessage::GetFile("file1".to_string()))
+        .expect("no reply");
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(matches!(
+        reply.content,
+        FromServer(ServerMessage::File { content, .. }) if content == "<p>configured</p>"
+    ));
+}
+
+#[test]
+fn only_offers_the_enabled_features() {
+    let config = ServerConfig {
+        features: vec![Feature::Deflate],
+        ..ServerConfig::default()
+    };
+    let mut network = builder(config).build_communication_server(SERVER);
+    network.flood();
+    let mut control = |control_message: ControlMessage| {
+        network.send_control(CLIENT, &control_message);
+        network.control_replies(CLIENT).pop().expect("no reply")
+    };
+    assert_eq!(
+        control(ControlMessage::Enable(Feature::Fec)),
+        ControlReply::Rejected("fec is disabled".to_string())
+    );
+    assert_eq!(
+        control(ControlMessage::Enable(Feature::Deflate)),
+        ControlReply::Enabled(Feature::Deflate)
+    );
+    let ControlReply::Capabilities(capabilities) = control(ControlMessage::GetCapabilities) else {
+        panic!("no capabilities");
+    };
+    assert_eq!(capabilities.features, [Feature::Deflate]);
+}
+
+#[test]
+fn refloods_after_the_configured_number_of_nacks() {
+    let config = ServerConfig {
+        flood_wait_ms: 0,
+        reflood_threshold: 1,
+        ..ServerConfig::default()
+    };
+    let mut network = builder(config).build_content_server(SERVER, ServerType::Text);
+    network.flood();
+    network.set_drop_rate(DRONE, 0.8);
+    network.send_request(CLIENT, ClientMessage::GetFilesList);
+    network.run_until_idle(10_000);
+    assert!(network.server.transport.metrics.counter("floods_initiated") > 1);
+}
+
+#[test]
+fn leaves_process_logging_to_the_caller() {
+    let config = ServerConfig {
+        log_level: Some("trace".to_string()),
+        ..ServerConfig::default()
+    };
+    let before = log::max_level();
+    let content = builder(config.clone()).build_content_server(SERVER, ServerType::Text);
+    let chat = builder(config.clone()).build_communication_server(SERVER);
+    assert_eq!(log::max_level(), before);
+    assert_eq!(
+        content.server.transport.config.log_level,
+        chat.server.transport.config.log_level
+    );
+
+    config.apply_logging();
+    assert_eq!(log::max_level(), log::LevelFilter::Trace);
+}
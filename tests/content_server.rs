@@ -0,0 +1,112 @@
+use communication_server::content_server::ContentServer;
+use communication_server::test_support::{DroneConfig, MockNetwork, NetworkBuilder};
+use messages::high_level_messages::MessageContent::FromServer;
+use messages::high_level_messages::{ClientMessage, ServerMessage, ServerType};
+use messages::server_commands::ContentServerEvent;
+
+const SERVER: u8 = 1;
+const CLIENT: u8 = 20;
+
+/// Two disjoint paths between the server and the client: 1-10-12-20 and 1-11-13-20.
+fn network(seed: u64, drone: DroneConfig) -> MockNetwork<ContentServer> {
+    let mut network = NetworkBuilder::new(seed)
+        .drone(10, drone)
+        .drone(11, drone)
+        .drone(12, drone)
+        .drone(13, drone)
+        .client(CLIENT)
+        .link(SERVER, 10)
+        .link(SERVER, 11)
+        .link(10, 12)
+        .link(11, 13)
+        .link(12, CLIENT)
+        .link(13, CLIENT)
+        .build_content_server(SERVER, ServerType::Text);
+    network.flood();
+    network
+}
+
+#[test]
+fn answers_server_type_after_flooding() {
+    let mut network = network(1, DroneConfig::default());
+    let reply = network
+        .request(CLIENT, ClientMessage::GetServerType)
+        .expect("no reply");
+    assert_eq!(reply.source_id, SERVER);
+    assert!(matches!(
+        reply.content,
+        FromServer(ServerMessage::ServerType(ServerType::Text))
+    ));
+}
+
+#[test]
+fn lists_the_catalog() {
+    let mut network = network(2, DroneConfig::default());
+    let reply = network
+        .request(CLIENT, ClientMessage::GetFilesList)
+        .expect("no reply");
+    let FromServer(ServerMessage::FilesList(mut files)) = reply.content else {
+        panic!("unexpected reply {:?}", reply.content);
+    };
+    files.sort();
+    assert_eq!(files, ["file1", "file2", "file3", "file4", "file5"]);
+}
+
+#[test]
+fn recovers_from_dropped_fragments() {
+    let mut network = network(3, DroneConfig::with_drop_rate(0.3));
+    for _ in 0..10 {
+        let reply = network
+            .request(CLIENT, ClientMessage::GetFilesList)
+            .expect("no reply");
+        assert!(matches!(
+            reply.content,
+            FromServer(ServerMessage::FilesList(_))
+        ));
+    }
+    assert!(
+        network
+            .server
+            .transport
+            .metrics
+            .counter("nacks_received.dropped")
+            > 0
+    );
+    assert!(network.server.transport.metrics.counter("retransmissions") > 0);
+}
+
+#[test]
+fn reroutes_around_a_crashed_drone() {
+    let mut network = network(4, DroneConfig::default());
+    network.set_crashed(12, true);
+    let reply = network
+        .request(CLIENT, ClientMessage::GetServerType)
+        .expect("no reply");
+    assert!(matches!(
+        reply.content,
+        FromServer(ServerMessage::ServerType(ServerType::Text))
+    ));
+}
+
+#[test]
+fn same_seed_gives_the_same_run() {
+    let run = |seed| {
+        let mut network = network(seed, DroneConfig::with_drop_rate(0.2));
+        network.request(CLIENT, ClientMessage::GetFilesList);
+        network.tick()
+    };
+    assert_eq!(run(5), run(5));
+}
+
+#[test]
+fn reports_nothing_to_the_controller_on_a_clean_run() {
+    let mut network = network(6, DroneConfig::default());
+    network.request(CLIENT, ClientMessage::GetServerType);
+    let events = network.events.try_iter().collect::<Vec<_>>();
+    assert!(
+        !events
+            .iter()
+            .any(|e| matches!(e, ContentServerEvent::ErrorPacketCache(..))),
+        "{events:?}"
+    );
+}
@@ -0,0 +1,110 @@
+use communication_server::ack::{AckAggregator, AckKind, ACK_BATCH, ACK_DELAY, MAX_SESSIONS};
+use communication_server::server::Server;
+use communication_server::test_support::{NetworkBuilder, CLIENT, DRONE, SERVER};
+use messages::high_level_messages::ServerType;
+use std::time::Instant;
+use wg_2024::network::SourceRoutingHeader;
+use wg_2024::packet::{Packet, PacketType};
+
+fn route() -> SourceRoutingHeader {
+    SourceRoutingHeader::with_first_hop(vec![SERVER, DRONE, CLIENT])
+}
+
+fn acked(packet: Option<Packet>) -> Option<AckKind> {
+    match packet?.pack_type {
+        PacketType::Ack(ack) => Some(AckKind::decode(ack.fragment_index)),
+        _ => None,
+    }
+}
+
+#[test]
+fn encodes_cumulative_acks_out_of_the_fragment_index_range() {
+    for kind in [AckKind::Single(5), AckKind::Cumulative(5)] {
+        assert_eq!(AckKind::decode(kind.encode()), kind);
+    }
+    assert_eq!(AckKind::Single(5).encode(), 5);
+}
+
+#[test]
+fn batches_in_order_fragments() {
+    let acks = AckAggregator::new();
+    let total = 2 * ACK_BATCH + 3;
+    for index in 0..ACK_BATCH - 1 {
+        assert_eq!(acks.received(CLIENT, 1, index, total, route()), None);
+    }
+    assert_eq!(
+        acked(acks.received(CLIENT, 1, ACK_BATCH - 1, total, route())),
+        Some(AckKind::Cumulative(ACK_BATCH))
+    );
+    // the last fragment acknowledges the rest of the message at once
+    for index in ACK_BATCH..total - 1 {
+        acks.received(CLIENT, 1, index, total, route());
+    }
+    assert_eq!(
+        acked(acks.received(CLIENT, 1, total - 1, total, route())),
+        Some(AckKind::Cumulative(total))
+    );
+}
+
+#[test]
+fn acknowledges_gaps_and_duplicates_right_away() {
+    let acks = AckAggregator::new();
+    assert_eq!(
+        acked(acks.received(CLIENT, 1, 3, 100, route())),
+        Some(AckKind::Single(3))
+    );
+    assert_eq!(acks.received(CLIENT, 1, 0, 100, route()), None);
+    assert_eq!(
+        acked(acks.received(CLIENT, 1, 0, 100, route())),
+        Some(AckKind::Cumulative(1))
+    );
+}
+
+#[test]
+fn sends_deferred_acks_after_the_delay() {
+    let acks = AckAggregator::new();
+    acks.received(CLIENT, 1, 0, 100, route());
+    assert!(acks.due(Instant::now()).is_empty());
+    let due = acks.due(Instant::now() + ACK_DELAY);
+    assert_eq!(due.len(), 1);
+    assert_eq!(acked(due.into_iter().next()), Some(AckKind::Cumulative(1)));
+}
+
+#[test]
+fn processes_each_fragment_of_a_cumulative_ack_once() {
+    let acks = AckAggregator::new();
+    assert_eq!(acks.newly_acked(1, 8), 0..8);
+    assert_eq!(acks.newly_acked(1, 12), 8..12);
+    assert!(acks.newly_acked(1, 4).is_empty());
+}
+
+#[test]
+fn forgets_the_least_recently_active_sessions() {
+    let acks = AckAggregator::new();
+    acks.newly_acked(0, 8);
+    for session_id in 1..MAX_SESSIONS as u64 {
+        acks.newly_acked(session_id, 8);
+    }
+    // the first session is still acked, the second is the idle one
+    assert_eq!(acks.newly_acked(0, 12), 8..12);
+    acks.newly_acked(MAX_SESSIONS as u64, 8);
+    assert_eq!(acks.newly_acked(0, 16), 12..16);
+    assert_eq!(acks.newly_acked(1, 8), 0..8);
+}
+
+#[test]
+fn enables_cumulative_acks_for_peers_sending_them() {
+    let mut network =
+        NetworkBuilder::single_drone(0).build_content_server(SERVER, ServerType::Text);
+    network.flood();
+    assert!(!network.server.transport.acks.supports(CLIENT));
+    network.server.handle_packet(Packet::new_ack(
+        SourceRoutingHeader {
+            hop_index: 2,
+            hops: vec![CLIENT, DRONE, SERVER],
+        },
+        1,
+        AckKind::Cumulative(0).encode(),
+    ));
+    assert!(network.server.transport.acks.supports(CLIENT));
+}
@@ -0,0 +1,122 @@
+use communication_server::content_server::ContentServer;
+use communication_server::control_message::{ControlMessage, ControlReply, Feature};
+use communication_server::fec::{self, FecPolicy, ParityBlock, MAX_BLOCK};
+use communication_server::test_support::{MockNetwork, NetworkBuilder, CLIENT, DRONE, SERVER};
+use communication_server::trace::{MessageTracer, TraceEventKind};
+use messages::high_level_messages::ServerType;
+use wg_2024::network::SourceRoutingHeader;
+use wg_2024::packet::{Fragment, Packet, PacketType};
+
+fn fragment(fragment_index: u64, total_n_fragments: u64, byte: u8) -> Packet {
+    let length = 100 + u8::try_from(fragment_index).unwrap();
+    let mut data = [0; 128];
+    data[..usize::from(length)].fill(byte);
+    Packet::new_fragment(
+        SourceRoutingHeader::with_first_hop(vec![SERVER, DRONE, CLIENT]),
+        7,
+        Fragment {
+            fragment_index,
+            total_n_fragments,
+            length,
+            data,
+        },
+    )
+}
+
+fn fragments(packets: &[Packet]) -> Vec<Fragment> {
+    packets
+        .iter()
+        .filter_map(|packet| match &packet.pack_type {
+            PacketType::MsgFragment(fragment) => Some(fragment.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn network() -> MockNetwork<ContentServer> {
+    let mut network =
+        NetworkBuilder::single_drone(1).build_content_server(SERVER, ServerType::Text);
+    network.flood();
+    network
+}
+
+#[test]
+fn encodes_parity_blocks_out_of_the_fragment_index_range() {
+    let block = ParityBlock { start: 12, len: 4 };
+    assert!(fec::is_parity(block.encode()));
+    assert_eq!(ParityBlock::decode(block.encode()), Some(block));
+    assert_eq!(ParityBlock::decode(12), None);
+    assert!(block.contains(15));
+    assert!(!block.contains(16));
+}
+
+#[test]
+fn recovers_one_lost_fragment_per_block() {
+    let packets = (0..10).map(|i| fragment(i, 10, i as u8 + 1)).collect();
+    let sent = fragments(&fec::with_parity(packets, 4));
+    // blocks 0..4, 4..8 and 8..10, each followed by its parity
+    assert_eq!(sent.len(), 13);
+    let parity = &sent[9];
+    assert_eq!(
+        ParityBlock::decode(parity.fragment_index),
+        Some(ParityBlock { start: 4, len: 4 })
+    );
+
+    let lost = sent[6].clone();
+    let received = [sent[5].clone(), sent[7].clone(), sent[8].clone()];
+    let recovered = fec::recover(parity, &received).expect("not recovered");
+    assert_eq!(recovered.fragment_index, lost.fragment_index);
+    assert_eq!(recovered.length, lost.length);
+    assert_eq!(recovered.data, lost.data);
+
+    // two losses in the same block are beyond a single parity fragment
+    assert_eq!(fec::recover(parity, &received[..2]), None);
+}
+
+#[test]
+fn sizes_blocks_from_the_loss_rate() {
+    assert_eq!(FecPolicy::Off.block_size(0.5), None);
+    assert_eq!(FecPolicy::Fixed(100).block_size(0.0), Some(MAX_BLOCK));
+    assert_eq!(FecPolicy::Adaptive.block_size(0.0), None);
+    assert_eq!(FecPolicy::Adaptive.block_size(0.05), Some(10));
+    assert_eq!(FecPolicy::Adaptive.block_size(0.5), Some(2));
+}
+
+#[test]
+fn enables_forward_error_correction_on_request() {
+    let mut network = network();
+    network.send_control(CLIENT, &ControlMessage::Enable(Feature::Fec));
+    let reply = network
+        .control_replies(CLIENT)
+        .into_iter()
+        .next()
+        .expect("no reply");
+    assert_eq!(reply, ControlReply::Enabled(Feature::Fec));
+    assert!(network.server.transport.client_features[&CLIENT].contains(&Feature::Fec));
+}
+
+#[test]
+fn completes_a_trace_only_once_the_data_fragments_are_acked() {
+    let tracer = MessageTracer::default();
+    let route = [SERVER, DRONE, CLIENT];
+    let parity = ParityBlock { start: 0, len: 2 }.encode();
+    tracer.fragmented(7, 2, &route);
+    for fragment_index in [0, 1, parity] {
+        tracer.sent(7, fragment_index, &route);
+    }
+    let completed = || {
+        tracer
+            .events()
+            .iter()
+            .any(|event| event.kind == TraceEventKind::Completed)
+    };
+    tracer.ack_received(7, parity, &route);
+    tracer.ack_received(7, 0, &route);
+    assert!(!completed());
+    tracer.ack_received(7, 1, &route);
+    assert!(completed());
+    assert!(!tracer
+        .events()
+        .iter()
+        .any(|event| fec::is_parity(event.fragment_index)));
+}
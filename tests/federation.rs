@@ -0,0 +1,160 @@
+use communication_server::communication_server::CommunicationServer;
+use communication_server::control_message::ControlMessage;
+use communication_server::test_support::{MockNetwork, NetworkBuilder, CLIENT, PEER, SERVER};
+use messages::high_level_messages::MessageContent::FromServer;
+use messages::high_level_messages::{ClientMessage, Message, ServerMessage, ServerType};
+
+const ALICE: u8 = CLIENT;
+/// Registered on the peer.
+const CAROL: u8 = 40;
+
+fn network() -> MockNetwork<CommunicationServer> {
+    let mut network = NetworkBuilder::with_peer(1).build_communication_server(SERVER);
+    network.flood();
+    let reply = network
+        .request(ALICE, ClientMessage::RegisterToChat)
+        .expect("no reply");
+    assert!(matches!(
+        reply.content,
+        FromServer(ServerMessage::SuccessfulRegistration)
+    ));
+    network
+}
+
+fn control(request: &ClientMessage) -> Option<ControlMessage> {
+    match request {
+        ClientMessage::SendMessage { content, .. } => ControlMessage::parse(content),
+        _ => None,
+    }
+}
+
+/// Makes the peer known as a chat server with CAROL registered on it.
+fn federate(network: &mut MockNetwork<CommunicationServer>) {
+    network.discover_server(PEER);
+    assert!(matches!(
+        network.requests_to(PEER)[..],
+        [ClientMessage::GetServerType]
+    ));
+    network.server.handle_message(Message {
+        source_id: PEER,
+        session_id: 0,
+        content: FromServer(ServerMessage::ServerType(ServerType::Chat)),
+    });
+    // the new peer learns who is registered here
+    let announced = network
+        .requests_to(PEER)
+        .iter()
+        .filter_map(control)
+        .collect::<Vec<_>>();
+    assert_eq!(announced, [ControlMessage::Clients(vec![ALICE])]);
+    network.send_control(PEER, &ControlMessage::Clients(vec![CAROL]));
+}
+
+#[test]
+fn encodes_federation_messages_round_trip() {
+    for control_message in [
+        ControlMessage::Clients(vec![1, 2, 3]),
+        ControlMessage::Clients(Vec::new()),
+        ControlMessage::Relay {
+            sender_id: 1,
+            recipient_id: 2,
+            content: "a:b:c".to_string(),
+        },
+        ControlMessage::RelayFailed {
+            sender_id: 1,
+            recipient_id: 2,
+        },
+    ] {
+        assert_eq!(
+            ControlMessage::parse(&control_message.encode()),
+            Some(control_message)
+        );
+    }
+}
+
+#[test]
+fn lists_and_relays_to_clients_of_peers() {
+    let mut network = network();
+    federate(&mut network);
+    assert_eq!(network.server.federation.home_of(CAROL), Some(PEER));
+
+    let reply = network
+        .request(ALICE, ClientMessage::GetClientList)
+        .expect("no reply");
+    let FromServer(ServerMessage::ClientList(mut clients)) = reply.content else {
+        panic!("unexpected reply {:?}", reply.content);
+    };
+    clients.sort_unstable();
+    assert_eq!(clients, [ALICE, CAROL]);
+
+    network.send_request(
+        ALICE,
+        ClientMessage::SendMessage {
+            recipient_id: CAROL,
+            content: "hi: carol".to_string(),
+        },
+    );
+    let relayed = network
+        .requests_to(PEER)
+        .iter()
+        .filter_map(control)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        relayed,
+        [ControlMessage::Relay {
+            sender_id: ALICE,
+            recipient_id: CAROL,
+            content: "hi: carol".to_string(),
+        }]
+    );
+}
+
+#[test]
+fn delivers_messages_relayed_by_peers() {
+    let mut network = network();
+    federate(&mut network);
+    network.send_control(
+        PEER,
+        &ControlMessage::Relay {
+            sender_id: CAROL,
+            recipient_id: ALICE,
+            content: "hello alice".to_string(),
+        },
+    );
+    let delivered = network.drain_inbox(ALICE);
+    assert!(delivered.iter().any(|message| matches!(
+        &message.content,
+        FromServer(ServerMessage::MessageReceived { sender_id: CAROL, content })
+            if content == "hello alice"
+    )));
+
+    // a recipient that is not registered here is reported back to the peer
+    network.send_control(
+        PEER,
+        &ControlMessage::Relay {
+            sender_id: CAROL,
+            recipient_id: 99,
+            content: "lost".to_string(),
+        },
+    );
+    let failed = network
+        .requests_to(PEER)
+        .iter()
+        .filter_map(control)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        failed,
+        [ControlMessage::RelayFailed {
+            sender_id: CAROL,
+            recipient_id: 99,
+        }]
+    );
+}
+
+#[test]
+fn ignores_federation_messages_from_other_nodes() {
+    let mut network = network();
+    network.send_control(PEER, &ControlMessage::Clients(vec![CAROL]));
+    assert_eq!(network.server.federation.home_of(CAROL), None);
+    assert!(network.server.federation.remote_clients().is_empty());
+}
@@ -0,0 +1,125 @@
+use communication_server::control_message::{ControlMessage, ControlReply, Feature};
+use communication_server::hybrid_server::{HybridServer, Roles};
+use communication_server::test_support::{MockNetwork, NetworkBuilder, CLIENT, DRONE, SERVER};
+use messages::high_level_messages::MessageContent::FromServer;
+use messages::high_level_messages::{ClientMessage, ServerMessage, ServerType};
+
+const ALICE: u8 = CLIENT;
+const BOB: u8 = 21;
+
+fn network(roles: Roles) -> MockNetwork<HybridServer> {
+    let mut network = NetworkBuilder::single_drone(1)
+        .client(BOB)
+        .link(DRONE, BOB)
+        .build_hybrid_server(SERVER, roles);
+    network.flood();
+    network
+}
+
+#[test]
+fn answers_chat_when_chat_is_one_of_the_roles() {
+    assert!(matches!(Roles::ALL.server_type(), ServerType::Chat));
+    let content = Roles {
+        chat: false,
+        ..Roles::ALL
+    };
+    assert!(matches!(content.server_type(), ServerType::Text));
+    let mut network = network(Roles::ALL);
+    let reply = network
+        .request(ALICE, ClientMessage::GetServerType)
+        .expect("no reply");
+    assert!(matches!(
+        reply.content,
+        FromServer(ServerMessage::ServerType(ServerType::Chat))
+    ));
+}
+
+#[test]
+fn serves_the_catalogs_and_chat_from_one_node() {
+    let mut network = network(Roles::ALL);
+    let reply = network
+        .request(ALICE, ClientMessage::GetFilesList)
+        .expect("no reply");
+    let FromServer(ServerMessage::FilesList(files)) = reply.content else {
+        panic!("unexpected reply {:?}", reply.content);
+    };
+    assert_eq!(files.len(), 10);
+    assert!(files.contains(&"file1".to_string()) && files.contains(&"media1".to_string()));
+
+    for client in [ALICE, BOB] {
+        let reply = network
+            .request(client, ClientMessage::RegisterToChat)
+            .expect("no reply");
+        assert!(matches!(
+            reply.content,
+            FromServer(ServerMessage::SuccessfulRegistration)
+        ));
+    }
+    network.send_request(
+        ALICE,
+        ClientMessage::SendMessage {
+            recipient_id: BOB,
+            content: "hello".to_string(),
+        },
+    );
+    let reply = network.await_message(BOB, 10_000).expect("no message");
+    assert!(matches!(
+        reply.content,
+        FromServer(ServerMessage::MessageReceived {
+            sender_id: ALICE,
+            ..
+        })
+    ));
+}
+
+#[test]
+fn ignores_requests_for_disabled_roles() {
+    let mut network = network(Roles {
+        text: true,
+        media: false,
+        chat: false,
+    });
+    assert_eq!(network.server.file_list.len(), 5);
+    assert!(network
+        .request(ALICE, ClientMessage::RegisterToChat)
+        .is_none());
+    assert!(network.server.registered_clients.is_empty());
+    assert!(network
+        .request(ALICE, ClientMessage::GetMedia("media1".to_string()))
+        .is_none());
+    let capabilities = network.server.capabilities();
+    assert_eq!(capabilities.server_type, "text");
+    assert_eq!(capabilities.roles, ["text"]);
+    assert!(!capabilities
+        .requests
+        .contains(&"register_to_chat".to_string()));
+}
+
+#[test]
+fn only_reads_files_of_the_catalog() {
+    let mut network = network(Roles::ALL);
+    network.send_control(ALICE, &ControlMessage::Enable(Feature::Fec));
+    assert_eq!(
+        network.control_replies(ALICE),
+        [ControlReply::Enabled(Feature::Fec)]
+    );
+    for request in [
+        ClientMessage::GetMedia("../../Cargo.toml".to_string()),
+        ClientMessage::GetFile("/etc/hostname".to_string()),
+    ] {
+        let reply = network.request(ALICE, request).expect("no reply");
+        let FromServer(ServerMessage::MessageReceived { sender_id, content }) = reply.content
+        else {
+            panic!("unexpected reply {:?}", reply.content);
+        };
+        assert_eq!(sender_id, SERVER);
+        assert!(matches!(
+            ControlReply::parse(&content),
+            Some(ControlReply::NotFound(_))
+        ));
+    }
+    // clients that never enabled a feature do not know control replies
+    assert!(network
+        .request(BOB, ClientMessage::GetFile("/etc/hostname".to_string()))
+        .is_none());
+}
@@ -0,0 +1,140 @@
+use communication_server::commands::{ServerCommand, ServerEvent};
+use communication_server::server::Server;
+use communication_server::test_support::{DroneConfig, NetworkBuilder};
+use crossbeam_channel::{never, unbounded};
+use messages::high_level_messages::MessageContent::{FromClient, FromServer};
+use messages::high_level_messages::{ClientMessage, Message, ServerMessage, ServerType};
+use std::collections::BTreeSet;
+use wg_2024::network::SourceRoutingHeader;
+use wg_2024::packet::{Fragment, Packet, PacketType};
+
+const SERVER: u8 = 1;
+const CLIENT: u8 = 20;
+
+fn builder() -> NetworkBuilder {
+    NetworkBuilder::new(1)
+        .drone(10, DroneConfig::default())
+        .drone(11, DroneConfig::default())
+        .client(CLIENT)
+        .link(SERVER, 10)
+        .link(SERVER, 11)
+        .link(10, CLIENT)
+        .link(11, CLIENT)
+}
+
+#[test]
+fn reports_neighbours_topology_and_clients() {
+    let mut network = builder().build_communication_server(SERVER);
+    network.flood();
+    let reply = network
+        .request(CLIENT, ClientMessage::RegisterToChat)
+        .expect("no reply");
+    assert!(matches!(
+        reply.content,
+        FromServer(ServerMessage::SuccessfulRegistration)
+    ));
+    let (event_send, events) = unbounded();
+    network.server.attach_server_channels(event_send, never());
+
+    network
+        .server
+        .handle_server_command(ServerCommand::GetNeighbours);
+    assert!(matches!(
+        events.try_recv(),
+        Ok(ServerEvent::Neighbours(neighbours)) if neighbours == [10, 11]
+    ));
+
+    network
+        .server
+        .handle_server_command(ServerCommand::GetTopology);
+    let Ok(ServerEvent::Topology(topology)) = events.try_recv() else {
+        panic!("no topology");
+    };
+    assert_eq!(topology.drones(), &BTreeSet::from([10, 11]));
+    assert_eq!(topology.links()[&CLIENT], BTreeSet::from([10, 11]));
+
+    network
+        .server
+        .handle_server_command(ServerCommand::GetClients);
+    assert!(matches!(
+        events.try_recv(),
+        Ok(ServerEvent::Clients(clients)) if clients == [CLIENT]
+    ));
+    network
+        .server
+        .handle_server_command(ServerCommand::GetCatalog);
+    assert!(matches!(
+        events.try_recv(),
+        Ok(ServerEvent::Catalog(catalog)) if catalog.is_empty()
+    ));
+}
+
+#[test]
+fn reports_catalog_pending_fragments_and_reassemblies() {
+    let mut network = builder().build_content_server(SERVER, ServerType::Text);
+    network.flood();
+    let (event_send, events) = unbounded();
+    network.server.attach_server_channels(event_send, never());
+
+    network
+        .server
+        .handle_server_command(ServerCommand::GetCatalog);
+    let Ok(ServerEvent::Catalog(catalog)) = events.try_recv() else {
+        panic!("no catalog");
+    };
+    assert_eq!(catalog.len(), 5);
+    assert_eq!(catalog["file1"], "file1.html");
+    network
+        .server
+        .handle_server_command(ServerCommand::GetClients);
+    assert!(matches!(
+        events.try_recv(),
+        Ok(ServerEvent::Clients(clients)) if clients.is_empty()
+    ));
+
+    // the reply is not delivered yet, so its fragment is waiting for an ack
+    network.server.handle_message(Message {
+        source_id: CLIENT,
+        session_id: 0,
+        content: FromClient(ClientMessage::GetServerType),
+    });
+    network
+        .server
+        .handle_server_command(ServerCommand::GetPendingCache);
+    let Ok(ServerEvent::PendingCache(pending)) = events.try_recv() else {
+        panic!("no pending cache");
+    };
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].routing_header.destination(), Some(CLIENT));
+
+    // first fragment of a two fragment request
+    network.server.handle_packet(Packet {
+        routing_header: SourceRoutingHeader {
+            hop_index: 2,
+            hops: vec![CLIENT, 10, SERVER],
+        },
+        session_id: 7,
+        pack_type: PacketType::MsgFragment(Fragment {
+            fragment_index: 0,
+            total_n_fragments: 2,
+            length: 128,
+            data: [0; 128],
+        }),
+    });
+    network
+        .server
+        .handle_server_command(ServerCommand::GetReassemblies);
+    let Ok(ServerEvent::Reassemblies(progress)) = events.try_recv() else {
+        panic!("no reassemblies");
+    };
+    assert_eq!(progress.len(), 1);
+    assert_eq!(
+        (
+            progress[0].source_id,
+            progress[0].session_id,
+            progress[0].received,
+            progress[0].total_n_fragments
+        ),
+        (CLIENT, 7, 1, 2)
+    );
+}
@@ -0,0 +1,195 @@
+//! Feeds the servers randomly generated packets, most of them malformed, and checks
+//! that they never panic.
+
+use communication_server::commands::ServerEvent;
+use communication_server::server::Server;
+use communication_server::test_support::{DroneConfig, MockNetwork, NetworkBuilder};
+use crossbeam_channel::unbounded;
+use messages::high_level_messages::ServerType;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use wg_2024::network::SourceRoutingHeader;
+use wg_2024::packet::{
+    Ack, FloodRequest, FloodResponse, Fragment, Nack, NackType, NodeType, Packet, PacketType,
+};
+
+const SERVER: u8 = 1;
+const CLIENT: u8 = 20;
+const PACKETS_PER_RUN: usize = 1_000;
+
+fn builder(seed: u64) -> NetworkBuilder {
+    NetworkBuilder::new(seed)
+        .drone(10, DroneConfig::default())
+        .drone(11, DroneConfig::default())
+        .client(CLIENT)
+        .link(SERVER, 10)
+        .link(10, 11)
+        .link(11, CLIENT)
+}
+
+/// Node ids likely to collide with the real ones, plus arbitrary ones.
+fn node_id(rng: &mut StdRng) -> u8 {
+    match rng.gen_range(0..4) {
+        0 => SERVER,
+        1 => rng.gen_range(10..12),
+        2 => CLIENT,
+        _ => rng.gen(),
+    }
+}
+
+fn node_type(rng: &mut StdRng) -> NodeType {
+    match rng.gen_range(0..3) {
+        0 => NodeType::Client,
+        1 => NodeType::Drone,
+        _ => NodeType::Server,
+    }
+}
+
+fn nack_type(rng: &mut StdRng) -> NackType {
+    match rng.gen_range(0..4) {
+        0 => NackType::ErrorInRouting(node_id(rng)),
+        1 => NackType::DestinationIsDrone,
+        2 => NackType::UnexpectedRecipient(node_id(rng)),
+        _ => NackType::Dropped,
+    }
+}
+
+/// Small values most of the time, extreme ones sometimes.
+fn index(rng: &mut StdRng) -> u64 {
+    match rng.gen_range(0..8) {
+        0 => u64::MAX,
+        1 => rng.gen(),
+        _ => rng.gen_range(0..4),
+    }
+}
+
+fn arbitrary_packet(rng: &mut StdRng) -> Packet {
+    let hops = (0..rng.gen_range(0..5))
+        .map(|_| node_id(rng))
+        .collect::<Vec<_>>();
+    let hop_index = rng.gen_range(0..6);
+    let path_trace = (0..rng.gen_range(0..4))
+        .map(|_| (node_id(rng), node_type(rng)))
+        .collect::<Vec<_>>();
+    let pack_type = match rng.gen_range(0..5) {
+        0 => {
+            let mut data = [0; 128];
+            rng.fill(&mut data[..]);
+            PacketType::MsgFragment(Fragment {
+                fragment_index: index(rng),
+                total_n_fragments: index(rng),
+                length: rng.gen(),
+                data,
+            })
+        }
+        1 => PacketType::Ack(Ack {
+            fragment_index: index(rng),
+        }),
+        2 => PacketType::Nack(Nack {
+            fragment_index: index(rng),
+            nack_type: nack_type(rng),
+        }),
+        3 => PacketType::FloodRequest(FloodRequest {
+            flood_id: rng.gen(),
+            initiator_id: node_id(rng),
+            path_trace,
+        }),
+        _ => PacketType::FloodResponse(FloodResponse {
+            flood_id: rng.gen(),
+            path_trace,
+        }),
+    };
+    Packet {
+        routing_header: SourceRoutingHeader { hop_index, hops },
+        session_id: index(rng),
+        pack_type,
+    }
+}
+
+fn survives_arbitrary_packets<S: Server>(mut network: MockNetwork<S>, seed: u64) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    network.flood();
+    for _ in 0..PACKETS_PER_RUN {
+        let packet = arbitrary_packet(&mut rng);
+        network.server.handle_packet(packet);
+        network.run_until_idle(100);
+    }
+}
+
+#[test]
+fn content_server_survives_arbitrary_packets() {
+    for seed in 0..4 {
+        survives_arbitrary_packets(
+            builder(seed).build_content_server(SERVER, ServerType::Text),
+            seed,
+        );
+    }
+}
+
+#[test]
+fn communication_server_survives_arbitrary_packets() {
+    for seed in 0..4 {
+        survives_arbitrary_packets(builder(seed).build_communication_server(SERVER), seed);
+    }
+}
+
+#[test]
+fn reports_malformed_packets() {
+    let mut network = builder(0).build_content_server(SERVER, ServerType::Text);
+    let (event_send, event_recv) = unbounded();
+    let (_command_send, command_recv) = unbounded();
+    network
+        .server
+        .attach_server_channels(event_send, command_recv);
+
+    let malformed = [
+        Packet::new_nack(
+            SourceRoutingHeader {
+                hop_index: 0,
+                hops: vec![],
+            },
+            1,
+            Nack {
+                fragment_index: 0,
+                nack_type: NackType::Dropped,
+            },
+        ),
+        Packet {
+            routing_header: SourceRoutingHeader {
+                hop_index: 7,
+                hops: vec![10, SERVER],
+            },
+            session_id: 2,
+            pack_type: PacketType::Ack(Ack { fragment_index: 0 }),
+        },
+        Packet {
+            routing_header: SourceRoutingHeader {
+                hop_index: 1,
+                hops: vec![10, SERVER],
+            },
+            session_id: 3,
+            pack_type: PacketType::MsgFragment(Fragment {
+                fragment_index: 4,
+                total_n_fragments: 2,
+                length: 0,
+                data: [0; 128],
+            }),
+        },
+    ];
+    for packet in malformed {
+        network.server.handle_packet(packet);
+    }
+    let reported = event_recv
+        .try_iter()
+        .filter(|event| matches!(event, ServerEvent::MalformedPacket(..)))
+        .count();
+    assert_eq!(reported, 3);
+    assert_eq!(
+        network
+            .server
+            .transport
+            .metrics
+            .counter("malformed_packets"),
+        3
+    );
+}
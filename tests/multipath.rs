@@ -0,0 +1,95 @@
+use communication_server::route_stats::RouteStats;
+use communication_server::test_support::{DroneConfig, NetworkBuilder};
+use communication_server::topology::{Topology, MAX_PATHS};
+use messages::high_level_messages::MessageContent::FromServer;
+use messages::high_level_messages::{ClientMessage, ServerMessage};
+use wg_2024::packet::NodeType;
+
+const SERVER: u8 = 1;
+const ALICE: u8 = 20;
+const BOB: u8 = 21;
+
+fn diamond() -> Topology {
+    let mut topology = Topology::new();
+    for drone in [10, 11, 12] {
+        topology.add_path_trace(&[
+            (SERVER, NodeType::Server),
+            (drone, NodeType::Drone),
+            (BOB, NodeType::Client),
+        ]);
+    }
+    topology
+}
+
+#[test]
+fn finds_routes_sharing_no_drone() {
+    let topology = diamond();
+    let paths = topology.disjoint_paths(SERVER, BOB, MAX_PATHS);
+    assert_eq!(
+        paths,
+        [
+            vec![SERVER, 10, BOB],
+            vec![SERVER, 11, BOB],
+            vec![SERVER, 12, BOB]
+        ]
+    );
+    assert_eq!(topology.disjoint_paths(SERVER, BOB, 2).len(), 2);
+}
+
+#[test]
+fn ranks_routes_by_delivery_and_round_trip_time() {
+    let topology = diamond();
+    let stats = RouteStats::new();
+    for fragment in 0..4 {
+        stats.sent(1, fragment, &[SERVER, 10, BOB]);
+        stats.nacked(1, fragment);
+        stats.sent(2, fragment, &[SERVER, 11, BOB]);
+        stats.acked(2, fragment);
+    }
+    assert_eq!(stats.path(&[SERVER, 10, BOB]).nacked, 4);
+    assert_eq!(stats.path(&[SERVER, 11, BOB]).acked, 4);
+
+    let ranked = stats.ranked(topology.disjoint_paths(SERVER, BOB, MAX_PATHS));
+    assert_eq!(ranked[0], [SERVER, 11, BOB]);
+    assert!(!ranked.contains(&vec![SERVER, 10, BOB]));
+}
+
+#[test]
+fn delivers_large_messages_striped_across_lossy_routes() {
+    let mut network = NetworkBuilder::new(9)
+        .drone(10, DroneConfig::with_drop_rate(0.3))
+        .drone(11, DroneConfig::default())
+        .drone(12, DroneConfig::default())
+        .client(ALICE)
+        .client(BOB)
+        .link(SERVER, 10)
+        .link(SERVER, 11)
+        .link(SERVER, 12)
+        .link(10, BOB)
+        .link(11, BOB)
+        .link(12, ALICE)
+        .build_communication_server(SERVER);
+    network.flood();
+    for client in [ALICE, BOB] {
+        network
+            .request(client, ClientMessage::RegisterToChat)
+            .expect("no reply");
+    }
+    let content = "multipath ".repeat(300);
+    network.send_request(
+        ALICE,
+        ClientMessage::SendMessage {
+            recipient_id: BOB,
+            content: content.clone(),
+        },
+    );
+    let message = network.await_message(BOB, 10_000).expect("no message");
+    let FromServer(ServerMessage::MessageReceived {
+        content: received, ..
+    }) = message.content
+    else {
+        panic!("unexpected message {:?}", message.content);
+    };
+    assert_eq!(received, content);
+    assert!(network.server.transport.metrics.counter("retransmissions") > 0);
+}
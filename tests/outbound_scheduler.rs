@@ -0,0 +1,78 @@
+use communication_server::scheduler::{OutboundScheduler, TrafficClass};
+use crossbeam_channel::bounded;
+use std::collections::HashMap;
+use wg_2024::network::SourceRoutingHeader;
+use wg_2024::packet::{Fragment, Packet};
+
+const NEIGHBOUR: u8 = 10;
+
+fn fragment(session_id: u64, fragment_index: u64) -> Packet {
+    Packet::new_fragment(
+        SourceRoutingHeader::with_first_hop(vec![1, NEIGHBOUR, 20]),
+        session_id,
+        Fragment {
+            fragment_index,
+            total_n_fragments: 64,
+            length: 0,
+            data: [0; 128],
+        },
+    )
+}
+
+#[test]
+fn interleaves_sessions_on_a_slow_neighbour() {
+    let scheduler = OutboundScheduler::new(128);
+    let (send, recv) = bounded(1);
+    let senders = HashMap::from([(NEIGHBOUR, send)]);
+    for index in 0..64 {
+        scheduler.enqueue(NEIGHBOUR, fragment(1, index)).unwrap();
+    }
+    scheduler.enqueue(NEIGHBOUR, fragment(2, 0)).unwrap();
+
+    let mut sessions = Vec::new();
+    while !scheduler.is_empty() {
+        assert!(scheduler.flush(&senders).is_empty());
+        sessions.push(recv.try_recv().unwrap().session_id);
+    }
+    assert_eq!(sessions.len(), 65);
+    assert_eq!(sessions[..3], [1, 2, 1]);
+}
+
+#[test]
+fn rejects_packets_beyond_the_queue_capacity() {
+    let scheduler = OutboundScheduler::new(2);
+    let (send, _recv) = bounded(0);
+    let senders = HashMap::from([(NEIGHBOUR, send)]);
+    scheduler.enqueue(NEIGHBOUR, fragment(1, 0)).unwrap();
+    scheduler.enqueue(NEIGHBOUR, fragment(1, 1)).unwrap();
+    assert!(scheduler.enqueue(NEIGHBOUR, fragment(1, 2)).is_err());
+
+    assert!(scheduler.flush(&senders).is_empty());
+    assert_eq!(scheduler.queued_for(NEIGHBOUR), 2);
+}
+
+#[test]
+fn sends_control_and_interactive_traffic_before_bulk() {
+    let scheduler = OutboundScheduler::new(128);
+    let (send, recv) = bounded(1);
+    let senders = HashMap::from([(NEIGHBOUR, send)]);
+    scheduler.set_bulk(1);
+    for index in 0..8 {
+        scheduler.enqueue(NEIGHBOUR, fragment(1, index)).unwrap();
+    }
+    scheduler.enqueue(NEIGHBOUR, fragment(2, 0)).unwrap();
+    let ack = Packet::new_ack(
+        SourceRoutingHeader::with_first_hop(vec![1, NEIGHBOUR, 20]),
+        3,
+        0,
+    );
+    scheduler.enqueue(NEIGHBOUR, ack).unwrap();
+    assert_eq!(scheduler.queued_in(TrafficClass::Bulk), 8);
+
+    let mut sessions = Vec::new();
+    while !scheduler.is_empty() {
+        scheduler.flush(&senders);
+        sessions.push(recv.try_recv().unwrap().session_id);
+    }
+    assert_eq!(sessions[..3], [3, 2, 1]);
+}
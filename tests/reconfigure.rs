@@ -0,0 +1,174 @@
+use communication_server::commands::{ServerCommand, ServerEvent};
+use communication_server::config::ServerConfig;
+use communication_server::content_server::ContentServer;
+use communication_server::fec::FecPolicy;
+use communication_server::server::Server;
+use communication_server::test_support::{MockNetwork, NetworkBuilder, CLIENT, DRONE, SERVER};
+use crossbeam_channel::{never, unbounded, Receiver};
+use messages::high_level_messages::MessageContent::FromClient;
+use messages::high_level_messages::{ClientMessage, Message, ServerType};
+
+fn network() -> (MockNetwork<ContentServer>, Receiver<ServerEvent>) {
+    // a file large enough to be sent as more fragments than the window starts with
+    let dir = std::env::temp_dir().join(format!("reconfigure-text-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("file1.html"), "<p>fragmented</p>".repeat(100)).unwrap();
+    let mut network = NetworkBuilder::single_drone(1)
+        .config(ServerConfig {
+            text_dir: dir,
+            ..ServerConfig::default()
+        })
+        .build_content_server(SERVER, ServerType::Text);
+    network.flood();
+    let (event_send, event_recv) = unbounded();
+    network.server.attach_server_channels(event_send, never());
+    (network, event_recv)
+}
+
+fn reconfigure(
+    network: &mut MockNetwork<ContentServer>,
+    events: &Receiver<ServerEvent>,
+    changes: &str,
+) -> Result<ServerConfig, String> {
+    network
+        .server
+        .handle_server_command(ServerCommand::Reconfigure(changes.to_string()));
+    match events.try_recv() {
+        Ok(ServerEvent::Reconfigured(result)) => result,
+        other => panic!("unexpected event {other:?}"),
+    }
+}
+
+/// Hands a request straight to the server, so its reply waits in the outbox.
+fn request_undelivered(network: &mut MockNetwork<ContentServer>, request: ClientMessage) {
+    network.server.handle_message(Message {
+        source_id: CLIENT,
+        session_id: 0,
+        content: FromClient(request),
+    });
+}
+
+/// Fragments of the reply to a `GetFile` of file1.
+const FILE_FRAGMENTS: usize = 14;
+
+#[test]
+fn confirms_the_effective_config() {
+    let (mut network, events) = network();
+    let config = reconfigure(
+        &mut network,
+        &events,
+        "max_retransmissions = 2\nlog_level = \"warn\"\nqueue_capacity = 8",
+    )
+    .expect("refused");
+    assert_eq!(config.max_retransmissions, Some(2));
+    assert_eq!(config.log_level.as_deref(), Some("warn"));
+    assert_eq!(config.queue_capacity, 8);
+    assert_eq!(config.flood_wait_ms, ServerConfig::default().flood_wait_ms);
+    assert_eq!(network.server.transport.config, config);
+
+    // a later change keeps the earlier ones
+    let config = reconfigure(&mut network, &events, "rate_limit = 10").expect("refused");
+    assert_eq!(config.max_retransmissions, Some(2));
+    assert_eq!(config.rate_limit, Some(10));
+
+    reconfigure(&mut network, &events, "fec_policy = \"off\"").expect("refused");
+    assert_eq!(network.server.transport.fec_policy, FecPolicy::Off);
+}
+
+#[test]
+fn refuses_invalid_changes_as_a_whole() {
+    let (mut network, events) = network();
+    let initial = network.server.transport.config.clone();
+    for changes in [
+        "rate_limit = 5\nqueue_capacity = 0",
+        "log_level = \"loud\"",
+        "retransmissions = 3",
+        "rate_limit = ",
+        "unset = [\"queue_capacity\"]",
+        "unset = \"rate_limit\"",
+        "rate_limit = 5\nunset = [\"rate_limit\"]",
+    ] {
+        assert!(
+            reconfigure(&mut network, &events, changes).is_err(),
+            "accepted {changes}"
+        );
+    }
+    assert_eq!(network.server.transport.config, initial);
+}
+
+#[test]
+fn limits_the_requests_of_each_client() {
+    let (mut network, events) = network();
+    reconfigure(&mut network, &events, "rate_limit = 2").expect("refused");
+    for _ in 0..4 {
+        network.send_request(CLIENT, ClientMessage::GetServerType);
+    }
+    network.run_until_idle(10_000);
+    assert_eq!(network.drain_inbox(CLIENT).len(), 2);
+    assert_eq!(
+        network
+            .server
+            .transport
+            .metrics
+            .counter("requests_rate_limited"),
+        2
+    );
+
+    // removing the limit lets every request through again
+    let config = reconfigure(&mut network, &events, "unset = [\"rate_limit\"]").expect("refused");
+    assert_eq!(config.rate_limit, None);
+    for _ in 0..4 {
+        network.send_request(CLIENT, ClientMessage::GetServerType);
+    }
+    network.run_until_idle(10_000);
+    assert_eq!(network.drain_inbox(CLIENT).len(), 4);
+}
+
+#[test]
+fn keeps_the_packet_cache_within_its_budget() {
+    let (mut network, events) = network();
+    request_undelivered(&mut network, ClientMessage::GetServerType);
+    request_undelivered(&mut network, ClientMessage::GetServerType);
+    assert_eq!(network.server.transport.pending.len(), 2);
+
+    // fragments in flight stay cached, the ones waiting for the window are dropped
+    reconfigure(&mut network, &events, "cache_budget = 8").expect("refused");
+    request_undelivered(&mut network, ClientMessage::GetFile("file1".to_string()));
+    assert_eq!(network.server.transport.pending.len(), 8);
+    assert_eq!(network.server.transport.send_windows.pending(), 2);
+    assert_eq!(
+        network.server.transport.metrics.counter("cache_evictions"),
+        FILE_FRAGMENTS as u64 + 2 - 8
+    );
+    assert_eq!(network.server.transport.metrics.gauge("cache_size"), 8);
+
+    // nothing in flight can be dropped
+    reconfigure(&mut network, &events, "cache_budget = 1").expect("refused");
+    assert_eq!(network.server.transport.pending.len(), 6);
+    assert_eq!(network.server.transport.send_windows.pending(), 0);
+}
+
+#[test]
+fn gives_up_after_the_retransmission_limit() {
+    let (mut network, events) = network();
+    reconfigure(&mut network, &events, "max_retransmissions = 2").expect("refused");
+    network.set_drop_rate(DRONE, 1.0);
+    request_undelivered(&mut network, ClientMessage::GetFile("file1".to_string()));
+    network.run_until_idle(10_000);
+    assert_eq!(
+        network.server.transport.metrics.counter("retransmissions"),
+        2 * FILE_FRAGMENTS as u64
+    );
+    // giving up on a fragment makes room for the rest of its message
+    assert_eq!(
+        network
+            .server
+            .transport
+            .metrics
+            .counter("retransmissions_abandoned"),
+        FILE_FRAGMENTS as u64
+    );
+    assert!(network.server.transport.pending.is_empty());
+    assert_eq!(network.server.transport.send_windows.pending(), 0);
+    assert_eq!(network.server.transport.tracer.traced_sessions(), 0);
+}
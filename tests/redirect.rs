@@ -0,0 +1,103 @@
+use communication_server::content_server::ContentServer;
+use communication_server::control_message::{ControlMessage, ControlReply, Feature};
+use communication_server::directory::Directory;
+use communication_server::replication::CatalogEntry;
+use communication_server::server::Server;
+use communication_server::test_support::{MockNetwork, NetworkBuilder, CLIENT, PEER, SERVER};
+use messages::high_level_messages::MessageContent::FromServer;
+use messages::high_level_messages::{ClientMessage, Message, ServerMessage, ServerType};
+use std::collections::BTreeSet;
+
+fn network() -> MockNetwork<ContentServer> {
+    let mut network = NetworkBuilder::with_peer(1).build_content_server(SERVER, ServerType::Text);
+    network.flood();
+    // redirect only, the peer never answers replication requests
+    network.server.replication.factor = 1;
+    network
+}
+
+/// Makes the peer a known content server hosting `remote1`.
+fn peer_hosts_remote1(network: &mut MockNetwork<ContentServer>) {
+    network.discover_server(PEER);
+    network.server.handle_message(Message {
+        source_id: PEER,
+        session_id: 0,
+        content: FromServer(ServerMessage::ServerType(ServerType::Text)),
+    });
+    let entry = CatalogEntry::of(&ServerMessage::File {
+        file_id: "remote1".to_string(),
+        size: 4,
+        content: "text".to_string(),
+    })
+    .unwrap();
+    network.send_control(PEER, &ControlMessage::Digest(vec![entry]));
+}
+
+#[test]
+fn prefers_configured_entries_and_present_servers() {
+    let mut directory = Directory::new();
+    directory.learn(30, ["a".to_string(), "b".to_string()]);
+    assert_eq!(directory.locate("a", &BTreeSet::new()), None);
+    assert_eq!(directory.locate("a", &BTreeSet::from([30])), Some(30));
+    directory.configure("a", 40);
+    assert_eq!(directory.locate("a", &BTreeSet::from([30])), Some(40));
+    assert_eq!(directory.locate("c", &BTreeSet::from([30])), None);
+}
+
+#[test]
+fn redirects_to_the_server_hosting_a_file() {
+    let mut network = network();
+    peer_hosts_remote1(&mut network);
+    network.send_control(CLIENT, &ControlMessage::Enable(Feature::Redirect));
+    network.send_request(CLIENT, ClientMessage::GetFile("remote1".to_string()));
+    network.run_until_idle(10_000);
+    assert_eq!(
+        network.control_replies(CLIENT),
+        [
+            ControlReply::Enabled(Feature::Redirect),
+            ControlReply::Redirect {
+                file_name: "remote1".to_string(),
+                node_id: PEER,
+            }
+        ]
+    );
+    assert_eq!(network.server.transport.metrics.counter("redirects"), 1);
+}
+
+#[test]
+fn redirects_from_the_configured_directory() {
+    let mut network = network();
+    let config = network
+        .server
+        .transport
+        .config
+        .updated("redirects = { elsewhere = 50 }")
+        .expect("invalid config");
+    network.server.apply_config(config);
+    network.send_control(CLIENT, &ControlMessage::Enable(Feature::Redirect));
+    network.control_replies(CLIENT);
+    network.send_request(CLIENT, ClientMessage::GetMedia("elsewhere".to_string()));
+    network.run_until_idle(10_000);
+    assert_eq!(
+        network.control_replies(CLIENT),
+        [ControlReply::Redirect {
+            file_name: "elsewhere".to_string(),
+            node_id: 50,
+        }]
+    );
+}
+
+#[test]
+fn answers_not_found_without_redirects() {
+    let mut network = network();
+    peer_hosts_remote1(&mut network);
+    network.send_control(CLIENT, &ControlMessage::Enable(Feature::Fec));
+    network.control_replies(CLIENT);
+    network.send_request(CLIENT, ClientMessage::GetFile("remote1".to_string()));
+    network.run_until_idle(10_000);
+    assert_eq!(
+        network.control_replies(CLIENT),
+        [ControlReply::NotFound("remote1".to_string())]
+    );
+    assert_eq!(network.server.transport.metrics.counter("redirects"), 0);
+}
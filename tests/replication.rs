@@ -0,0 +1,200 @@
+use communication_server::content_server::ContentServer;
+use communication_server::control_message::ControlMessage;
+use communication_server::replication::{holders, CatalogEntry, Replication};
+use communication_server::server::Server;
+use communication_server::test_support::{MockNetwork, NetworkBuilder, CLIENT, PEER, SERVER};
+use messages::high_level_messages::MessageContent::FromServer;
+use messages::high_level_messages::{ClientMessage, Message, ServerMessage, ServerType};
+use std::collections::BTreeSet;
+
+fn network() -> MockNetwork<ContentServer> {
+    let mut network = NetworkBuilder::with_peer(1).build_content_server(SERVER, ServerType::Text);
+    network.flood();
+    network
+}
+
+fn file(name: &str, content: &str) -> ServerMessage {
+    ServerMessage::File {
+        file_id: name.to_string(),
+        size: content.len(),
+        content: content.to_string(),
+    }
+}
+
+/// Delivers a message as if the peer had sent it.
+fn from_peer(network: &mut MockNetwork<ContentServer>, content: ServerMessage) {
+    network.server.handle_message(Message {
+        source_id: PEER,
+        session_id: 0,
+        content: FromServer(content),
+    });
+}
+
+/// Makes the peer known as a content server.
+fn identify_peer(network: &mut MockNetwork<ContentServer>) {
+    network.discover_server(PEER);
+    let requests = network.requests_to(PEER);
+    assert!(matches!(requests[..], [ClientMessage::GetServerType]));
+    from_peer(network, ServerMessage::ServerType(ServerType::Text));
+}
+
+#[test]
+fn encodes_digests_round_trip() {
+    let entries = vec![
+        CatalogEntry::of(&file("a,b", "hello")).unwrap(),
+        CatalogEntry::of(&ServerMessage::Media("m".to_string(), "AAAA".to_string())).unwrap(),
+    ];
+    let digest = ControlMessage::Digest(entries);
+    assert_eq!(ControlMessage::parse(&digest.encode()), Some(digest));
+    assert_eq!(CatalogEntry::parse("name,video,00"), None);
+}
+
+#[test]
+fn picks_the_same_holders_everywhere() {
+    let servers = BTreeSet::from([1, 2, 3, 4]);
+    let chosen = holders("file1", &servers, 2);
+    assert_eq!(chosen.len(), 2);
+    assert_eq!(holders("file1", &servers, 2), chosen);
+    // losing a server that did not hold the file does not move it
+    let other = *servers.iter().find(|id| !chosen.contains(id)).unwrap();
+    let mut remaining = servers.clone();
+    remaining.remove(&other);
+    assert_eq!(holders("file1", &remaining, 2), chosen);
+}
+
+#[test]
+fn discovers_content_servers_from_path_traces() {
+    let mut network = network();
+    identify_peer(&mut network);
+    assert_eq!(network.server.replication.peers(), BTreeSet::from([PEER]));
+
+    let mut replication = Replication::new(2);
+    assert!(replication.discovered(PEER));
+    assert!(!replication.identified(PEER, false));
+    assert!(!replication.discovered(PEER));
+    assert!(replication.peers().is_empty());
+}
+
+#[test]
+fn only_replicates_with_servers_of_the_same_type() {
+    let mut network = network();
+    network.discover_server(PEER);
+    network.requests_to(PEER);
+    from_peer(&mut network, ServerMessage::ServerType(ServerType::Media));
+    assert!(network.server.replication.peers().is_empty());
+    // nothing was offered to the media server
+    assert!(network.requests_to(PEER).is_empty());
+}
+
+#[test]
+fn digests_each_catalog_file_once() {
+    let mut replication = Replication::new(2);
+    let entry = CatalogEntry::of(&file("file1", "<p>one</p>"));
+    let mut reads = 0;
+    for _ in 0..3 {
+        let read = replication.catalog_entry("file1", "file1.html", || {
+            reads += 1;
+            entry.clone()
+        });
+        assert_eq!(read, entry);
+    }
+    assert_eq!(reads, 1);
+    // unreadable files are not read again either
+    assert_eq!(
+        replication.catalog_entry("file2", "file2.html", || None),
+        None
+    );
+    assert_eq!(
+        replication.catalog_entry("file2", "file2.html", || entry.clone()),
+        None
+    );
+}
+
+#[test]
+fn fetches_missing_entries_and_serves_replicas() {
+    let mut network = network();
+    let entry = CatalogEntry::of(&file("remote1", "<p>remote</p>")).unwrap();
+    let digest = ControlMessage::Digest(vec![entry]);
+    // digests and files from servers that are not peers are ignored
+    network.send_control(PEER, &digest);
+    assert!(network.requests_to(PEER).is_empty());
+    from_peer(&mut network, file("remote1", "<p>remote</p>"));
+    assert_eq!(
+        network.server.transport.metrics.counter("replicas_stored"),
+        0
+    );
+    assert!(network.server.replication.get("remote1").is_none());
+
+    identify_peer(&mut network);
+    // the peer is new, so it was offered the catalog right away
+    network.requests_to(PEER);
+    network.send_control(PEER, &digest);
+    let requests = network.requests_to(PEER);
+    assert!(matches!(&requests[..], [ClientMessage::GetFile(name)] if name == "remote1"));
+
+    from_peer(&mut network, file("remote1", "<p>remote</p>"));
+    assert_eq!(
+        network.server.transport.metrics.counter("replicas_stored"),
+        1
+    );
+    let reply = network
+        .request(CLIENT, ClientMessage::GetFilesList)
+        .expect("no reply");
+    let FromServer(ServerMessage::FilesList(files)) = reply.content else {
+        panic!("unexpected reply {:?}", reply.content);
+    };
+    assert!(files.contains(&"remote1".to_string()));
+    let reply = network
+        .request(CLIENT, ClientMessage::GetFile("remote1".to_string()))
+        .expect("no reply");
+    assert!(matches!(
+        reply.content,
+        FromServer(ServerMessage::File { content, .. }) if content == "<p>remote</p>"
+    ));
+}
+
+#[test]
+fn stores_only_requested_replicas_up_to_the_limit() {
+    let mut replication = Replication::new(2);
+    replication.max_replicas = 2;
+    assert!(!replication.store(PEER, file("unasked", "x")));
+    for name in ["a", "b", "c"] {
+        assert!(replication.fetch(PEER, name));
+        // only the peer asked can answer
+        assert!(!replication.store(PEER + 1, file(name, name)));
+        assert!(replication.store(PEER, file(name, name)));
+    }
+    assert_eq!(replication.evict(), 1);
+    assert!(replication.get("a").is_none());
+    assert!(replication.get("b").is_some() && replication.get("c").is_some());
+    // a fetch is answered once
+    assert!(!replication.store(PEER, file("c", "changed")));
+
+    assert!(replication.fetch(PEER, "d") && replication.fetch(PEER, "e"));
+    assert!(!replication.fetch(PEER, "f"));
+}
+
+#[test]
+fn offers_held_files_to_their_other_holders() {
+    let mut network = network();
+    identify_peer(&mut network);
+    // the peer is new, so it was offered everything readable right away
+    network.requests_to(PEER);
+    let entry = CatalogEntry::of(&file("remote1", "<p>remote</p>")).unwrap();
+    network.send_control(PEER, &ControlMessage::Digest(vec![entry]));
+    network.requests_to(PEER);
+    from_peer(&mut network, file("remote1", "<p>remote</p>"));
+    assert_eq!(network.server.catalog()["remote1"], "replica");
+    assert!(!network.server.snapshot().file_list.contains_key("remote1"));
+
+    // with two servers and a factor of two, both hold every file
+    network.server.replicate();
+    let requests = network.requests_to(PEER);
+    let [ClientMessage::SendMessage { content, .. }] = &requests[..] else {
+        panic!("unexpected requests {requests:?}");
+    };
+    let Some(ControlMessage::Digest(entries)) = ControlMessage::parse(content) else {
+        panic!("not a digest: {content}");
+    };
+    assert!(entries.iter().any(|entry| entry.name == "remote1"));
+}
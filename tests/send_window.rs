@@ -0,0 +1,110 @@
+use communication_server::window::{SendWindows, INITIAL_WINDOW, MAX_SESSIONS};
+use wg_2024::network::SourceRoutingHeader;
+use wg_2024::packet::{Fragment, Packet};
+
+fn fragments(session_id: u64, count: u64) -> Vec<Packet> {
+    (0..count)
+        .map(|fragment_index| {
+            Packet::new_fragment(
+                SourceRoutingHeader::with_first_hop(vec![1, 10, 20]),
+                session_id,
+                Fragment {
+                    fragment_index,
+                    total_n_fragments: count,
+                    length: 0,
+                    data: [0; 128],
+                },
+            )
+        })
+        .collect()
+}
+
+fn indices(packets: &[Packet]) -> Vec<u64> {
+    packets.iter().map(Packet::get_fragment_index).collect()
+}
+
+#[test]
+fn releases_fragments_as_acks_open_the_window() {
+    let mut windows = SendWindows::new();
+    let sent = windows.push(7, fragments(7, 20));
+    assert_eq!(indices(&sent), [0, 1, 2, 3]);
+    assert_eq!(windows.pending(), 16);
+
+    // slow start: every ack grows the window by one fragment
+    assert_eq!(indices(&windows.acked(7, 0)), [4, 5]);
+    assert_eq!(windows.get(7).unwrap().size(), INITIAL_WINDOW + 1.0);
+    // an unknown or repeated ack releases nothing
+    assert!(windows.acked(7, 0).is_empty());
+    assert!(windows.acked(8, 0).is_empty());
+}
+
+#[test]
+fn halves_the_window_on_loss() {
+    let mut windows = SendWindows::new();
+    windows.push(7, fragments(7, 20));
+    windows.lost(7);
+    let window = windows.get(7).unwrap();
+    assert_eq!(window.size(), INITIAL_WINDOW / 2.0);
+    assert_eq!(window.in_flight(), 4);
+
+    // past the threshold the window grows by about one fragment per window
+    assert!(windows.acked(7, 0).is_empty());
+    assert!(windows.acked(7, 1).is_empty());
+    assert_eq!(indices(&windows.acked(7, 2)), [4, 5]);
+    assert_eq!(windows.get(7).unwrap().size().floor(), 3.0);
+}
+
+#[test]
+fn forgets_a_session_once_every_fragment_is_acked() {
+    let mut windows = SendWindows::new();
+    windows.push(7, fragments(7, 2));
+    windows.acked(7, 0);
+    windows.acked(7, 1);
+    assert!(windows.get(7).is_none());
+}
+
+#[test]
+fn abandoned_fragments_free_their_room() {
+    let mut windows = SendWindows::new();
+    windows.push(7, fragments(7, 6));
+    assert!(windows.is_in_flight(7, 0));
+    assert_eq!(indices(&windows.abandon(7, 0)), [4]);
+    assert!(!windows.is_in_flight(7, 0));
+    assert_eq!(windows.get(7).unwrap().size(), INITIAL_WINDOW);
+
+    // a fragment waiting for room is never sent
+    assert!(windows.abandon(7, 5).is_empty());
+    assert_eq!(windows.pending(), 0);
+    for fragment_index in 1..=4 {
+        windows.acked(7, fragment_index);
+    }
+    assert!(windows.get(7).is_none());
+}
+
+#[test]
+fn evicts_the_least_recently_active_session() {
+    let mut windows = SendWindows::new();
+    windows.push(0, fragments(0, 6));
+    for session_id in 1..MAX_SESSIONS as u64 {
+        windows.push(session_id, fragments(session_id, 1));
+    }
+    assert!(windows.evict_idle().is_none());
+
+    // the first session is the idle one, its fragments waiting for room are given back
+    windows.push(MAX_SESSIONS as u64, fragments(MAX_SESSIONS as u64, 1));
+    let (session_id, unsent) = windows.evict_idle().expect("nothing evicted");
+    assert_eq!(session_id, 0);
+    assert_eq!(indices(&unsent), [4, 5]);
+
+    // a loss counts as activity
+    windows.lost(1);
+    windows.push(
+        MAX_SESSIONS as u64 + 1,
+        fragments(MAX_SESSIONS as u64 + 1, 1),
+    );
+    assert_eq!(
+        windows.evict_idle().map(|(session_id, _)| session_id),
+        Some(2)
+    );
+    assert!(windows.get(1).is_some());
+}
@@ -0,0 +1,218 @@
+use communication_server::commands::{ServerCommand, ServerEvent};
+use communication_server::communication_server::CommunicationServer;
+use communication_server::server::Server;
+use communication_server::snapshot::ServerSnapshot;
+use communication_server::test_support::{
+    MockNetwork, NetworkBuilder, CLIENT, DRONE, PEER, SERVER,
+};
+use communication_server::topology::Topology;
+use communication_server::window::INITIAL_WINDOW;
+use crossbeam_channel::{never, unbounded};
+use messages::high_level_messages::MessageContent::{FromClient, FromServer};
+use messages::high_level_messages::{ClientMessage, Message, ServerMessage, ServerType};
+use std::path::PathBuf;
+use std::time::Duration;
+use wg_2024::packet::NodeType;
+
+const ALICE: u8 = CLIENT;
+
+fn snapshot_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("snapshot-{}-{name}.bin", std::process::id()))
+}
+
+fn builder() -> NetworkBuilder {
+    NetworkBuilder::single_drone(1)
+}
+
+fn registered_network() -> MockNetwork<CommunicationServer> {
+    let mut network = builder().build_communication_server(SERVER);
+    network.flood();
+    let reply = network
+        .request(ALICE, ClientMessage::RegisterToChat)
+        .expect("no reply");
+    assert!(matches!(
+        reply.content,
+        FromServer(ServerMessage::SuccessfulRegistration)
+    ));
+    // the acks are back, nothing is pending
+    network.run_until_idle(10_000);
+    network
+}
+
+#[test]
+fn restores_registered_clients_and_routes_without_flooding() {
+    let path = snapshot_path("clients");
+    registered_network()
+        .server
+        .save_snapshot(&path)
+        .expect("snapshot not saved");
+
+    let mut restarted = builder().build_communication_server(SERVER);
+    restarted.server.restore_from(&path).expect("not restored");
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(restarted.server.registered_clients, [ALICE]);
+    let reply = restarted
+        .request(ALICE, ClientMessage::GetClientList)
+        .expect("no reply");
+    assert!(matches!(
+        reply.content,
+        FromServer(ServerMessage::ClientList(clients)) if clients == [ALICE]
+    ));
+    assert_eq!(
+        restarted
+            .server
+            .transport
+            .metrics
+            .counter("floods_initiated"),
+        0
+    );
+}
+
+#[test]
+fn restores_unacknowledged_fragments() {
+    let mut network = builder().build_content_server(SERVER, ServerType::Text);
+    network.flood();
+    // the reply is never delivered, so none of its fragments is acknowledged
+    network.server.handle_message(Message {
+        source_id: ALICE,
+        session_id: 0,
+        content: FromClient(ClientMessage::GetFilesList),
+    });
+    let snapshot = network.server.snapshot();
+    assert!(!snapshot.pending.is_empty());
+
+    let mut restarted = builder().build_content_server(SERVER, ServerType::Text);
+    restarted
+        .server
+        .restore(snapshot.clone())
+        .expect("not restored");
+    assert_eq!(
+        restarted.server.transport.pending.len(),
+        snapshot.pending.len()
+    );
+    assert_eq!(
+        restarted.server.transport.metrics.gauge("cache_size"),
+        snapshot.pending.len() as u64
+    );
+    assert_eq!(restarted.server.snapshot(), snapshot);
+}
+
+#[test]
+fn completes_a_message_restored_mid_window() {
+    let mut network = registered_network();
+    // more fragments than the window lets out at once, none delivered yet
+    network.server.handle_message(Message {
+        source_id: ALICE,
+        session_id: 0,
+        content: FromClient(ClientMessage::SendMessage {
+            recipient_id: ALICE,
+            content: "x".repeat(2048),
+        }),
+    });
+    let snapshot = network.server.snapshot();
+    assert!(snapshot.pending.len() > INITIAL_WINDOW as usize);
+
+    let mut restarted = builder().build_communication_server(SERVER);
+    restarted.server.restore(snapshot).expect("not restored");
+    restarted.run_until_idle(10_000);
+    assert!(restarted.drain_inbox(ALICE).is_empty());
+
+    // sent again once running
+    restarted.server.resume();
+    let message = restarted.await_message(ALICE, 10_000).expect("no message");
+    assert!(matches!(
+        message.content,
+        FromServer(ServerMessage::MessageReceived { content, .. }) if content.len() == 2048
+    ));
+    restarted.run_until_idle(10_000);
+    assert!(restarted.server.transport.pending.is_empty());
+    assert_eq!(restarted.server.transport.send_windows.pending(), 0);
+}
+
+#[test]
+fn restores_routes_to_other_servers_without_contacting_them() {
+    let path_trace = vec![
+        (SERVER, NodeType::Server),
+        (DRONE, NodeType::Drone),
+        (PEER, NodeType::Server),
+    ];
+    let mut network = NetworkBuilder::with_peer(1).build_content_server(SERVER, ServerType::Text);
+    let mut snapshot = network.server.snapshot();
+    snapshot.path_traces.push(path_trace.clone());
+
+    let mut restarted = NetworkBuilder::with_peer(1).build_content_server(SERVER, ServerType::Text);
+    restarted.server.restore(snapshot).expect("not restored");
+    assert_eq!(
+        restarted.server.transport.topology.path_traces(),
+        std::slice::from_ref(&path_trace)
+    );
+    assert!(restarted.server.transport.pending.is_empty());
+    assert!(restarted.server.replication.peers().is_empty());
+
+    // the same path trace learned from a flood response asks the peer for its type
+    network.discover_server(PEER);
+    assert_eq!(
+        network.server.transport.topology.path_traces(),
+        std::slice::from_ref(&path_trace)
+    );
+    assert!(!network.server.transport.pending.is_empty());
+}
+
+#[test]
+fn refuses_snapshots_it_cannot_use() {
+    let snapshot = registered_network().server.snapshot();
+    let mut other = builder().build_communication_server(2);
+    assert!(other.server.restore(snapshot).is_err());
+    assert!(other.server.registered_clients.is_empty());
+
+    let path = snapshot_path("garbage");
+    std::fs::write(&path, b"not a snapshot").unwrap();
+    assert!(ServerSnapshot::load(&path).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn saves_snapshots_on_command_and_periodically() {
+    let mut network = registered_network();
+    let (event_send, event_recv) = unbounded();
+    network.server.attach_server_channels(event_send, never());
+
+    let path = snapshot_path("command");
+    network
+        .server
+        .handle_server_command(ServerCommand::SaveSnapshot(path.clone()));
+    assert!(matches!(
+        event_recv.try_recv(),
+        Ok(ServerEvent::SnapshotSaved(Ok(saved))) if saved == path
+    ));
+    std::fs::remove_file(&path).unwrap();
+
+    network
+        .server
+        .handle_server_command(ServerCommand::SetAutosnapshot(Some((
+            path.clone(),
+            Duration::ZERO,
+        ))));
+    network.server.autosnapshot_if_due();
+    let saved = ServerSnapshot::load(&path).expect("no autosnapshot");
+    assert_eq!(saved.registered_clients, [ALICE]);
+    assert_eq!(
+        network.server.transport.metrics.counter("snapshots_saved"),
+        2
+    );
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn forgets_path_traces_through_removed_nodes() {
+    let mut topology = Topology::new();
+    let through = |drone| vec![(SERVER, NodeType::Server), (drone, NodeType::Drone)];
+    topology.add_path_trace(&through(10));
+    topology.add_path_trace(&through(10));
+    topology.add_path_trace(&through(11));
+    assert_eq!(topology.path_traces().len(), 2);
+    topology.remove_node(10);
+    assert_eq!(topology.path_traces(), [through(11)]);
+    topology.remove_link(11, SERVER);
+    assert!(topology.path_traces().is_empty());
+}
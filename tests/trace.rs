@@ -0,0 +1,79 @@
+use communication_server::commands::{ServerCommand, ServerEvent};
+use communication_server::server::Server;
+use communication_server::test_support::{NetworkBuilder, CLIENT, DRONE, SERVER};
+use communication_server::trace::{MessageTracer, TraceEventKind, MAX_TRACED_SESSIONS};
+use crossbeam_channel::{never, unbounded};
+use messages::high_level_messages::{ClientMessage, ServerType};
+
+#[test]
+fn exports_the_lifecycle_of_a_reply() {
+    let mut network =
+        NetworkBuilder::single_drone(1).build_content_server(SERVER, ServerType::Text);
+    network.flood();
+    network
+        .request(CLIENT, ClientMessage::GetServerType)
+        .expect("no reply");
+    network.run_until_idle(10_000);
+
+    let kinds = network
+        .server
+        .transport
+        .tracer
+        .events()
+        .into_iter()
+        .map(|event| event.kind)
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        [
+            TraceEventKind::RequestAssembled,
+            TraceEventKind::Fragmented,
+            TraceEventKind::Sent,
+            TraceEventKind::AckReceived,
+            TraceEventKind::Completed
+        ]
+    );
+
+    let (event_send, events) = unbounded();
+    network.server.attach_server_channels(event_send, never());
+    let path = std::env::temp_dir().join(format!("trace-{}.jsonl", std::process::id()));
+    network
+        .server
+        .handle_server_command(ServerCommand::ExportTrace(path.clone()));
+    assert!(matches!(
+        events.try_recv(),
+        Ok(ServerEvent::TraceExported(Ok(exported))) if exported == path
+    ));
+    let exported = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let lines = exported.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), kinds.len());
+    for (line, event) in lines.iter().zip([
+        "request_assembled",
+        "fragmented",
+        "sent",
+        "ack_received",
+        "completed",
+    ]) {
+        assert!(line.starts_with(&format!("{{\"node_id\":{SERVER},")));
+        assert!(line.contains(&format!("\"event\":\"{event}\"")), "{line}");
+    }
+    // the ack and the completion are timed
+    assert!(lines[3].contains("\"latency_us\":") && lines[4].contains("\"latency_us\":"));
+    assert!(lines[2].contains(&format!("\"route\":[{SERVER},{DRONE},{CLIENT}]")));
+}
+
+#[test]
+fn forgets_messages_that_cannot_complete() {
+    let tracer = MessageTracer::new(0);
+    tracer.fragmented(1, 2, &[SERVER, DRONE, CLIENT]);
+    tracer.sent(1, 0, &[SERVER, DRONE, CLIENT]);
+    tracer.abandoned(1);
+    assert_eq!(tracer.traced_sessions(), 0);
+
+    for session_id in 0..=MAX_TRACED_SESSIONS as u64 {
+        tracer.fragmented(session_id, 1, &[SERVER, DRONE, CLIENT]);
+    }
+    assert_eq!(tracer.traced_sessions(), MAX_TRACED_SESSIONS);
+}